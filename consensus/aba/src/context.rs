@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, SocketAddrV4},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use config::Node;
+
+use fnv::FnvHashMap;
+use network::{
+    plaintcp::CancelHandler,
+    Acknowledgement,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver},
+    oneshot,
+};
+// use tokio_util::time::DelayQueue;
+use types::{Replica, SyncMsg, SyncState};
+
+use super::ProtMsg;
+use super::{ABAState};
+use consensus::{inbox, listen_sync, new_transport, sync_sender, AuthKind, Authenticator, Envelope, InboxReceiver, NetworkConfig, Transport};
+
+pub struct Context {
+    /// Networking context
+    pub net_send: Box<dyn Transport>,
+    pub net_recv: InboxReceiver<Envelope>,
+    pub sync_send: Box<dyn Transport<SyncMsg>>,
+    pub sync_recv: UnboundedReceiver<SyncMsg>,
+    /// Data context
+    pub num_nodes: usize,
+    pub myid: usize,
+    pub num_faults: usize,
+    pub inp_message: Vec<u8>,
+    pub byz: bool,
+    pub crash: bool,
+    /// Tags outgoing messages and checks incoming ones with MACs or signatures
+    pub auth: Authenticator,
+
+    /// Seed of the local common coin, identical at all nodes
+    pub coin_seed: Vec<u8>,
+
+    /// Cancel Handlers
+    pub cancel_handlers: HashMap<u64, Vec<CancelHandler<Acknowledgement>>>,
+    exit_rx: oneshot::Receiver<()>,
+
+    // Each Binary Agreement instance is associated with a Unique Identifier.
+    pub aba_context: HashMap<usize, ABAState>,
+
+    // All nodes take part in the same ABA instance, so instance ids are shared across nodes.
+    pub max_id: usize,
+}
+
+impl Context {
+    pub fn spawn(config: Node, message: Vec<u8>, byz: bool, crash: bool, auth: AuthKind, network: NetworkConfig) -> anyhow::Result<oneshot::Sender<()>> {
+        // Add a separate configuration for ABA service.
+
+        let mut syncer_map: FnvHashMap<Replica, SocketAddr> = FnvHashMap::default();
+        syncer_map.insert(0, config.client_addr);
+
+        let auth = Authenticator::new(auth, &config, network.batch.is_some()).map_err(anyhow::Error::msg)?;
+        // Setup networking
+        let (tx_net_to_consensus, rx_net_to_consensus) = inbox(network.inbox_capacity);
+        let consensus_net = new_transport(&network, &config, &auth, tx_net_to_consensus).map_err(anyhow::Error::msg)?;
+
+        let syncer_listen_port = config.client_port;
+        let syncer_l_address = to_socket_address("0.0.0.0", syncer_listen_port);
+
+        // The server must listen to the client's messages on some port that is not being used to listen to other servers
+        let (tx_net_to_client, rx_net_from_client) = unbounded_channel();
+        listen_sync(&network, syncer_l_address, tx_net_to_client).map_err(anyhow::Error::msg)?;
+
+        let sync_net = sync_sender(&network, syncer_map);
+        let (exit_tx, exit_rx) = oneshot::channel();
+
+        // Common coin seed
+        let coin_seed = [17u8; 32].to_vec();
+
+        tokio::spawn(async move {
+            let mut c = Context {
+                net_send: consensus_net,
+                net_recv: rx_net_to_consensus,
+                sync_send: sync_net,
+                sync_recv: rx_net_from_client,
+                num_nodes: config.num_nodes,
+                auth,
+                coin_seed,
+                myid: config.id,
+                byz: byz & (config.id < config.num_faults),
+                crash: crash & (config.id < config.num_faults),
+                num_faults: config.num_faults,
+                cancel_handlers: HashMap::default(),
+                exit_rx: exit_rx,
+                inp_message: message,
+
+                aba_context: HashMap::default(),
+                max_id: 0,
+            };
+
+            // Run the consensus context
+            if let Err(e) = c.run().await {
+                log::error!("Consensus error: {}", e);
+            }
+        });
+        Ok(exit_tx)
+    }
+
+    pub async fn broadcast(&mut self, protmsg: ProtMsg) {
+        // Sleep to simulate network delay
+        // sleep(Duration::from_millis(50)).await;
+
+        let sealed = self.auth.seal(&protmsg);
+        for replica in 0..self.num_nodes {
+            if self.byz && replica != self.myid {
+                // log::info!("Byzantine node {} sending a fake message to {}", self.myid, replica);
+                let mut byz_msg = protmsg.clone();
+
+                // Match to access inner message
+                match &mut byz_msg {
+                    ProtMsg::Est(msg, _) | ProtMsg::Aux(msg, _) | ProtMsg::Term(msg, _) => {
+                        msg.value = 1 - msg.value;
+                    }
+                }
+
+                let envelope = self.auth.wrap(&byz_msg, replica);
+                let cancel_handler = self.net_send.send(replica, envelope).await;
+                self.add_cancel_handler(cancel_handler);
+                continue;
+            }
+            if replica != self.myid {
+                let envelope = self.auth.envelope(&sealed, replica);
+                let cancel_handler: CancelHandler<Acknowledgement> =
+                    self.net_send.send(replica, envelope).await;
+                self.add_cancel_handler(cancel_handler);
+            }
+        }
+    }
+
+    pub fn add_cancel_handler(&mut self, canc: CancelHandler<Acknowledgement>) {
+        self.cancel_handlers.entry(0).or_default().push(canc);
+    }
+
+    pub async fn send(&mut self, replica: Replica, envelope: Envelope) {
+        let cancel_handler: CancelHandler<Acknowledgement> =
+            self.net_send.send(replica, envelope).await;
+        self.add_cancel_handler(cancel_handler);
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        // The process starts listening to messages in this process.
+        // First, the node sends an alive message
+        let cancel_handler = self
+            .sync_send
+            .send(
+                0,
+                SyncMsg {
+                    sender: self.myid,
+                    state: SyncState::ALIVE,
+                    value: "".to_string().into_bytes(),
+                },
+            )
+            .await;
+        self.add_cancel_handler(cancel_handler);
+        loop {
+            tokio::select! {
+                // Receive exit handlers
+                exit_val = &mut self.exit_rx => {
+                    exit_val.map_err(anyhow::Error::new)?;
+                    log::info!("Termination signal received by the server. Exiting.");
+                    break
+                },
+                msg = self.net_recv.recv() => {
+                    // Received messages are processed here
+                    // log::trace!("Got a consensus message from the network: {:?}", msg);
+                    let msg = msg.ok_or_else(||
+                        anyhow!("Networking layer has closed")
+                    )?;
+                    for envelope in self.auth.unbatch(msg) {
+                        self.process_msg(envelope).await;
+                    }
+                },
+                sync_msg = self.sync_recv.recv() =>{
+                    let sync_msg = sync_msg.ok_or_else(||
+                        anyhow!("Networking layer has closed")
+                    )?;
+                    match sync_msg.state {
+                        SyncState::START =>{
+                            log::info!("Consensus Start time: {:?}", SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_millis());
+                            // Start your protocol from here
+                            // Write a function to broadcast a message. We demonstrate an example with a PING function
+                            // Every node inputs a bit to the same instance. Alternate inputs across nodes
+                            // so that the benchmark exercises rounds with disagreeing estimates.
+                            let aba_inst_id = self.max_id + 1;
+                            self.max_id = aba_inst_id;
+                            let input = (self.myid % 2) as u8;
+                            self.start_aba(input, aba_inst_id).await;
+                            // wait for messages
+                        },
+                        SyncState::STOP =>{
+                            // Code used for internal purposes
+                            log::info!("Consensus Stop time: {:?}", SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_millis());
+                            log::info!("Termination signal received by the server. Exiting.");
+                            break
+                        },
+                        _=>{}
+                    }
+                },
+            };
+        }
+        Ok(())
+    }
+}
+
+pub fn to_socket_address(ip_str: &str, port: u16) -> SocketAddr {
+    let addr = SocketAddrV4::new(ip_str.parse().unwrap(), port);
+    addr.into()
+}
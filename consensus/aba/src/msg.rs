@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use types::Replica;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ABAMsg {
+    pub round: usize,
+    pub value: u8,
+    pub origin: Replica,
+}
+
+/*
+This is how Mostefaoui-Moumen-Raynal's binary agreement works (round r, estimate est):
+1. BV-broadcast <EST, r, est>
+    a. on (t+1 <EST, r, v>) relay <EST, r, v> if not sent already
+    b. on (2t+1 <EST, r, v>) add v to bin_values[r]
+2. When bin_values[r] becomes non-empty, broadcast <AUX, r, w> for the first w in bin_values[r]
+3. Wait until n-t <AUX, r, *> messages carry values vals ⊆ bin_values[r]
+4. Toss the common coin s = coin(r)
+    a. if vals = {v}: if v = s decide v, est = v
+    b. else est = s
+5. After deciding v, broadcast <TERM, v>
+    a. on (t+1 <TERM, v>) relay <TERM, v>
+    b. on (2t+1 <TERM, v>) output v, terminate
+*/
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ProtMsg {
+    Est(ABAMsg, usize),
+    Aux(ABAMsg, usize),
+    Term(ABAMsg, usize),
+}
+
+// Output reported to the syncer. Laid out like the syncer's RBCSyncMsg so the agreement
+// check and latency report work unchanged, with the number of rounds following as a SyncReport.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ABASyncMsg {
+    pub id: usize,
+    pub msg: Vec<u8>,
+}
@@ -0,0 +1,67 @@
+use super::{ABASyncMsg, ProtMsg};
+use crate::context::Context;
+use consensus::{Envelope, SyncReport};
+use types::{SyncMsg, SyncState};
+
+impl Context {
+    // This function verifies the MAC or signature of a sent message
+    // A node cannot impersonate as another node because of them
+    pub fn check_proposal(&self, envelope: &Envelope) -> Option<ProtMsg> {
+        // validate the MAC or signature on the received bytes, and only then deserialize them
+        let protmsg = self.auth.open(envelope);
+        if protmsg.is_none() {
+            log::warn!("Authentication of a message from node {} failed.", envelope.sender);
+        }
+        protmsg
+    }
+
+    pub(crate) async fn process_msg(&mut self, envelope: Envelope) {
+        // Verify the message's authenticity before proceeding
+        if let Some(protmsg) = self.check_proposal(&envelope) {
+            log::trace!("Received protocol msg: {:?}", protmsg);
+            // Votes are counted under the origin they name, which must be the node that sent them
+            let origin = match &protmsg {
+                ProtMsg::Est(msg, _) | ProtMsg::Aux(msg, _) | ProtMsg::Term(msg, _) => msg.origin,
+            };
+            if origin != envelope.sender {
+                log::warn!("Node {} sent a message in the name of node {}", envelope.sender, origin);
+                return;
+            }
+            match protmsg {
+                ProtMsg::Est(main_msg, instance_id) => {
+                    log::debug!("Received Est for instance id {} from node : {}", instance_id, main_msg.origin);
+                    self.handle_est(main_msg,instance_id).await;
+                }
+                ProtMsg::Aux(main_msg, instance_id) => {
+                    log::debug!("Received Aux for instance id {} from node : {}", instance_id, main_msg.origin);
+                    self.handle_aux(main_msg,instance_id).await;
+                }
+                ProtMsg::Term(main_msg, instance_id) => {
+                    log::debug!("Received Term for instance id {} from node : {}", instance_id, main_msg.origin);
+                    self.handle_term(main_msg,instance_id).await;
+                }
+            }
+        }
+    }
+
+    // Invoke this function once you terminate the protocol
+    pub async fn terminate(&mut self, instance_id: usize, value: u8, rounds: usize) {
+        let sync_aba_msg = ABASyncMsg {
+            id: instance_id,
+            msg: vec![value],
+        };
+        let value = bincode::serialize(&sync_aba_msg).expect("Failed to serialize ABA output");
+        let cancel_handler = self
+            .sync_send
+            .send(
+                0,
+                SyncMsg {
+                    sender: self.myid,
+                    state: SyncState::COMPLETED,
+                    value: SyncReport::Rounds(rounds).append(value),
+                },
+            )
+            .await;
+        self.add_cancel_handler(cancel_handler);
+    }
+}
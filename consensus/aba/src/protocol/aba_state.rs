@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+
+use types::Replica;
+
+// What counting an EST vote calls for
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct EstOutcome {
+    // t+1 nodes sent the value, so at least one honest node proposed it and it is relayed
+    pub relay: bool,
+    // 2t+1 nodes sent the value, which joined bin_values
+    pub bin_value: bool,
+}
+
+// What counting a TERM vote calls for
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct TermOutcome {
+    // t+1 nodes decided the value, so at least one honest node did and it is relayed
+    pub relay: bool,
+    // 2t+1 nodes decided the value, so every honest node will relay it and this one terminates
+    pub terminate: bool,
+}
+
+// Per-round state of the binary agreement
+pub struct RoundState {
+    // Senders of <EST, r, v> indexed by v
+    pub est_senders: [HashSet<Replica>; 2],
+    pub sent_est: [bool; 2],
+    pub bin_values: HashSet<u8>,
+
+    // Value carried by the <AUX, r, w> message of each sender
+    pub aux_values: HashMap<Replica, u8>,
+    pub sent_aux: bool,
+
+    pub coin_tossed: bool,
+}
+
+impl RoundState {
+    pub fn new() -> RoundState {
+        RoundState {
+            est_senders: [HashSet::default(), HashSet::default()],
+            sent_est: [false, false],
+            bin_values: HashSet::default(),
+
+            aux_values: HashMap::default(),
+            sent_aux: false,
+
+            coin_tossed: false,
+        }
+    }
+
+    // Count the EST vote of the sender for the value. Repeated votes count once.
+    pub fn add_est(&mut self, sender: Replica, value: u8, num_faults: usize) -> EstOutcome {
+        let senders = &mut self.est_senders[value as usize];
+        if !senders.insert(sender) {
+            return EstOutcome::default();
+        }
+        let size = senders.len();
+        EstOutcome {
+            relay: size == num_faults + 1 && !self.sent_est[value as usize],
+            bin_value: size == 2 * num_faults + 1 && self.bin_values.insert(value),
+        }
+    }
+
+    // Returns false if the sender sent an AUX message in this round already
+    pub fn add_aux(&mut self, sender: Replica, value: u8) -> bool {
+        if self.aux_values.contains_key(&sender) {
+            return false;
+        }
+        self.aux_values.insert(sender, value);
+        true
+    }
+
+    // The values of the round once this node sent its AUX and a quorum of AUX messages carries values in bin_values.
+    // None before that and once the coin of the round was tossed.
+    pub fn aux_quorum(&self, quorum: usize) -> Option<HashSet<u8>> {
+        if self.coin_tossed || !self.sent_aux {
+            return None;
+        }
+        let (vals, count) = self.get_aux_vals();
+        (count >= quorum).then_some(vals)
+    }
+
+    // Values of AUX messages whose value is in bin_values, with the number of such senders
+    pub fn get_aux_vals(&self) -> (HashSet<u8>, usize) {
+        let mut vals = HashSet::default();
+        let mut count = 0;
+        for (_rep, value) in self.aux_values.iter() {
+            if self.bin_values.contains(value) {
+                vals.insert(*value);
+                count += 1;
+            }
+        }
+        (vals, count)
+    }
+}
+
+impl Default for RoundState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ABAState {
+    pub round: usize,
+    pub est: u8,
+    pub rounds: HashMap<usize, RoundState>,
+
+    pub decided: Option<u8>,
+    pub decided_round: usize,
+
+    pub term_senders: [HashSet<Replica>; 2],
+    pub sent_term: bool,
+    pub terminated: bool,
+}
+
+impl ABAState {
+    pub fn new() -> ABAState {
+        ABAState {
+            round: 0,
+            est: 0,
+            rounds: HashMap::default(),
+
+            decided: None,
+            decided_round: 0,
+
+            term_senders: [HashSet::default(), HashSet::default()],
+            sent_term: false,
+            terminated: false,
+        }
+    }
+}
+
+impl ABAState {
+    // End the current round with its values and coin, and move to the next one. A node decides the value the coin
+    // matches, and keeps taking part in rounds until the TERM quorum terminates it. Returns the decided value.
+    pub fn complete_round(&mut self, vals: &HashSet<u8>, coin: u8) -> Option<u8> {
+        let round = self.round;
+        let mut decision = None;
+        if vals.len() == 1 {
+            let value = *vals.iter().next().unwrap();
+            self.est = value;
+            if value == coin && self.decided.is_none() {
+                self.decided = Some(value);
+                self.decided_round = round;
+                decision = Some(value);
+            }
+        } else {
+            self.est = coin;
+        }
+        self.round = round + 1;
+        decision
+    }
+
+    // Count the TERM vote of the sender for the value. Repeated votes count once.
+    pub fn add_term(&mut self, sender: Replica, value: u8, num_faults: usize) -> TermOutcome {
+        let senders = &mut self.term_senders[value as usize];
+        if !senders.insert(sender) {
+            return TermOutcome::default();
+        }
+        let size = senders.len();
+        let relay = size == num_faults + 1 && !self.sent_term;
+        if relay && self.decided.is_none() {
+            self.decided = Some(value);
+            self.decided_round = self.round;
+        }
+        let terminate = size == 2 * num_faults + 1 && !self.terminated;
+        if terminate {
+            self.terminated = true;
+        }
+        TermOutcome { relay, terminate }
+    }
+
+    // Rounds up to and including the one the decision came in
+    pub fn rounds_taken(&self) -> usize {
+        self.decided_round + 1
+    }
+}
+
+impl Default for ABAState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // n = 4 and t = 1
+    const NUM_FAULTS: usize = 1;
+
+    fn values(values: &[u8]) -> HashSet<u8> {
+        values.iter().copied().collect()
+    }
+
+    #[test]
+    fn est_relays_at_t_plus_one_and_accepts_at_2t_plus_one() {
+        let mut round = RoundState::new();
+        assert_eq!(round.add_est(0, 1, NUM_FAULTS), EstOutcome::default());
+        // A repeated vote does not count
+        assert_eq!(round.add_est(0, 1, NUM_FAULTS), EstOutcome::default());
+        assert_eq!(
+            round.add_est(1, 1, NUM_FAULTS),
+            EstOutcome {
+                relay: true,
+                bin_value: false
+            }
+        );
+        round.sent_est[1] = true;
+        assert_eq!(
+            round.add_est(2, 1, NUM_FAULTS),
+            EstOutcome {
+                relay: false,
+                bin_value: true
+            }
+        );
+        assert_eq!(round.bin_values, values(&[1]));
+        assert_eq!(round.add_est(3, 1, NUM_FAULTS), EstOutcome::default());
+        // Votes for the other value are counted apart
+        assert_eq!(round.add_est(3, 0, NUM_FAULTS), EstOutcome::default());
+        assert_eq!(round.bin_values, values(&[1]));
+    }
+
+    #[test]
+    fn est_is_not_relayed_twice() {
+        let mut round = RoundState::new();
+        round.sent_est[0] = true;
+        round.add_est(0, 0, NUM_FAULTS);
+        assert!(!round.add_est(1, 0, NUM_FAULTS).relay);
+    }
+
+    #[test]
+    fn aux_quorum_needs_own_aux_and_values_in_bin_values() {
+        let mut round = RoundState::new();
+        round.bin_values.insert(1);
+        round.sent_aux = true;
+        assert!(round.add_aux(0, 1));
+        assert!(!round.add_aux(0, 0));
+        round.add_aux(1, 1);
+        // AUX messages with values outside bin_values do not count
+        round.add_aux(2, 0);
+        assert_eq!(round.aux_quorum(3), None);
+        round.add_aux(3, 1);
+        assert_eq!(round.aux_quorum(3), Some(values(&[1])));
+        round.coin_tossed = true;
+        assert_eq!(round.aux_quorum(3), None);
+
+        // Nor does a quorum count before this node sent its own AUX
+        let mut round = RoundState::new();
+        round.bin_values.insert(0);
+        for sender in 0..3 {
+            round.add_aux(sender, 0);
+        }
+        assert_eq!(round.aux_quorum(3), None);
+    }
+
+    #[test]
+    fn decides_only_when_the_single_value_matches_the_coin() {
+        let mut aba = ABAState::new();
+        // Both values: adopt the coin
+        assert_eq!(aba.complete_round(&values(&[0, 1]), 1), None);
+        assert_eq!((aba.est, aba.round), (1, 1));
+        // One value the coin misses: keep the value
+        assert_eq!(aba.complete_round(&values(&[0]), 1), None);
+        assert_eq!((aba.est, aba.round), (0, 2));
+        // One value the coin matches: decide
+        assert_eq!(aba.complete_round(&values(&[0]), 0), Some(0));
+        assert_eq!((aba.decided, aba.decided_round, aba.rounds_taken()), (Some(0), 2, 3));
+    }
+
+    #[test]
+    fn decided_node_takes_part_in_the_next_round_until_terms_terminate_it() {
+        let mut aba = ABAState::new();
+        assert_eq!(aba.complete_round(&values(&[1]), 1), Some(1));
+        assert_eq!((aba.round, aba.est), (1, 1));
+        assert!(!aba.terminated);
+        // A later round neither decides again nor changes the decision
+        assert_eq!(aba.complete_round(&values(&[1]), 1), None);
+        assert_eq!((aba.decided, aba.decided_round, aba.rounds_taken()), (Some(1), 0, 1));
+
+        aba.sent_term = true;
+        assert_eq!(aba.add_term(0, 1, NUM_FAULTS), TermOutcome::default());
+        assert_eq!(aba.add_term(0, 1, NUM_FAULTS), TermOutcome::default());
+        assert_eq!(aba.add_term(1, 1, NUM_FAULTS), TermOutcome::default());
+        assert_eq!(
+            aba.add_term(2, 1, NUM_FAULTS),
+            TermOutcome {
+                relay: false,
+                terminate: true
+            }
+        );
+        assert!(aba.terminated);
+        assert_eq!(aba.add_term(3, 1, NUM_FAULTS), TermOutcome::default());
+    }
+
+    #[test]
+    fn t_plus_one_terms_decide_an_undecided_node() {
+        let mut aba = ABAState::new();
+        aba.round = 4;
+        aba.add_term(0, 0, NUM_FAULTS);
+        assert_eq!(
+            aba.add_term(1, 0, NUM_FAULTS),
+            TermOutcome {
+                relay: true,
+                terminate: false
+            }
+        );
+        assert_eq!((aba.decided, aba.decided_round, aba.rounds_taken()), (Some(0), 4, 5));
+    }
+}
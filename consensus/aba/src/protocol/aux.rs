@@ -0,0 +1,93 @@
+use async_recursion::async_recursion;
+
+use crate::{ABAMsg, Context, ProtMsg};
+
+impl Context {
+    // Send <AUX, r, w> once bin_values of the current round is non-empty
+    pub async fn send_aux(self: &mut Context, instance_id: usize) {
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        if aba_context.terminated {
+            return;
+        }
+        let round = aba_context.round;
+        let round_state = aba_context.rounds.entry(round).or_default();
+        if round_state.sent_aux || round_state.bin_values.is_empty() {
+            return;
+        }
+        round_state.sent_aux = true;
+        let value = *round_state.bin_values.iter().next().unwrap();
+
+        if self.crash {
+            return;
+        }
+        let aba_msg = ABAMsg {
+            round,
+            value,
+            origin: self.myid,
+        };
+        self.broadcast(ProtMsg::Aux(aba_msg.clone(), instance_id)).await;
+        self.handle_aux(aba_msg, instance_id).await;
+    }
+
+    pub async fn handle_aux(self: &mut Context, msg: ABAMsg, instance_id: usize) {
+        if msg.value > 1 {
+            log::error!(
+                "Non-binary AUX value {} sent by node {} for ABA instance {}",
+                msg.value,
+                msg.origin,
+                instance_id
+            );
+            return;
+        }
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        if aba_context.terminated {
+            return;
+        }
+
+        if !aba_context.rounds.entry(msg.round).or_default().add_aux(msg.origin, msg.value) {
+            return;
+        }
+
+        if aba_context.round == msg.round {
+            self.check_round_completion(instance_id).await;
+        }
+    }
+
+    // Wait for n-t AUX messages with values in bin_values, then toss the coin and move to the next round
+    #[async_recursion]
+    pub async fn check_round_completion(self: &mut Context, instance_id: usize) {
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        if aba_context.terminated {
+            return;
+        }
+        let round = aba_context.round;
+        let round_state = aba_context.rounds.entry(round).or_default();
+        let vals = match round_state.aux_quorum(self.num_nodes - self.num_faults) {
+            Some(vals) => vals,
+            None => return,
+        };
+        round_state.coin_tossed = true;
+
+        let coin = self.toss_coin(instance_id, round);
+        log::info!(
+            "Round {} of ABA instance id {} completed with values {:?} and coin {}",
+            round,
+            instance_id,
+            vals,
+            coin
+        );
+
+        let decision = self.aba_context.entry(instance_id).or_default().complete_round(&vals, coin);
+        if let Some(value) = decision {
+            log::info!(
+                "Decided {} in round {} for ABA instance id {}",
+                value,
+                round,
+                instance_id
+            );
+            self.send_term(value, instance_id).await;
+        }
+        // Keep participating in rounds until the TERM quorum terminates the instance
+        self.start_round(instance_id).await;
+    }
+}
@@ -0,0 +1,17 @@
+use crypto::hash::do_hash;
+
+use crate::Context;
+
+impl Context {
+    // Common coin for testing only: every node hashes a fixed seed with the instance id and round number, so all
+    // honest nodes see the same coin without exchanging messages. Every party, the adversary included, knows each
+    // coin in advance. An adversary that schedules messages after seeing the coins can keep the values of honest
+    // nodes from ever matching them, so the termination of Mostefaoui-Moumen-Raynal does not hold with this coin.
+    // Deployments need an unpredictable coin, e.g. one from threshold signatures.
+    pub fn toss_coin(&self, instance_id: usize, round: usize) -> u8 {
+        let mut coin_input = self.coin_seed.clone();
+        coin_input.extend(instance_id.to_be_bytes());
+        coin_input.extend(round.to_be_bytes());
+        do_hash(coin_input.as_slice())[0] & 1
+    }
+}
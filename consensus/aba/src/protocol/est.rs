@@ -0,0 +1,99 @@
+use async_recursion::async_recursion;
+
+use crate::{ABAMsg, Context, ProtMsg};
+
+impl Context {
+    pub async fn start_aba(self: &mut Context, value: u8, instance_id: usize) {
+        log::info!(
+            "Starting ABA for instance id {} with input {}. My byz status: {}",
+            instance_id,
+            value,
+            self.byz
+        );
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        aba_context.est = value;
+        aba_context.round = 0;
+
+        self.start_round(instance_id).await;
+    }
+
+    pub async fn start_round(self: &mut Context, instance_id: usize) {
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        let round = aba_context.round;
+        let est = aba_context.est;
+        log::info!(
+            "Starting round {} of ABA instance id {} with estimate {}",
+            round,
+            instance_id,
+            est
+        );
+
+        self.send_est(round, est, instance_id).await;
+        // EST messages of this round might have filled bin_values before we got here
+        self.send_aux(instance_id).await;
+    }
+
+    pub async fn send_est(self: &mut Context, round: usize, value: u8, instance_id: usize) {
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        let round_state = aba_context.rounds.entry(round).or_default();
+        if round_state.sent_est[value as usize] {
+            return;
+        }
+        round_state.sent_est[value as usize] = true;
+
+        if self.crash {
+            return;
+        }
+        let aba_msg = ABAMsg {
+            round,
+            value,
+            origin: self.myid,
+        };
+        self.broadcast(ProtMsg::Est(aba_msg.clone(), instance_id)).await;
+        self.handle_est(aba_msg, instance_id).await;
+    }
+
+    #[async_recursion]
+    pub async fn handle_est(self: &mut Context, msg: ABAMsg, instance_id: usize) {
+        if msg.value > 1 {
+            log::error!(
+                "Non-binary EST value {} sent by node {} for ABA instance {}",
+                msg.value,
+                msg.origin,
+                instance_id
+            );
+            return;
+        }
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        if aba_context.terminated {
+            // ABA already terminated, skip processing this message
+            return;
+        }
+
+        let outcome = aba_context
+            .rounds
+            .entry(msg.round)
+            .or_default()
+            .add_est(msg.origin, msg.value, self.num_faults);
+
+        // On t+1 ESTs, relay the value because at least one honest node proposed it
+        if outcome.relay {
+            self.send_est(msg.round, msg.value, instance_id).await;
+        }
+
+        // On 2t+1 ESTs, the value was proposed by an honest node and every honest node will see it
+        if outcome.bin_value {
+            let aba_context = self.aba_context.entry(instance_id).or_default();
+            log::info!(
+                "Added {} to bin_values of round {} for ABA instance id {}",
+                msg.value,
+                msg.round,
+                instance_id
+            );
+            if aba_context.round == msg.round {
+                self.send_aux(instance_id).await;
+                self.check_round_completion(instance_id).await;
+            }
+        }
+    }
+}
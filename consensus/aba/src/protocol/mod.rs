@@ -0,0 +1,10 @@
+mod est;
+
+mod aux;
+
+mod coin;
+
+mod term;
+
+mod aba_state;
+pub use aba_state::*;
@@ -0,0 +1,60 @@
+use async_recursion::async_recursion;
+
+use crate::{ABAMsg, Context, ProtMsg};
+
+impl Context {
+    pub async fn send_term(self: &mut Context, value: u8, instance_id: usize) {
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        if aba_context.sent_term {
+            return;
+        }
+        aba_context.sent_term = true;
+        let round = aba_context.round;
+
+        if self.crash {
+            return;
+        }
+        let aba_msg = ABAMsg {
+            round,
+            value,
+            origin: self.myid,
+        };
+        self.broadcast(ProtMsg::Term(aba_msg.clone(), instance_id)).await;
+        self.handle_term(aba_msg, instance_id).await;
+    }
+
+    #[async_recursion]
+    pub async fn handle_term(self: &mut Context, msg: ABAMsg, instance_id: usize) {
+        if msg.value > 1 {
+            log::error!(
+                "Non-binary TERM value {} sent by node {} for ABA instance {}",
+                msg.value,
+                msg.origin,
+                instance_id
+            );
+            return;
+        }
+        let aba_context = self.aba_context.entry(instance_id).or_default();
+        if aba_context.terminated {
+            return;
+        }
+
+        let outcome = aba_context.add_term(msg.origin, msg.value, self.num_faults);
+
+        // t+1 TERMs imply an honest node decided this value
+        if outcome.relay {
+            self.send_term(msg.value, instance_id).await;
+        }
+
+        if outcome.terminate {
+            let rounds = self.aba_context.entry(instance_id).or_default().rounds_taken();
+            log::info!(
+                "Terminating ABA instance id {} with output {} after {} rounds",
+                instance_id,
+                msg.value,
+                rounds
+            );
+            self.terminate(instance_id, msg.value, rounds).await;
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt::Debug;
 
+use bytes::Bytes;
 use crypto::hash::Hash;
 use reed_solomon_rs::fec::fec::*;
 use serde::{Deserialize, Serialize};
@@ -7,7 +8,7 @@ use types::Replica;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Msg {
-    pub content: Vec<u8>,
+    pub content: Bytes,
     pub origin: Replica,
     // Add your custom fields here
 }
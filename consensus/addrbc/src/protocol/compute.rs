@@ -0,0 +1,59 @@
+use consensus::{offload, DecodeAttempt};
+use crypto::hash::Hash;
+
+use crate::Context;
+
+// Results of the work offloaded to the blocking pool, handed back to the event loop
+pub enum Computed {
+    // Hash and shares of the message received from the dealer
+    Encoding {
+        instance_id: usize,
+        hash: Hash,
+        result: Result<Vec<Vec<u8>>, String>,
+    },
+    // Outcome of an online error correction attempt over the READY fragments for this hash
+    Decoding {
+        instance_id: usize,
+        hash: Hash,
+        output: Option<Vec<u8>>,
+    },
+}
+
+impl Context {
+    // Run an online error correction attempt without blocking the event loop
+    pub fn start_decoding(&self, instance_id: usize, hash: Hash, attempt: DecodeAttempt) {
+        let hash_kind = self.hash;
+        offload(&self.compute_send, move || {
+            let output = attempt.run().filter(|data| {
+                let matches = hash_kind.digest(data) == hash;
+                if !matches {
+                    log::warn!("Decoded message does not match its hash for instance id: {:?}", instance_id);
+                }
+                matches
+            });
+            Computed::Decoding {
+                instance_id,
+                hash,
+                output,
+            }
+        });
+    }
+
+    pub async fn handle_computed(&mut self, computed: Computed) {
+        match computed {
+            Computed::Encoding {
+                instance_id,
+                hash,
+                result,
+            } => match result {
+                Ok(shards) => self.send_echo(hash, shards, instance_id).await,
+                Err(e) => log::info!("Encoding failed with error: {:?}", e),
+            },
+            Computed::Decoding {
+                instance_id,
+                hash,
+                output,
+            } => self.finish_decoding(instance_id, hash, output).await,
+        }
+    }
+}
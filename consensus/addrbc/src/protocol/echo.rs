@@ -1,10 +1,10 @@
 // TODO: Make into broadcast
-use crypto::hash::{do_hash, Hash};
+use bytes::Bytes;
+use crypto::hash::Hash;
 use reed_solomon_rs::fec::fec::*;
 
-use crate::{Context, ProtMsg, ShareMsg};
-
-use types::WrapperMsg;
+use crate::{Computed, Context, ProtMsg, ShareMsg};
+use consensus::offload;
 
 use crate::Status;
 use network::{plaintcp::CancelHandler, Acknowledgement};
@@ -18,8 +18,19 @@ impl Context {
         };
         self.handle_echo(msg, instance_id).await;
     }
-    pub async fn start_echo(self: &mut Context, msg_content: Vec<u8>, instance_id: usize) {
-        let hash = do_hash(&msg_content);
+    pub async fn start_echo(self: &mut Context, msg_content: Bytes, instance_id: usize) {
+        assert!(msg_content.len() > 0, "Message content is empty");
+        // Hashing and encoding a large message takes a while. Do it off the event loop and echo once done.
+        let codec = self.codec.clone();
+        let hash = self.hash;
+        offload(&self.compute_send, move || Computed::Encoding {
+            instance_id,
+            hash: hash.digest(&msg_content),
+            result: codec.encode(&msg_content),
+        });
+    }
+
+    pub async fn send_echo(self: &mut Context, hash: Hash, shards: Vec<Vec<u8>>, instance_id: usize) {
         let rbc_context = self.rbc_context.entry(instance_id).or_default();
         let _status = &rbc_context.status;
         // if *status != Status::INIT || *status != Status::WAITING {
@@ -30,41 +41,21 @@ impl Context {
         //     "Start Echo: Status is not INIT for instance id: {:?}. Found {:?} instead.",
         //     instance_id, status
         // );
-        let f = match FEC::new(self.num_faults, self.num_nodes) {
-            Ok(f) => f,
-            Err(e) => {
-                log::info!("FEC initialization failed with error: {:?}", e);
-                return;
-            }
-        };
-        let mut shares: Vec<Share> = vec![
-            Share {
-                number: 0,
-                data: vec![]
-            };
-            self.num_nodes
-        ];
-        {
-            let output = |s: Share| {
-                shares[s.number] = s.clone(); // deep copy
-            };
-            assert!(msg_content.len() > 0, "Message content is empty");
-            if let Err(e) = f.encode(&msg_content, output) {
-                log::info!("Encoding failed with error: {:?}", e);
-            }
-            //f.encode(&msg_content, output)?;
-        }
+        let shares: Vec<Share> = shards
+            .into_iter()
+            .enumerate()
+            .map(|(number, data)| Share { number, data })
+            .collect();
         rbc_context.fragment = shares[self.myid].clone();
 
         // log::info!("Decoding Shares: {:?}", shares);
 
         // Echo to every node the encoding corresponding to the replica id
-        let sec_key_map = self.sec_key_map.clone();
         // Sleep to simulate network delay
         // log::info!("Starting echo for: {:?}", instance_id,);
         // sleep(Duration::from_millis(50)).await;
         if !self.crash {
-            for (replica, sec_key) in sec_key_map.into_iter() {
+            for replica in 0..self.num_nodes {
                 if replica == self.myid {
                     self.echo_self(hash, shares[self.myid].clone(), instance_id)
                         .await;
@@ -85,8 +76,7 @@ impl Context {
                 };
 
                 let protocol_msg = ProtMsg::Echo(msg, instance_id);
-                let wrapper_msg =
-                    WrapperMsg::new(protocol_msg.clone(), self.myid, &sec_key.as_slice());
+                let wrapper_msg = self.auth.wrap(&protocol_msg, replica);
                 let cancel_handler: CancelHandler<Acknowledgement> =
                     self.net_send.send(replica, wrapper_msg).await;
                 self.add_cancel_handler(cancel_handler);
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::{Context, Msg, ProtMsg};
 
 use crate::Status;
@@ -25,8 +27,9 @@ impl Context {
         );
         rbc_context.status = Status::INIT;
         // Draft a message
+        self.compression.account(&input_msg, self.num_nodes - 1);
         let msg = Msg {
-            content: input_msg.clone(),
+            content: Bytes::from(input_msg),
             origin: self.myid,
         };
         self.handle_init(msg.clone(), instance_id).await;
@@ -38,8 +41,21 @@ impl Context {
     }
 
     pub async fn handle_init(self: &mut Context, msg: Msg, instance_id: usize) {
+        // Only echo payloads the application accepts
+        let valid = match self.compression.decompress(&msg.content) {
+            Some(payload) => (self.validator)(&payload),
+            None => false,
+        };
+        if !valid {
+            log::warn!(
+                "Payload sent by node {} failed validation, abandoning RBC instance {}",
+                msg.origin,
+                instance_id
+            );
+            return;
+        }
         //send echo
-        self.start_echo(msg.content.clone(), instance_id).await;
+        self.start_echo(msg.content, instance_id).await;
 
         // log::info!(
         //     "Received Init message {:?} from node {}.",
@@ -4,5 +4,8 @@ mod echo;
 
 mod ready;
 
+mod compute;
+pub use compute::*;
+
 mod rbc_state;
 pub use rbc_state::*;
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+use consensus::OnlineDecoder;
 use crypto::hash::Hash;
 use reed_solomon_rs::fec::fec::*;
 use std::collections::{HashMap, HashSet};
@@ -15,10 +17,12 @@ pub enum Status {
 pub struct RBCState {
     pub received_echo_count: HashMap<Hash, usize>,
     pub received_readys: HashMap<Hash, Vec<Share>>,
+    // Online error correction of the READY fragments per hash
+    pub decoders: HashMap<Hash, OnlineDecoder>,
     pub echo_senders: HashMap<Hash, HashSet<usize>>,
     pub ready_senders: HashMap<Hash, HashSet<usize>>,
     pub fragment: Share,
-    pub output_message: Vec<u8>,
+    pub output_message: Bytes,
     pub status: Status,
     pub sent_ready: bool,
 }
@@ -28,13 +32,14 @@ impl RBCState {
         RBCState {
             received_echo_count: HashMap::default(),
             received_readys: HashMap::default(),
+            decoders: HashMap::default(),
             echo_senders: HashMap::default(),
             ready_senders: HashMap::default(),
             fragment: Share {
                 number: 0,
                 data: vec![],
             },
-            output_message: vec![],
+            output_message: Bytes::new(),
             status: Status::WAITING,
             sent_ready: false,
         }
@@ -1,12 +1,12 @@
 // TODO: Call broadcast
+use bytes::Bytes;
+
 use crate::{Context, ProtMsg, ShareMsg, Status};
+use consensus::OnlineDecoder;
 use crypto::hash::Hash;
 use network::{plaintcp::CancelHandler, Acknowledgement};
-use reed_solomon_rs::fec::fec::FEC;
 use reed_solomon_rs::fec::fec::*;
 
-use types::WrapperMsg;
-
 impl Context {
     pub async fn ready_self(&mut self, hash: Hash, instance_id: usize) {
         let rbc_context = self.rbc_context.entry(instance_id).or_default();
@@ -61,16 +61,15 @@ impl Context {
         // Sleep to simulate network delay
         // sleep(Duration::from_millis(50)).await;
         // Echo to every node the encoding corresponding to the replica id
-        let sec_key_map = self.sec_key_map.clone();
         if !self.crash {
-            for (replica, sec_key) in sec_key_map.into_iter() {
+            let sealed = self.auth.seal(&protocol_msg);
+            for replica in 0..self.num_nodes {
                 if replica == self.myid {
                     self.ready_self(hash, instance_id).await;
                     continue;
                 }
 
-                let wrapper_msg =
-                    WrapperMsg::new(protocol_msg.clone(), self.myid, &sec_key.as_slice());
+                let wrapper_msg = self.auth.envelope(&sealed, replica);
                 let cancel_handler: CancelHandler<Acknowledgement> =
                     self.net_send.send(replica, wrapper_msg).await;
                 self.add_cancel_handler(cancel_handler);
@@ -97,7 +96,7 @@ impl Context {
             rbc_context.status = Status::TERMINATED;
             let _ = rbc_context;
             log::info!("Terminating for instance id: {:?}", instance_id);
-            self.terminate(output_message).await;
+            self.terminate(instance_id, output_message).await;
             return;
         }
         // log::info!("Received {:?} as ready", msg);
@@ -112,6 +111,7 @@ impl Context {
                 .received_readys
                 .entry(msg.hash.clone())
                 .or_default();
+            let fragment_data = msg.share.data.clone();
             shares.push(msg.share);
 
             let (max_shares_count, max_shares_hash) = rbc_context.get_max_ready_count();
@@ -149,17 +149,13 @@ impl Context {
                         let protocol_msg = ProtMsg::Ready(msg.clone(), instance_id);
 
                         if !self.crash {
-                            let sec_key_map = self.sec_key_map.clone();
-                            for (replica, sec_key) in sec_key_map.into_iter() {
+                            let sealed = self.auth.seal(&protocol_msg);
+                            for replica in 0..self.num_nodes {
                                 if replica == self.myid {
                                     continue;
                                 }
 
-                                let wrapper_msg = WrapperMsg::new(
-                                    protocol_msg.clone(),
-                                    self.myid,
-                                    &sec_key.as_slice(),
-                                );
+                                let wrapper_msg = self.auth.envelope(&sealed, replica);
                                 let cancel_handler: CancelHandler<Acknowledgement> =
                                     self.net_send.send(replica, wrapper_msg).await;
 
@@ -172,53 +168,42 @@ impl Context {
 
             let rbc_context = self.rbc_context.entry(instance_id).or_default();
 
-            // If we have enough shares for a hash, prepare for error correction
-            if max_shares_count >= self.num_nodes - self.num_faults {
-                if let Some(hash) = max_shares_hash {
-                    let shares_for_correction = rbc_context.received_readys.get(&hash).unwrap();
-                    assert!(
-                        shares_for_correction.len() >= self.num_nodes - self.num_faults,
-                        "Not enough shares for error correction"
-                    );
-                    let f = match FEC::new(self.num_faults, self.num_nodes) {
-                        Ok(f) => f,
-                        Err(e) => {
-                            log::info!("FEC initialization failed with error: {:?}", e);
-                            return;
-                        }
-                    };
-                    // log::info!("Decoding {:?}", shares_for_correction.to_vec());
-                    // assert that the length of each share for correction is the same
-                    // for share in shares_for_correction.iter() {
-                    //     assert!(
-                    //         share.data.len() == shares_for_correction[0].data.len(),
-                    //         "Share length mismatch, 0: {:?}, index: {:?}",
-                    //         shares_for_correction[0].data.len(),
-                    //         share.data.len()
-                    //     );
-                    // }
-                    match f.decode([].to_vec(), shares_for_correction.to_vec()) {
-                        Ok(data) => {
-                            if data.len() != 0 {
-                                log::info!("Outputting: for instance id: {:?}", instance_id);
-                                rbc_context.output_message = data;
-                                rbc_context.status = Status::OUTPUT;
-                            }
-                        }
-                        Err(e) => {
-                            log::info!("Decoding failed with error: {}", e.to_string());
-                        }
-                    }
-                    if rbc_context.status == Status::OUTPUT {
-                        let output_message = rbc_context.output_message.clone();
-                        rbc_context.status = Status::TERMINATED;
-                        let _ = rbc_context;
-                        log::info!("Terminating for instance id: {:?}", instance_id);
-                        self.terminate(output_message).await;
-                        return;
-                    }
-                }
+            // Online error correction over the READY fragments for this hash, retried with every new fragment.
+            // Fragments are indexed by their sender, which process_msg checks against the origin they name, so a faulty node
+            // cannot occupy the slot of an honest one.
+            // Decoding runs on the blocking pool and continues in finish_decoding.
+            let codec = self.codec.clone();
+            let num_faults = self.num_faults;
+            let decoder = rbc_context
+                .decoders
+                .entry(msg.hash)
+                .or_insert_with(|| OnlineDecoder::new(codec, num_faults));
+            if let Some(attempt) = decoder.offer_share(msg.origin, fragment_data) {
+                self.start_decoding(instance_id, msg.hash, attempt);
             }
         }
     }
+
+    pub async fn finish_decoding(self: &mut Context, instance_id: usize, hash: Hash, output: Option<Vec<u8>>) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if rbc_context.status == Status::TERMINATED {
+            return;
+        }
+        let decoder = match rbc_context.decoders.get_mut(&hash) {
+            Some(decoder) => decoder,
+            None => return,
+        };
+        // Fragments that arrived while decoding get another attempt
+        if let Some(attempt) = decoder.finish_attempt(output) {
+            self.start_decoding(instance_id, hash, attempt);
+            return;
+        }
+        if let Some(data) = decoder.output().cloned().map(Bytes::from) {
+            log::info!("Outputting: for instance id: {:?}", instance_id);
+            rbc_context.output_message = data.clone();
+            rbc_context.status = Status::TERMINATED;
+            log::info!("Terminating for instance id: {:?}", instance_id);
+            self.terminate(instance_id, data).await;
+        }
+    }
 }
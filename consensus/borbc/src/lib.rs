@@ -6,8 +6,5 @@ mod process;
 mod msg;
 use msg::*;
 
-mod handlers;
-pub use handlers::*;
-
 mod protocol;
 pub use protocol::*;
\ No newline at end of file
@@ -1,25 +1,34 @@
-use crypto::aes_hash::{HashState, Proof};
-
-use crypto::hash::{do_hash};
+use bytes::Bytes;
+use consensus::{Evidence, HashKind, MerkleProof};
 use serde::{Deserialize, Serialize};
 
 use types::{Replica};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CTRBCMsg {
-    pub shard: Vec<u8>,
-    pub mp: Proof,
+    pub shard: Bytes,
+    pub mp: MerkleProof,
     pub origin: Replica,
 }
 
 impl CTRBCMsg {
-    pub fn verify_mr_proof(&self, hf: &HashState) -> bool {
-        // 2. Validate Merkle Proof
-        let hash_of_shard: [u8; 32] = do_hash(&self.shard.as_slice());
-        let state: bool = hash_of_shard == self.mp.item().clone() && self.mp.validate(hf);
+    pub fn verify_mr_proof(&self, hash: HashKind, index: usize, num_nodes: usize) -> bool {
+        // 2. Validate Merkle Proof of the shard at its position in the tree over the shards of all nodes
+        let hash_of_shard: [u8; 32] = hash.digest(&self.shard);
+        let state: bool =
+            self.mp.index() == index && hash_of_shard == self.mp.item() && self.mp.validate(hash, num_nodes);
         return state;
     }
 }
+// Segment of the shard of a streamed INIT. The INIT itself then carries an empty shard.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StripeMsg {
+    pub index: usize,
+    pub count: usize,
+    pub data: Bytes,
+    pub origin: Replica,
+}
+
 /*
 this is how the rbc protocol works
 1. <sendall, m> (this is broadcast)
@@ -36,4 +45,7 @@ pub enum ProtMsg {
     Echo(CTRBCMsg, usize),
     Ready(CTRBCMsg, usize),
     Vote(CTRBCMsg, usize),
+    Stripe(StripeMsg, usize),
+    // Evidence against the dealer of an instance
+    Blame(Evidence, usize),
 }
\ No newline at end of file
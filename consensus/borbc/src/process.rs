@@ -1,37 +1,69 @@
-use std::sync::Arc;
+use bytes::Bytes;
 
 use super::{ProtMsg};
 use crate::context::Context;
-use crypto::hash::verf_mac;
-use types::{SyncMsg, SyncState, WrapperMsg};
+use consensus::{append_blame, validated, Envelope};
+use types::{Replica, SyncMsg, SyncState};
+
+// Instance whose state a message makes this node keep
+fn instance(protmsg: &ProtMsg) -> Option<usize> {
+    match protmsg {
+        ProtMsg::Init(_, instance_id)
+        | ProtMsg::Echo(_, instance_id)
+        | ProtMsg::Ready(_, instance_id)
+        | ProtMsg::Vote(_, instance_id)
+        | ProtMsg::Stripe(_, instance_id)
+        | ProtMsg::Blame(_, instance_id) => Some(*instance_id),
+    }
+}
+
+// Origin an ECHO or READY claims, whose position its shard must open
+fn claimed_origin(protmsg: &ProtMsg) -> Option<Replica> {
+    match protmsg {
+        ProtMsg::Echo(msg, _) | ProtMsg::Ready(msg, _) | ProtMsg::Vote(msg, _) => Some(msg.origin),
+        ProtMsg::Init(..) | ProtMsg::Stripe(..) | ProtMsg::Blame(..) => None,
+    }
+}
 
 impl Context {
-    // This function verifies the Message Authentication Code (MAC) of a sent message
-    // A node cannot impersonate as another node because of MACs
-    pub fn check_proposal(&self, wrapper_msg: Arc<WrapperMsg<ProtMsg>>) -> bool {
-        // validate MAC
-        let byte_val =
-            bincode::serialize(&wrapper_msg.protmsg).expect("Failed to serialize object");
-        let sec_key = match self.sec_key_map.get(&wrapper_msg.clone().sender) {
-            Some(val) => val,
-            None => {
-                panic!("Secret key not available, this shouldn't happen")
-            }
-        };
-        if !verf_mac(&byte_val, &sec_key.as_slice(), &wrapper_msg.mac) {
-            log::warn!("MAC Verification failed.");
-            return false;
+    // This function verifies the MAC or signature of a sent message
+    // A node cannot impersonate as another node because of them
+    pub fn check_proposal(&self, envelope: &Envelope) -> Option<ProtMsg> {
+        // validate the MAC or signature on the received bytes, and only then deserialize them
+        let protmsg = self.auth.open(envelope);
+        if protmsg.is_none() {
+            log::warn!("Authentication of a message from node {} failed.", envelope.sender);
         }
-        true
+        protmsg
     }
 
-    pub(crate) async fn process_msg(&mut self, wrapper_msg: WrapperMsg<ProtMsg>) {
-        log::trace!("Received protocol msg: {:?}", wrapper_msg);
-        let msg = Arc::new(wrapper_msg.clone());
-
+    pub(crate) async fn process_msg(&mut self, envelope: Envelope) {
         // Verify the message's authenticity before proceeding
-        if self.check_proposal(msg) {
-            match wrapper_msg.clone().protmsg {
+        if let Some(protmsg) = self.check_proposal(&envelope) {
+            log::trace!("Received protocol msg: {:?}", protmsg);
+            // Shards are kept under the origin they name, which must be the node that sent them
+            if let Some(origin) = claimed_origin(&protmsg) {
+                if origin != envelope.sender {
+                    log::warn!("Node {} sent a message in the name of node {}", envelope.sender, origin);
+                    return;
+                }
+            }
+            // Only the dealer an instance id names may start that instance
+            if let ProtMsg::Init(_, instance_id) = &protmsg {
+                if envelope.sender != self.dealer(*instance_id) {
+                    log::warn!("Node {} sent an INIT for instance {} of another dealer", envelope.sender, instance_id);
+                    return;
+                }
+            }
+            // Fabricated instances and floods are held back before they take any state
+            let instance_id = instance(&protmsg);
+            let known = instance_id.is_none_or(|instance_id| self.rbc_context.contains_key(&instance_id));
+            let envelope = match self.admission.admit(envelope.sender, instance_id, known, envelope) {
+                Some(envelope) => envelope,
+                None => return,
+            };
+            self.check_dealer(&envelope, &protmsg).await;
+            match protmsg {
                 ProtMsg::Echo(main_msg, instance_id) => {
                     // RBC initialized
                     log::debug!("Received Echo for instance id {} from node : {}", instance_id, main_msg.origin);
@@ -45,40 +77,68 @@ impl Context {
                 ProtMsg::Init(main_msg, instance_id) => {
                     // RBC initialized
                     log::debug!("Received Init for instance id {} from node : {}", instance_id, main_msg.origin);
+                    // Kept as evidence in case the share does not verify
+                    self.init_envelope = Some(envelope);
                     self.handle_init(main_msg,instance_id).await;
+                    self.init_envelope = None;
                 }
                 ProtMsg::Vote(main_msg, instance_id) => {
                     // RBC initialized
                     log::debug!("Received Vote for instance id {} from node : {}", instance_id, main_msg.origin);
                     self.handle_vote(main_msg,instance_id).await;
                 }
+                ProtMsg::Stripe(main_msg, instance_id) => {
+                    log::debug!("Received stripe {} for instance id {} from node : {}", main_msg.index, instance_id, envelope.sender);
+                    // Only the dealer hands out shards
+                    if envelope.sender != self.dealer(instance_id) {
+                        log::warn!("Node {} sent a stripe of RBC instance {} it does not deal", envelope.sender, instance_id);
+                        return;
+                    }
+                    self.handle_stripe(main_msg, instance_id).await;
+                }
+                ProtMsg::Blame(evidence, instance_id) => {
+                    log::debug!("Received evidence for instance id {} against node {}", instance_id, evidence.culprit());
+                    self.handle_blame(evidence, instance_id).await;
+                }
             }
-        } else {
-            log::warn!(
-                "MAC Verification failed for message {:?}",
-                wrapper_msg.protmsg
-            );
         }
     }
 
     // Invoke this function once you terminate the protocol
-    pub async fn terminate(&mut self, instance_id: usize, data: Vec<u8>) {
-        log::info!("Terminating RBC instance id: {:?} {}", instance_id, self.term_instances.len());
-        self.term_instances.insert(instance_id);
-        if self.term_instances.len() == self.num_nodes && !self.sent_term{
-            self.sent_term = true;
-            log::info!("All RBC instances terminated, sending to sync module");
-            let cancel_handler = self
-            .sync_send
-            .send(
-                0,
-                SyncMsg {
-                    sender: self.myid,
-                    state: SyncState::COMPLETED,
-                    value: data,
-                },
-            ).await;
-            self.add_cancel_handler(cancel_handler);
+    pub async fn terminate(&mut self, instance_id: usize, data: Bytes) {
+        self.admission.delivered(instance_id);
+        // Honest nodes never deliver payloads failing the application's validity predicate.
+        // All honest nodes terminate with the same payload, so they all deliver nothing for this instance.
+        let data = validated(&self.compression, &self.validator, &data);
+        if data.is_none() {
+            log::warn!("Output of RBC instance {} failed validation, delivering nothing", instance_id);
+        }
+        // The ordering layer may hold this instance back or release earlier ones along with it
+        for (delivered_id, payload) in self.delivery.deliver(instance_id, data) {
+            if let Some(out_send) = &self.out_send {
+                if let Err(e) = out_send.send((delivered_id, payload)) {
+                    log::error!("Failed to hand RBC instance {} to the upper layer: {:?}", delivered_id, e);
+                }
+                continue;
+            }
+            log::info!("Terminating RBC instance id: {:?} {}", delivered_id, self.term_instances.len());
+            self.term_instances.insert(delivered_id);
+            if self.term_instances.len() == self.num_nodes && !self.sent_term{
+                self.sent_term = true;
+                log::info!("All RBC instances terminated, sending to sync module");
+                let cancel_handler = self
+                .sync_send
+                .send(
+                    0,
+                    SyncMsg {
+                        sender: self.myid,
+                        state: SyncState::COMPLETED,
+                        // The syncer reports the nodes blamed so far along with the run
+                        value: append_blame(payload, &self.blame_list),
+                    },
+                ).await;
+                self.add_cancel_handler(cancel_handler);
+            }
         }
     }
 }
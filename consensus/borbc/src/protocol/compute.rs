@@ -0,0 +1,120 @@
+use bytes::Bytes;
+use consensus::{offload, HashKind, MerkleProof, MerkleTree, Striping};
+use crypto::hash::Hash;
+
+use crate::Context;
+
+// Results of the work offloaded to the blocking pool, handed back to the event loop
+pub enum Computed {
+    // Segments of one stripe of a streamed broadcast started by this node
+    Stripe {
+        instance_id: usize,
+        index: usize,
+        count: usize,
+        segments: Vec<Vec<u8>>,
+    },
+    // Shards of a broadcast started by this node and their Merkle tree. The shards are empty if they were streamed.
+    Dispersal {
+        instance_id: usize,
+        result: Result<(Vec<Vec<u8>>, MerkleTree), String>,
+    },
+    // Message interpolated from the n-f ECHOs or t+1 READYs carrying this root
+    Reconstruction {
+        instance_id: usize,
+        root: Hash,
+        phase: Phase,
+        result: Result<Reconstructed, String>,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Echo,
+    Ready,
+}
+
+pub struct Reconstructed {
+    pub message: Bytes,
+    pub share: Bytes,
+    pub proof: MerkleProof,
+}
+
+// Interpolate the message and our share. The message only counts if its encoding hashes to the root again, since
+// shards opening against the root need not encode a single message if the dealer is faulty.
+fn reconstruct(
+    striping: &Striping,
+    hash: HashKind,
+    mut shards: Vec<Option<Vec<u8>>>,
+    myid: usize,
+    root: Hash,
+    proof: Option<MerkleProof>,
+) -> Result<Reconstructed, String> {
+    let message = striping.reconstruct(&mut shards)?;
+    let (mut shards, digests) = striping.reencode(&message, hash)?;
+    let merkle_tree = MerkleTree::new(digests, hash);
+    if merkle_tree.root() != root {
+        return Err("The shards under this root do not encode a single message".to_string());
+    }
+    let proof = proof.unwrap_or_else(|| merkle_tree.gen_proof(myid));
+    Ok(Reconstructed {
+        message: Bytes::from(message),
+        share: Bytes::from(shards.swap_remove(myid)),
+        proof,
+    })
+}
+
+impl Context {
+    // Interpolate the message from the shards received under this root without blocking the event loop
+    pub fn start_reconstruction(&self, instance_id: usize, root: Hash, phase: Phase, shards: Vec<Option<Vec<u8>>>) {
+        // Hashes on large messages are very expensive. Do as much as you can to avoid recomputing them.
+        let proof = self
+            .rbc_context
+            .get(&instance_id)
+            .and_then(|rbc_context| rbc_context.fragment.as_ref())
+            .filter(|(_, proof)| proof.root() == root)
+            .map(|(_, proof)| proof.clone());
+        let striping = self.striping.clone();
+        let hash = self.hash;
+        let myid = self.myid;
+        offload(&self.compute_send, move || Computed::Reconstruction {
+            instance_id,
+            root,
+            phase,
+            result: reconstruct(&striping, hash, shards, myid, root, proof),
+        });
+    }
+
+    pub async fn handle_computed(&mut self, computed: Computed) {
+        match computed {
+            Computed::Stripe {
+                instance_id,
+                index,
+                count,
+                segments,
+            } => self.send_stripe(segments, index, count, instance_id).await,
+            Computed::Dispersal { instance_id, result } => match result {
+                Ok((shards, merkle_tree)) => self.send_init(shards, merkle_tree, instance_id).await,
+                Err(e) => log::error!("FATAL: Error in encoding for instance id {}: {}", instance_id, e),
+            },
+            Computed::Reconstruction {
+                instance_id,
+                root,
+                phase,
+                result,
+            } => {
+                let reconstructed = match result {
+                    Ok(reconstructed) => reconstructed,
+                    // Only a faulty dealer makes reconstruction fail, and no honest node delivers its message then
+                    Err(e) => {
+                        log::warn!("Unable to reconstruct the message of RBC instance {}: {}", instance_id, e);
+                        return;
+                    }
+                };
+                match phase {
+                    Phase::Echo => self.finish_echo(instance_id, root, reconstructed).await,
+                    Phase::Ready => self.finish_ready(instance_id, root, reconstructed).await,
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,6 @@
+use crypto::hash::Hash;
 
-use consensus::reconstruct_data;
-
-use super::init::construct_merkle_tree;
-use crate::ProtMsg;
-use crate::{CTRBCMsg, Context};
+use crate::{CTRBCMsg, Context, Phase, ProtMsg, Reconstructed};
 
 impl Context {
     pub async fn handle_echo(self: &mut Context, msg: CTRBCMsg, instance_id: usize) {
@@ -33,7 +30,7 @@ impl Context {
 
             let echo_senders = rbc_context.echos.entry(root).or_default();
             // check if verifies
-            if !echo_senders.contains_key(&msg.origin) && !msg.verify_mr_proof(&self.hash_context) {
+            if !echo_senders.contains_key(&msg.origin) && !msg.verify_mr_proof(self.hash, msg.origin, self.num_nodes) {
                 log::error!(
                     "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                     msg.origin,
@@ -154,68 +151,20 @@ impl Context {
         if should_reconstruct_opt_commit || (ready_quorum_reached && should_reconstruct_latch) || should_reconstruct_nf {
             let rbc_context = self.rbc_context.entry(instance_id).or_default();
 
-            let senders = rbc_context.echos.get(&root).unwrap().clone();
-            
-            let mut shards_opt: Vec<Option<Vec<u8>>> = (0..self.num_nodes).map(|rep|
-                 senders.get(&rep).cloned()
-             ).collect();
-                        
-            let status = reconstruct_data(&mut shards_opt, self.num_faults + 1, 2 * self.num_faults); // Added +1 to 2*self.num_faults to match Reed-Solomon 'n'
-            
-            if let Err(e) = status {
-                log::error!("FATAL: Error in Lagrange interpolation {}", e);
-                return;
-            }
-            let shards: Vec<Vec<u8>> = shards_opt.into_iter().map(|opt| opt.unwrap()).collect(); 
-
-            let mut message = Vec::new();
-            for i in 0..self.num_faults + 1 { 
-                message.extend(shards.get(i).expect("Missing share after reconstruction")); 
-            }
-            let my_share: Vec<u8> = shards[self.myid].clone();
-
-            // Hashes on large messages are very expensive. Do as much as you can to avoid recomputing them.
-            let my_proof;
-            if rbc_context.fragment.is_some() && rbc_context.fragment.clone().unwrap().1.root() == root {
-                my_proof = rbc_context.fragment.clone().unwrap().1;
-            } else {
-                my_proof = construct_merkle_tree(shards.clone(), &self.hash_context).gen_proof(self.myid);
+            if rbc_context.echo_root == Some(root) {
+                // The message under this root is already known, no need to interpolate again
+                self.advance_echo(instance_id, root, false).await;
+            } else if rbc_context.reconstructing.insert(root) {
+                let senders = rbc_context.echos.get(&root).unwrap().clone();
+
+                let shards_opt: Vec<Option<Vec<u8>>> = (0..self.num_nodes).map(|rep|
+                     senders.get(&rep).map(|shard| shard.to_vec())
+                 ).collect();
+                // Interpolation continues in finish_echo once the blocking pool is done
+                self.start_reconstruction(instance_id, root, Phase::Echo, shards_opt);
             }
-
-            // if merkle_tree.root() == root {
-                //let my_mp = merkle_tree.gen_proof(self.myid);
-                let out_msg = CTRBCMsg { shard: my_share.clone(), mp: my_proof.clone(), origin: self.myid };
-
-                let rbc_context = self.rbc_context.get_mut(&instance_id).unwrap();
-                
-                rbc_context.echo_root = Some(root);
-                rbc_context.fragment = Some((my_share.clone(), my_proof.clone()));
-                rbc_context.message = Some(message.clone());
-                
-                if should_reconstruct_opt_commit || should_reconstruct_latch {
-                     rbc_context.terminated = true;
-
-                     if !self.crash && !should_broadcast_ready_1 {
-                         self.broadcast(ProtMsg::Ready(out_msg.clone(), instance_id)).await;
-                     }
-                     log::info!("Terminated RBC after optimistic RBC path");
-                     self.terminate(instance_id, message).await;
-                     return;
-                }
-                
-                if should_reconstruct_nf {
-                    log::info!(
-                        "Received n-f ECHO messages for RBC Instance ID {}, sending READY message",
-                        instance_id
-                    );
-                    
-                    self.handle_ready(out_msg.clone(), instance_id).await;
-                    let ready_msg = ProtMsg::Ready(out_msg, instance_id);
-                    self.broadcast(ready_msg).await;
-                }
-            // }
         }
-        
+
         // Handle Optimistic termination at n
         if should_terminate_n {
             log::info!(
@@ -230,4 +179,61 @@ impl Context {
             self.terminate(instance_id, message_n.unwrap()).await;
         }
     }
+
+    // Interpolation from ECHOs is completed. Save our share and the root for later purposes and quick access.
+    pub async fn finish_echo(self: &mut Context, instance_id: usize, root: Hash, reconstructed: Reconstructed) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        rbc_context.reconstructing.remove(&root);
+        if rbc_context.terminated {
+            return;
+        }
+        rbc_context.echo_root = Some(root);
+        rbc_context.fragment = Some((reconstructed.share, reconstructed.proof));
+        rbc_context.message = Some(reconstructed.message);
+
+        self.advance_echo(instance_id, root, true).await;
+    }
+
+    // Commit through the optimistic paths, or send READY on n-f ECHOs, once the message under this root is known.
+    // More ECHOs and READYs may have arrived while interpolating, so the thresholds are checked on the current counts.
+    async fn advance_echo(self: &mut Context, instance_id: usize, root: Hash, reconstructed: bool) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if rbc_context.terminated {
+            return;
+        }
+        let size = rbc_context.echos.get(&root).map_or(0, |senders| senders.len());
+        let opt_commit_thresh = (self.num_nodes + 2*self.num_faults - 2 + 1) / 2;
+        let latch_echo_thresh = (self.num_nodes - self.num_faults + 1 + 1) / 2;
+        let commit = size >= opt_commit_thresh
+            || (rbc_context.ready_quorum_reached && size >= latch_echo_thresh)
+            || size == self.num_nodes;
+
+        let (my_share, my_proof) = rbc_context.fragment.clone().unwrap();
+        let message = rbc_context.message.clone().unwrap();
+        let out_msg = CTRBCMsg { shard: my_share, mp: my_proof, origin: self.myid };
+
+        if commit {
+            rbc_context.terminated = true;
+            let send_ready = !rbc_context.sent_ready;
+            rbc_context.sent_ready = true;
+
+            if !self.crash && send_ready {
+                self.broadcast(ProtMsg::Ready(out_msg, instance_id)).await;
+            }
+            log::info!("Terminated RBC after optimistic RBC path");
+            self.terminate(instance_id, message).await;
+            return;
+        }
+
+        if reconstructed && size >= self.num_nodes - self.num_faults {
+            log::info!(
+                "Received n-f ECHO messages for RBC Instance ID {}, sending READY message",
+                instance_id
+            );
+
+            self.handle_ready(out_msg.clone(), instance_id).await;
+            let ready_msg = ProtMsg::Ready(out_msg, instance_id);
+            self.broadcast(ready_msg).await;
+        }
+    }
 }
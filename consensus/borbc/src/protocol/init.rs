@@ -1,12 +1,8 @@
-use consensus::get_shards;
-use crypto::{
-    aes_hash::{HashState, MerkleTree},
-    hash::{do_hash, Hash},
-};
-use types::WrapperMsg;
+use bytes::{Bytes, BytesMut};
+use consensus::{offload_streamed, MerkleTree};
 
 use crate::Context;
-use crate::{CTRBCMsg, ProtMsg};
+use crate::{CTRBCMsg, Computed, ProtMsg, StripeMsg};
 use network::{plaintcp::CancelHandler, Acknowledgement};
 
 
@@ -18,19 +14,64 @@ impl Context {
             instance_id,
             self.byz
         );
-        let shards = get_shards(msg, self.num_faults + 1, 2 * self.num_faults);
-        let merkle_tree = construct_merkle_tree(shards.clone(), &self.hash_context);
-        
-        let sec_key_map = self.sec_key_map.clone();
+        // The shards are dealt once, so the payload counts once
+        self.compression.account(&msg, 1);
+        // Encoding and hashing a large message takes a while. Do it off the event loop and send the shards once done.
+        // Payloads of several stripes are streamed, and the Merkle tree is built from the digests of the shards.
+        let striping = self.striping.clone();
+        let hash = self.hash;
+        offload_streamed(&self.stripe_send, move |emit| {
+            let count = striping.stripes(msg.len());
+            let mut shards = Vec::new();
+            let digests = striping.encode(&msg, hash, |index, segments| {
+                if count == 1 {
+                    shards = segments;
+                } else {
+                    emit(Computed::Stripe {
+                        instance_id,
+                        index,
+                        count,
+                        segments,
+                    });
+                }
+            });
+            Computed::Dispersal {
+                instance_id,
+                result: digests.map(|digests| (shards, MerkleTree::new(digests, hash))),
+            }
+        });
+    }
+
+    // Send every node its segment of a stripe
+    pub async fn send_stripe(self: &mut Context, segments: Vec<Vec<u8>>, index: usize, count: usize, instance_id: usize) {
+        for (replica, segment) in segments.into_iter().enumerate() {
+            let stripe_msg = StripeMsg {
+                index,
+                count,
+                data: Bytes::from(segment),
+                origin: self.myid,
+            };
+            if replica == self.myid {
+                self.handle_stripe(stripe_msg, instance_id).await;
+            } else {
+                let protocol_msg = ProtMsg::Stripe(stripe_msg, instance_id);
+                let wrapper_msg = self.auth.wrap(&protocol_msg, replica);
+                let cancel_handler: CancelHandler<Acknowledgement> =
+                    self.net_send.send(replica, wrapper_msg).await;
+                self.add_cancel_handler(cancel_handler);
+            }
+        }
+    }
+
+    // Send every node its shard along with the Merkle proof. Streamed shards were sent already.
+    pub async fn send_init(self: &mut Context, shards: Vec<Vec<u8>>, merkle_tree: MerkleTree, instance_id: usize) {
         // Sleep to simulate network delay
         // sleep(Duration::from_millis(50)).await;
-        for (replica, sec_key) in sec_key_map.into_iter() {
+        // The shard kept by this node and the one in its message share a buffer
+        let shards: Vec<Bytes> = shards.into_iter().map(Bytes::from).collect();
+        for replica in 0..self.num_nodes {
             let ctrbc_msg = CTRBCMsg {
-                shard: if self.byz {
-                    shards[replica].clone()
-                } else {
-                    shards[replica].clone()
-                },
+                shard: shards.get(replica).cloned().unwrap_or_default(),
                 mp: if self.byz {
                     merkle_tree.gen_proof(replica)
                 } else {
@@ -43,8 +84,7 @@ impl Context {
                 self.handle_init(ctrbc_msg, instance_id).await;
             } else {
                 let protocol_msg = ProtMsg::Init(ctrbc_msg, instance_id);
-                let wrapper_msg =
-                    WrapperMsg::new(protocol_msg.clone(), self.myid, &sec_key.as_slice());
+                let wrapper_msg = self.auth.wrap(&protocol_msg, replica);
                 let cancel_handler: CancelHandler<Acknowledgement> =
                     self.net_send.send(replica, wrapper_msg).await;
                 self.add_cancel_handler(cancel_handler);
@@ -52,25 +92,78 @@ impl Context {
         }
     }
 
+    pub async fn handle_stripe(self: &mut Context, msg: StripeMsg, instance_id: usize) {
+        let streamed = &mut self.rbc_context.entry(instance_id).or_default().streamed;
+        if streamed.assembled {
+            return;
+        }
+        if msg.index >= msg.count || streamed.count.is_some_and(|count| count != msg.count) {
+            log::warn!("Node {} sent stripes of RBC instance {} that do not add up", msg.origin, instance_id);
+            return;
+        }
+        streamed.count = Some(msg.count);
+        streamed.stripes.entry(msg.index).or_insert(msg.data);
+        if let Some(init) = self.assemble_shard(instance_id) {
+            self.handle_init(init, instance_id).await;
+        }
+    }
+
+    // The INIT of a streamed shard, with the shard put together once it and all stripes are in
+    fn assemble_shard(&mut self, instance_id: usize) -> Option<CTRBCMsg> {
+        let streamed = &mut self.rbc_context.get_mut(&instance_id)?.streamed;
+        let count = streamed.count?;
+        if streamed.init.is_none() || streamed.stripes.len() < count {
+            return None;
+        }
+        let mut shard = BytesMut::with_capacity(streamed.stripes.values().map(|stripe| stripe.len()).sum());
+        for index in 0..count {
+            shard.extend_from_slice(&streamed.stripes[&index]);
+        }
+        streamed.stripes.clear();
+        streamed.assembled = true;
+        let mut init = streamed.init.take()?;
+        init.shard = shard.freeze();
+        Some(init)
+    }
+
     pub async fn handle_init(self: &mut Context, msg: CTRBCMsg, instance_id: usize) {
         //send echo
         // self.start_echo(msg.content.clone()).await;
-        if !msg.verify_mr_proof(&self.hash_context) {
+        // The shard of a streamed INIT comes in stripes, which may still be on their way. The INIT alone does not
+        // bind the dealer to the shard, so it is no evidence if the shard fails its proof.
+        let msg = if msg.shard.is_empty() {
+            self.init_envelope = None;
+            let rbc_context = self.rbc_context.entry(instance_id).or_default();
+            if rbc_context.streamed.assembled {
+                return;
+            }
+            rbc_context.streamed.init = Some(msg);
+            match self.assemble_shard(instance_id) {
+                Some(msg) => msg,
+                None => return,
+            }
+        } else {
+            msg
+        };
+        // The INIT does not name its receiver, so an opening of another position proves nothing against the dealer
+        if msg.mp.index() != self.myid {
+            log::warn!("INIT of node {} opens the shard of node {}, ignoring", msg.origin, msg.mp.index());
+            return;
+        }
+        if !msg.verify_mr_proof(self.hash, self.myid, self.num_nodes) {
             log::error!(
                 "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                 msg.origin,
                 instance_id
             );
+            self.blame_invalid_init(instance_id).await;
             return;
         }
 
         let ctrbc_msg;
         if self.byz{
-            let zero_shards: Vec<Vec<u8>> = (0..self.num_nodes)
-            .map(|_| vec![0u8; msg.shard.len()])
-            .collect();
             ctrbc_msg = CTRBCMsg {
-                shard: zero_shards[msg.origin].clone(),
+                shard: Bytes::from(vec![0u8; msg.shard.len()]),
                 mp: msg.mp.clone(),
                 origin: self.myid,
             };
@@ -98,9 +191,3 @@ impl Context {
         //self.terminate("1".to_string()).await;
     }
 }
-
-pub fn construct_merkle_tree(shards: Vec<Vec<u8>>, hc: &HashState) -> MerkleTree {
-    let hashes_rbc: Vec<Hash> = shards.into_iter().map(|x| do_hash(x.as_slice())).collect();
-
-    MerkleTree::new(hashes_rbc, hc)
-}
@@ -4,5 +4,10 @@ mod echo;
 mod vote;
 mod ready;
 
+mod blame;
+
+mod compute;
+pub use compute::*;
+
 mod rbc_state;
 pub use rbc_state::*;
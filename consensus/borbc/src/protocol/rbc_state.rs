@@ -1,22 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crypto::{aes_hash::{Proof}, hash::Hash};
+use bytes::Bytes;
+use consensus::{Envelope, MerkleProof};
+use crypto::hash::Hash;
+
+use crate::CTRBCMsg;
 
 pub struct RBCState{
-    pub echos: HashMap<Hash, HashMap<usize,Vec<u8>>>,
+    pub echos: HashMap<Hash, HashMap<usize,Bytes>>,
     pub echo_root: Option<Hash>,
+    // Roots whose ECHO shards are being interpolated on the blocking pool
+    pub reconstructing: HashSet<Hash>,
 
-    pub readys: HashMap<Hash, HashMap<usize,Vec<u8>>>,
+    pub readys: HashMap<Hash, HashMap<usize,Bytes>>,
 
 
-    pub votes: HashMap<Hash, HashMap<usize,Vec<u8>>>, 
+    pub votes: HashMap<Hash, HashMap<usize,Bytes>>, 
     pub sent_vote: bool,       
     pub sent_ready: bool,      
     pub ready_quorum_reached: bool,     // 2f+1 
 
     
-    pub fragment: Option<(Vec<u8>, Proof)>,
-    pub message: Option<Vec<u8>>,
+    pub fragment: Option<(Bytes, MerkleProof)>,
+    pub message: Option<Bytes>,
+
+    // First message of the dealer and the root it carries
+    pub dealer_binding: Option<(Hash, Envelope)>,
+    // Shard of a streamed INIT, gathered from its stripes
+    pub streamed: StreamedShard,
 
     pub terminated: bool
 }
@@ -27,6 +38,7 @@ impl RBCState{
         RBCState { 
             echos: HashMap::default(), 
             echo_root: None, 
+            reconstructing: HashSet::default(),
             
             readys: HashMap::default(), 
             votes: HashMap::default(),
@@ -37,6 +49,9 @@ impl RBCState{
             fragment: None, 
             message: None,
 
+            dealer_binding: None,
+            streamed: StreamedShard::default(),
+
             terminated: false
         }
     }
@@ -46,4 +61,13 @@ impl Default for RBCState {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[derive(Default)]
+pub struct StreamedShard {
+    pub count: Option<usize>,
+    pub stripes: HashMap<usize, Bytes>,
+    // INIT with the Merkle proof of the shard
+    pub init: Option<CTRBCMsg>,
+    pub assembled: bool,
+}
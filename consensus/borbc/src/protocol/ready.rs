@@ -1,8 +1,6 @@
-use consensus::reconstruct_data;
+use crypto::hash::Hash;
 
-use crate::protocol::init::construct_merkle_tree;
-
-use crate::{CTRBCMsg, ProtMsg};
+use crate::{CTRBCMsg, Phase, ProtMsg, Reconstructed};
 
 use crate::Context;
 impl Context {
@@ -20,7 +18,7 @@ impl Context {
 
         // Hashes on large messages are very expensive. Do as much as you can to avoid recomputing them.
         if !echo_senders.contains_key(&msg.origin) {
-            if !msg.verify_mr_proof(&self.hash_context){
+            if !msg.verify_mr_proof(self.hash, msg.origin, self.num_nodes){
                 log::error!(
                     "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                     msg.origin,
@@ -32,7 +30,7 @@ impl Context {
         else{
             let msg_echo = echo_senders.get(&msg.origin).unwrap().clone();
             if msg_echo != msg.shard {
-                if !msg.verify_mr_proof(&self.hash_context){
+                if !msg.verify_mr_proof(self.hash, msg.origin, self.num_nodes){
                     log::error!(
                         "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                         msg.origin,
@@ -61,61 +59,14 @@ impl Context {
             let mut shards: Vec<Option<Vec<u8>>> = Vec::new();
             for rep in 0..self.num_nodes {
                 if ready_senders.contains_key(&rep) {
-                    shards.push(Some(ready_senders.get(&rep).unwrap().clone()));
+                    shards.push(Some(ready_senders.get(&rep).unwrap().to_vec()));
                 } else {
                     shards.push(None);
                 }
             }
 
-            let status = reconstruct_data(&mut shards, self.num_faults + 1, 2 * self.num_faults);
-
-            if status.is_err() {
-                log::error!(
-                    "FATAL: Error in Lagrange interpolation {}",
-                    status.err().unwrap()
-                );
-                return;
-            }
-
-            let shards: Vec<Vec<u8>> = shards.into_iter().map(|opt| opt.unwrap()).collect();
-
-            let mut message = Vec::new();
-            for i in 0..self.num_faults + 1 {
-                message.extend(shards.get(i).clone().unwrap());
-            }
-            let my_share: Vec<u8> = shards[self.myid].clone();
-            let my_proof;
-            if rbc_context.fragment.is_some() && rbc_context.fragment.clone().unwrap().1.root() == root {
-                my_proof = rbc_context.fragment.clone().unwrap().1;
-            } else {
-                my_proof = construct_merkle_tree(shards.clone(), &self.hash_context).gen_proof(self.myid);
-            }
-            
-            // if merkle_tree.root() == root {
-                // Ready phase is completed. Save our share for later purposes and quick access.
-                rbc_context.fragment = Some((my_share.clone(), my_proof.clone()));
-
-                rbc_context.message = Some(message);
-
-                // Insert own ready share
-                rbc_context
-                    .readys
-                    .get_mut(&root)
-                    .unwrap()
-                    .insert(self.myid, my_share.clone());
-                // Send ready message
-                let ctrbc_msg = CTRBCMsg {
-                    shard: my_share,
-                    mp: my_proof,
-                    origin: self.myid,
-                };
-
-                let ready_msg = ProtMsg::Ready(ctrbc_msg.clone(), instance_id);
-
-                if !self.crash {
-                    self.broadcast(ready_msg).await;
-                }
-            // }
+            // Interpolation continues in finish_ready once the blocking pool is done
+            self.start_reconstruction(instance_id, root, Phase::Ready, shards);
         } 
         else if size == 2 * self.num_faults + 1 && !rbc_context.terminated {
             
@@ -127,39 +78,39 @@ impl Context {
                         let msg = rbc_context.message.clone().unwrap();
                         rbc_context.terminated = true;
                         self.terminate(instance_id,msg).await;
-                        // // Let echo.rs shared code handle reconstruction & termination (to avoid duplication)
-                        // // Reuse the same reconstruct block you already have (or factor into a helper).
-                        // let senders = echo_senders.clone();
-                        // let mut shards: Vec<Option<Vec<u8>>> =
-                        //     (0..self.num_nodes).map(|rep| senders.get(&rep).cloned()).collect();
-
-                        // let status = reconstruct_data(&mut shards, self.num_faults + 1, 2 * self.num_faults);
-                        // if let Err(e) = status {
-                        //     log::error!("FATAL: Error in Lagrange interpolation {}", e);
-                        //     return;
-                        // }
-                        // let shards: Vec<Vec<u8>> = shards.into_iter().map(|opt| opt.unwrap()).collect();
-
-                        // let mut message = Vec::new();
-                        // for i in 0..self.num_faults + 1 { message.extend(shards.get(i).unwrap()); }
-                        // let my_share: Vec<u8> = shards[self.myid].clone();
-
-                        // let merkle_tree = construct_merkle_tree(shards, &self.hash_context);
-                        // if merkle_tree.root() == root {
-                        //     rbc_context.fragment = Some((my_share.clone(), merkle_tree.gen_proof(self.myid)));
-                        //     rbc_context.message = Some(message);
-                        //     rbc_context.terminated = true;
-                        //     let term_msg = rbc_context.message.clone().unwrap();
-                        //     if !self.crash {
-                        //         let out = CTRBCMsg { shard: my_share, mp: merkle_tree.gen_proof(self.myid), origin: self.myid };
-                        //         self.broadcast(ProtMsg::Ready(out, instance_id)).await;
-                        //     }
-                        //     self.terminate(instance_id,term_msg).await;
-                        // }
                     }
                 }
             }
         }
     }
-}
 
+    // Ready phase is completed. Save our share for later purposes and quick access.
+    pub async fn finish_ready(self: &mut Context, instance_id: usize, root: Hash, reconstructed: Reconstructed) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        // If the echo phase completed for this root in the meantime, we already sent ready for this message
+        if rbc_context.terminated || rbc_context.echo_root == Some(root) {
+            return;
+        }
+        rbc_context.fragment = Some((reconstructed.share.clone(), reconstructed.proof.clone()));
+        rbc_context.message = Some(reconstructed.message);
+
+        // Insert own ready share
+        rbc_context
+            .readys
+            .entry(root)
+            .or_default()
+            .insert(self.myid, reconstructed.share.clone());
+        // Send ready message
+        let ctrbc_msg = CTRBCMsg {
+            shard: reconstructed.share,
+            mp: reconstructed.proof,
+            origin: self.myid,
+        };
+
+        let ready_msg = ProtMsg::Ready(ctrbc_msg, instance_id);
+
+        if !self.crash {
+            self.broadcast(ready_msg).await;
+        }
+    }
+}
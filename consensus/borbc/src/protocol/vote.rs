@@ -13,7 +13,7 @@ impl Context {
         let echo_senders = rbc_context.echos.entry(root).or_default();
         let vote_senders = rbc_context.votes.entry(root).or_default();
         if !echo_senders.contains_key(&msg.origin) {
-            if !msg.verify_mr_proof(&self.hash_context){
+            if !msg.verify_mr_proof(self.hash, msg.origin, self.num_nodes){
                 log::error!(
                     "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                     msg.origin,
@@ -25,7 +25,7 @@ impl Context {
         else{
             let msg_echo = echo_senders.get(&msg.origin).unwrap().clone();
             if msg_echo != msg.shard {
-                if !msg.verify_mr_proof(&self.hash_context){
+                if !msg.verify_mr_proof(self.hash, msg.origin, self.num_nodes){
                     log::error!(
                         "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                         msg.origin,
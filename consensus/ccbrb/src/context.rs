@@ -1,7 +1,8 @@
 use std::{
     collections::{HashMap, HashSet},
     net::{SocketAddr, SocketAddrV4},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{anyhow, Result};
@@ -9,26 +10,26 @@ use config::Node;
 
 use fnv::FnvHashMap;
 use network::{
-    plaintcp::{CancelHandler, TcpReceiver, TcpReliableSender},
+    plaintcp::CancelHandler,
     Acknowledgement,
 };
 use tokio::sync::{
-    mpsc::{unbounded_channel, UnboundedReceiver},
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
     oneshot,
 };
 // use tokio_util::time::DelayQueue;
 use types::{Replica, SyncMsg, SyncState};
 
-use super::{Handler, ProtMsg, RBCState, SyncHandler};
+use super::{Computed, GatherState, ProtMsg, RBCState};
+use consensus::{inbox, listen_sync, new_codec, new_transport, sync_sender, Admission, Authenticator, BlameList, Compression, DeliveryMode, Envelope, ErasureCode, Evidence, HashKind, InboxReceiver, OrderedDelivery, RBCOptions, RBCService, Transport, Validator};
 
 use crate::Status;
-use types::WrapperMsg;
 
 pub struct Context {
     /// Networking context
-    pub net_send: TcpReliableSender<Replica, WrapperMsg<ProtMsg>, Acknowledgement>,
-    pub net_recv: UnboundedReceiver<WrapperMsg<ProtMsg>>,
-    pub sync_send: TcpReliableSender<Replica, SyncMsg, Acknowledgement>,
+    pub net_send: Box<dyn Transport>,
+    pub net_recv: InboxReceiver<Envelope>,
+    pub sync_send: Box<dyn Transport<SyncMsg>>,
     pub sync_recv: UnboundedReceiver<SyncMsg>,
     /// Data context
     pub num_nodes: usize,
@@ -38,8 +39,8 @@ pub struct Context {
     pub byz: bool,
     pub crash: bool,
 
-    /// Secret Key map
-    pub sec_key_map: HashMap<Replica, Vec<u8>>,
+    /// Tags outgoing messages and checks incoming ones with MACs or signatures
+    pub auth: Authenticator,
 
     /// Cancel Handlers
     pub cancel_handlers: HashMap<u64, Vec<CancelHandler<Acknowledgement>>>,
@@ -48,48 +49,111 @@ pub struct Context {
     pub rbc_context: HashMap<usize, RBCState>,
     pub max_id: usize,
 
+    // Hands terminated instances to the application in the configured order
+    pub delivery: OrderedDelivery,
+
+    // Set when this context runs as an RBC service under another protocol
+    pub out_send: Option<UnboundedSender<(usize, Vec<u8>)>>,
+    pub blame_send: Option<UnboundedSender<(usize, Evidence)>>,
+
+    // Application validity predicate on broadcast payloads
+    pub validator: Validator,
+    // Frames the payloads this node deals, compressed if configured
+    pub compression: Compression,
+    pub admission: Admission<Envelope>,
+    // Erasure code of the dispersed shards
+    pub codec: Arc<dyn ErasureCode>,
+    // Hash function of shards and payloads
+    pub hash: HashKind,
+    // Erasure code of the shard digest vector
+    pub hash_codec: Arc<dyn ErasureCode>,
+    // Encoding, interpolation and shard hashing run on the blocking pool and report back here
+    pub compute_send: UnboundedSender<Computed>,
+    compute_recv: UnboundedReceiver<Computed>,
+
+    // Dealers blamed in each instance, with the evidence against them
+    pub blame_list: BlameList,
+    // INIT being handled, kept as evidence against its dealer
+    pub init_envelope: Option<Envelope>,
+
     pub term_instances: HashSet<usize>,
     pub sent_term: bool,
+
+    // Run Gather over the delivered instances and report its output instead of waiting for all n
+    pub gather: bool,
+    pub gather_state: GatherState,
 }
 
 impl Context {
-    pub fn spawn(config: Node, message: Vec<u8>, byz: bool, crash: bool) -> anyhow::Result<oneshot::Sender<()>> {
-        let mut consensus_addrs: FnvHashMap<Replica, SocketAddr> = FnvHashMap::default();
-        for (replica, address) in config.net_map.iter() {
-            let address: SocketAddr = address.parse().expect("Unable to parse address");
-            consensus_addrs.insert(*replica, SocketAddr::from(address.clone()));
-        }
-        let my_port = consensus_addrs.get(&config.id).unwrap();
-        let my_address = to_socket_address("0.0.0.0", my_port.port());
+    pub fn spawn(
+        config: Node,
+        message: Vec<u8>,
+        options: RBCOptions,
+        gather: bool,
+    ) -> anyhow::Result<oneshot::Sender<()>> {
+        Self::spawn_with(config, message, options, gather, None)
+    }
+
+    // Run as the dissemination primitive of another protocol in this process, which talks to the syncer itself
+    pub fn spawn_service(
+        config: Node,
+        service: RBCService,
+        options: RBCOptions,
+    ) -> anyhow::Result<oneshot::Sender<()>> {
+        let options = RBCOptions {
+            delivery_mode: DeliveryMode::Unordered,
+            ..options
+        };
+        Self::spawn_with(config, Vec::new(), options, false, Some(service))
+    }
+
+    fn spawn_with(
+        config: Node,
+        message: Vec<u8>,
+        options: RBCOptions,
+        gather: bool,
+        service: Option<RBCService>,
+    ) -> anyhow::Result<oneshot::Sender<()>> {
+        let RBCOptions {
+            byz,
+            crash,
+            delivery_mode,
+            codec,
+            hash,
+            auth,
+            network,
+            validator,
+        } = options;
         let mut syncer_map: FnvHashMap<Replica, SocketAddr> = FnvHashMap::default();
         syncer_map.insert(0, config.client_addr);
 
+        let auth = Authenticator::new(auth, &config, network.batch.is_some()).map_err(anyhow::Error::msg)?;
         // Setup networking
-        let (tx_net_to_consensus, rx_net_to_consensus) = unbounded_channel();
-        TcpReceiver::<Acknowledgement, WrapperMsg<ProtMsg>, _>::spawn(
-            my_address,
-            Handler::new(tx_net_to_consensus),
-        );
+        let (tx_net_to_consensus, rx_net_to_consensus) = inbox(network.inbox_capacity);
+        let consensus_net = new_transport(&network, &config, &auth, tx_net_to_consensus).map_err(anyhow::Error::msg)?;
 
         let syncer_listen_port = config.client_port;
         let syncer_l_address = to_socket_address("0.0.0.0", syncer_listen_port);
 
-        // The server must listen to the client's messages on some port that is not being used to listen to other servers
-        let (tx_net_to_client, rx_net_from_client) = unbounded_channel();
-        TcpReceiver::<Acknowledgement, SyncMsg, _>::spawn(
-            syncer_l_address,
-            SyncHandler::new(tx_net_to_client),
-        );
-
-        let consensus_net =
-            TcpReliableSender::<Replica, WrapperMsg<ProtMsg>, Acknowledgement>::with_peers(
-                consensus_addrs.clone(),
-            );
-        let sync_net =
-            TcpReliableSender::<Replica, SyncMsg, Acknowledgement>::with_peers(syncer_map);
+        let (rx_net_from_client, out_send, blame_send) = match service {
+            Some(service) => (service.req_recv, Some(service.out_send), Some(service.blame_send)),
+            None => {
+                // The server must listen to the client's messages on some port that is not being used to listen to other servers
+                let (tx_net_to_client, rx_net_from_client) = unbounded_channel();
+                listen_sync(&network, syncer_l_address, tx_net_to_client).map_err(anyhow::Error::msg)?;
+                (rx_net_from_client, None, None)
+            }
+        };
+
+        let sync_net = sync_sender(&network, syncer_map);
         let (exit_tx, exit_rx) = oneshot::channel();
         let threshold: usize = 10000;
         let rbc_start_id = threshold * config.id;
+        let hash_codec = new_codec(codec, config.num_faults, config.num_nodes - config.num_faults).map_err(anyhow::Error::msg)?;
+        let codec = new_codec(codec, config.num_faults + 1, config.num_nodes - config.num_faults - 1).map_err(anyhow::Error::msg)?;
+        let (compute_send, compute_recv) = unbounded_channel();
+        let compression = Compression::new(network.compression, network.max_payload);
+        let admission = Admission::new(network.admission, config.num_nodes, threshold);
         tokio::spawn(async move {
             let mut c = Context {
                 net_send: consensus_net,
@@ -97,7 +161,7 @@ impl Context {
                 sync_send: sync_net,
                 sync_recv: rx_net_from_client,
                 num_nodes: config.num_nodes,
-                sec_key_map: HashMap::default(),
+                auth,
                 myid: config.id,
                 byz: byz & (config.id < config.num_faults),
                 crash: crash & (config.id < config.num_faults),
@@ -107,15 +171,27 @@ impl Context {
                 inp_message: message,
                 rbc_context: HashMap::default(),
                 max_id: rbc_start_id,
+                delivery: OrderedDelivery::new(delivery_mode, config.num_nodes, threshold),
+                out_send,
+                blame_send,
+                validator,
+                compression,
+                admission,
+                codec,
+                hash,
+                hash_codec,
+                compute_send,
+                compute_recv,
+
+                blame_list: BlameList::default(),
+                init_envelope: None,
 
                 term_instances: HashSet::new(),
                 sent_term: false,
-            };
 
-            // Populate secret keys from config
-            for (id, sk_data) in config.sk_map.clone() {
-                c.sec_key_map.insert(id, sk_data.clone());
-            }
+                gather,
+                gather_state: GatherState::default(),
+            };
 
             // Run the consensus context
             if let Err(e) = c.run().await {
@@ -127,11 +203,11 @@ impl Context {
     }
 
     pub async fn broadcast(&mut self, protmsg: ProtMsg) {
-        let sec_key_map = self.sec_key_map.clone();
         // Sleep to simulate network delay
         // sleep(Duration::from_millis(50)).await;
 
-        for (replica, sec_key) in sec_key_map.into_iter() {
+        let sealed = self.auth.seal(&protmsg);
+        for replica in 0..self.num_nodes {
             if self.byz && replica != self.myid {
                 let mut byz_msg = protmsg.clone();
 
@@ -144,15 +220,15 @@ impl Context {
                 }
                  
 
-                let wrapper_msg = WrapperMsg::new(byz_msg, self.myid, &sec_key.as_slice());
-                let cancel_handler = self.net_send.send(replica, wrapper_msg).await;
+                let envelope = self.auth.wrap(&byz_msg, replica);
+                let cancel_handler = self.net_send.send(replica, envelope).await;
                 self.add_cancel_handler(cancel_handler);
                 continue;
             }
             if replica != self.myid {
-                let wrapper_msg = WrapperMsg::new(protmsg.clone(), self.myid, &sec_key.as_slice());
+                let envelope = self.auth.envelope(&sealed, replica);
                 let cancel_handler: CancelHandler<Acknowledgement> =
-                    self.net_send.send(replica, wrapper_msg).await;
+                    self.net_send.send(replica, envelope).await;
                 self.add_cancel_handler(cancel_handler);
             }
         }
@@ -162,28 +238,31 @@ impl Context {
         self.cancel_handlers.entry(0).or_default().push(canc);
     }
 
-    pub async fn send(&mut self, replica: Replica, wrapper_msg: WrapperMsg<ProtMsg>) {
+    pub async fn send(&mut self, replica: Replica, envelope: Envelope) {
         let cancel_handler: CancelHandler<Acknowledgement> =
-            self.net_send.send(replica, wrapper_msg).await;
+            self.net_send.send(replica, envelope).await;
         self.add_cancel_handler(cancel_handler);
     }
 
     pub async fn run(&mut self) -> Result<()> {
         // The process starts listening to messages in this process.
-        // First, the node sends an alive message
-        let cancel_handler = self
-            .sync_send
-            .send(
-                0,
-                SyncMsg {
-                    sender: self.myid,
-                    state: SyncState::ALIVE,
-                    value: "".to_string().into_bytes(),
-                },
-            )
-            .await;
-        self.add_cancel_handler(cancel_handler);
+        // First, the node sends an alive message. As a service, the upper layer talks to the syncer instead.
+        if self.out_send.is_none() {
+            let cancel_handler = self
+                .sync_send
+                .send(
+                    0,
+                    SyncMsg {
+                        sender: self.myid,
+                        state: SyncState::ALIVE,
+                        value: "".to_string().into_bytes(),
+                    },
+                )
+                .await;
+            self.add_cancel_handler(cancel_handler);
+        }
         loop {
+            let refill = self.admission.next_refill();
             tokio::select! {
                 // Receive exit handlers
                 exit_val = &mut self.exit_rx => {
@@ -191,13 +270,24 @@ impl Context {
                     log::info!("Termination signal received by the server. Exiting.");
                     break
                 },
+                // Peers held back by the rate limit have tokens again
+                _ = tokio::time::sleep_until(refill.unwrap_or_else(Instant::now).into()), if refill.is_some() => {},
                 msg = self.net_recv.recv() => {
                     // Received messages are processed here
                     // log::debug!("Got a consensus message from the network: {:?}", msg);
                     let msg = msg.ok_or_else(||
                         anyhow!("Networking layer has closed")
                     )?;
-                    self.process_msg(msg).await;
+                    for envelope in self.auth.unbatch(msg) {
+                        self.process_msg(envelope).await;
+                    }
+                },
+                computed = self.compute_recv.recv() => {
+                    // Results of offloaded work continue the protocol step that started it
+                    let computed = computed.ok_or_else(||
+                        anyhow!("Blocking pool channel has closed")
+                    )?;
+                    self.handle_computed(computed).await;
                 },
                 sync_msg = self.sync_recv.recv() =>{
                     let sync_msg = sync_msg.ok_or_else(||
@@ -210,12 +300,20 @@ impl Context {
                                 .unwrap()
                                 .as_millis());
                             // Dealer sends message to everybody. <M, init>
-                                let rbc_inst_id = self.max_id + 1;
+                                // Our sequence numbers must stay below the threshold or our ids would run into the next dealer's
+                                let rbc_inst_id = match self.delivery.next_instance(self.max_id) {
+                                    Some(rbc_inst_id) => rbc_inst_id,
+                                    None => {
+                                        log::error!("Node {} used up the ids of its broadcasts, dropping the payload", self.myid);
+                                        continue;
+                                    }
+                                };
                                 self.max_id = rbc_inst_id;
                                 let rbc_context = self.rbc_context.entry(rbc_inst_id).or_default();
                                 let status = &rbc_context.status;
                                 assert!(*status == Status::WAITING, "Status is not waiting during initialization");
-                                self.start_init(sync_msg.value, rbc_inst_id).await;
+                                let payload = self.compression.compress(&self.delivery.wrap(sync_msg.value));
+                                self.start_init(payload, rbc_inst_id).await;
 
                             // wait for messages
                         },
@@ -232,6 +330,10 @@ impl Context {
                     }
                 },
             };
+            // Messages held back by the admission that may pass now
+            for envelope in self.admission.released() {
+                self.process_msg(envelope).await;
+            }
         }
         Ok(())
     }
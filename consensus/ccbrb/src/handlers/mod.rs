@@ -1,5 +0,0 @@
-mod handler;
-pub use handler::*;
-
-mod sync_handler;
-pub use sync_handler::*;
\ No newline at end of file
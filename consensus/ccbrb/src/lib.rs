@@ -4,10 +4,7 @@ pub use context::*;
 mod process;
 
 mod msg;
-use msg::*;
-
-mod handlers;
-pub use handlers::*;
+pub use msg::*;
 
 mod protocol;
 pub use protocol::*;
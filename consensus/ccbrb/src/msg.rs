@@ -1,5 +1,6 @@
 use std::fmt::Debug;
 
+use consensus::Evidence;
 use crypto::hash::Hash;
 use reed_solomon_rs::fec::fec::*;
 use serde::{Deserialize, Serialize};
@@ -30,9 +31,20 @@ pub struct ReadyMsg {
     pub origin: Replica,
 }
 
+// Set of RBC instance ids delivered by (GATHER1) or reported to (GATHER2) the origin
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GatherMsg {
+    pub ids: Vec<usize>,
+    pub origin: Replica,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum ProtMsg {
     Init(SendMsg, Replica),
     Echo(EchoMsg, Replica),
     Ready(ReadyMsg, Replica),
+    Gather1(GatherMsg, Replica),
+    Gather2(GatherMsg, Replica),
+    // Evidence against the dealer of an instance
+    Blame(Evidence, usize),
 }
@@ -0,0 +1,40 @@
+use crypto::hash::Hash;
+
+use crate::{Context, Status};
+
+// Results of the work offloaded to the blocking pool, handed back to the event loop
+pub enum Computed {
+    // Shards d of a broadcast started by this node and their hashes D
+    Dispersal {
+        instance_id: usize,
+        result: Result<(Vec<Vec<u8>>, Vec<Hash>), String>,
+    },
+    // M interpolated from the data shares, and whether its re-encoding matches D′
+    Reconstruction {
+        instance_id: usize,
+        result: Result<(Vec<u8>, bool), String>,
+    },
+}
+
+impl Context {
+    pub async fn handle_computed(&mut self, computed: Computed) {
+        match computed {
+            Computed::Dispersal { instance_id, result } => match result {
+                Ok((shards, d_hashes)) => self.send_init(shards, d_hashes, instance_id).await,
+                Err(e) => log::error!("FATAL: Error in encoding for instance id {}: {}", instance_id, e),
+            },
+            Computed::Reconstruction { instance_id, result } => {
+                let rbc_context = self.rbc_context.entry(instance_id).or_default();
+                rbc_context.reconstructing = false;
+                if rbc_context.status == Status::TERMINATED {
+                    return;
+                }
+                match result {
+                    // A later READY retries the reconstruction
+                    Err(e) => log::warn!("Reconstruction of the data shards failed: {}", e),
+                    Ok((output_message, all_match)) => self.finish_reconstruction(instance_id, output_message, all_match).await,
+                }
+            }
+        }
+    }
+}
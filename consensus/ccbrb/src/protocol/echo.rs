@@ -3,55 +3,33 @@ use crate::msg::{EchoMsg, SendMsg};
 use crate::Status;
 use crate::{Context, ProtMsg};
 use bincode;
-use crypto::hash::{do_hash};
 use network::{plaintcp::CancelHandler, Acknowledgement};
 use reed_solomon_rs::fec::fec::*;
-use types::WrapperMsg;
 
 impl Context {
     pub async fn start_echo(&mut self, msg: SendMsg, instance_id: usize) {
         let d_hashes = msg.d_hashes.clone(); // D = [H(d1), ..., H(dn)]
-        let c = do_hash(&bincode::serialize(&d_hashes).unwrap()); // c = H(D)
-                                                                  // log::info!(
-                                                                  //     "Starting ECHO for instance_id {} with c: {:?}, d_hashes: {:?}",
-                                                                  //     instance_id,
-                                                                  //     c,
-                                                                  //     d_hashes
-                                                                  // );
-
-        let f = match FEC::new(self.num_faults, self.num_nodes) {
-            Ok(f) => f,
+        let c = self.hash.digest(&bincode::serialize(&d_hashes).unwrap()); // c = H(D)
+                                                                           // log::info!(
+                                                                           //     "Starting ECHO for instance_id {} with c: {:?}, d_hashes: {:?}",
+                                                                           //     instance_id,
+                                                                           //     c,
+                                                                           //     d_hashes
+                                                                           // );
+
+        assert!(d_hashes.len() > 0, "Message content is empty");
+        let serialized_hashes = bincode::serialize(&d_hashes).unwrap();
+        let mut pi: Vec<Share> = match self.hash_codec.encode(&serialized_hashes) {
+            Ok(shards) => shards
+                .into_iter()
+                .enumerate()
+                .map(|(number, data)| Share { number, data })
+                .collect(),
             Err(e) => {
-                log::info!("FEC initialization failed with error: {:?}", e);
+                log::info!("Encoding failed with error: {:?}", e);
                 return;
             }
         };
-
-        let mut pi: Vec<Share> = vec![
-            Share {
-                number: 0,
-                data: vec![]
-            };
-            self.num_nodes
-        ];
-        {
-            let output = |s: Share| {
-                pi[s.number] = s.clone(); // deep copy
-            };
-            // log::info!(
-            //     "d_hashes before encoding: {:?}, instance_id: {}",
-            //     d_hashes,
-            //     instance_id
-            // );
-            assert!(d_hashes.len() > 0, "Message content is empty");
-            // let encoded: Vec<u8> = d_hashes.iter().flatten().copied().collect();
-            let serialized_hashes = bincode::serialize(&d_hashes).unwrap();
-
-            if let Err(e) = f.encode(&serialized_hashes, output) {
-                log::info!("Encoding failed with error: {:?}", e);
-            }
-            //f.encode(&msg_content, output)?;
-        }
         if self.byz {
             // if byzantine, set all shares to empty, but make sure to keep the size consistent, so fill with 0
             for i in 0..self.num_nodes {
@@ -102,8 +80,7 @@ impl Context {
                     continue;
                 }
 
-                let sec_key = &self.sec_key_map[&replica];
-                let wrapped = WrapperMsg::new(proto_msg.clone(), self.myid, sec_key);
+                let wrapped = self.auth.wrap(&proto_msg, replica);
                 let cancel_handler: CancelHandler<Acknowledgement> =
                     self.net_send.send(replica, wrapped).await;
                 self.add_cancel_handler(cancel_handler);
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use consensus::SyncReport;
+use types::{SyncMsg, SyncState};
+
+use crate::msg::{GatherMsg, ProtMsg};
+use crate::Context;
+
+/*
+Gather over the n parallel RBC instances:
+1. On delivering n-t instances, send <GATHER1, S> with the set S of delivered instance ids
+2. Accept <GATHER1, S_j> once every instance in S_j is delivered. On n-t accepted sets,
+   send <GATHER2, T> with T the union of the accepted sets
+3. Accept <GATHER2, T_j> once every instance in T_j is delivered. On n-t accepted sets,
+   output the union of the accepted sets
+All honest outputs contain a common core of at least n-t instances.
+*/
+
+impl Context {
+    pub async fn handle_gather1(&mut self, msg: GatherMsg) {
+        if !self.valid_gather_set(&msg) {
+            log::warn!("Invalid Gather1 set sent by node {}, ignoring", msg.origin);
+            return;
+        }
+        let origin = msg.origin;
+        if self.gather_state.gather1_pending.contains_key(&origin)
+            || self.gather_state.gather1_accepted.contains_key(&origin)
+        {
+            return;
+        }
+        self.gather_state
+            .gather1_pending
+            .insert(origin, msg.ids.into_iter().collect());
+        self.check_gather().await;
+    }
+
+    pub async fn handle_gather2(&mut self, msg: GatherMsg) {
+        if !self.valid_gather_set(&msg) {
+            log::warn!("Invalid Gather2 set sent by node {}, ignoring", msg.origin);
+            return;
+        }
+        let origin = msg.origin;
+        if self.gather_state.gather2_pending.contains_key(&origin)
+            || self.gather_state.gather2_accepted.contains_key(&origin)
+        {
+            return;
+        }
+        self.gather_state
+            .gather2_pending
+            .insert(origin, msg.ids.into_iter().collect());
+        self.check_gather().await;
+    }
+
+    fn valid_gather_set(&self, msg: &GatherMsg) -> bool {
+        let ids: HashSet<usize> = msg.ids.iter().copied().collect();
+        ids.len() == msg.ids.len()
+            && ids.len() >= self.num_nodes - self.num_faults
+            && ids.iter().all(|id| id / self.delivery.threshold < self.num_nodes)
+    }
+
+    // Invoke after every delivered instance and every received Gather message
+    pub async fn check_gather(&mut self) {
+        if !self.gather || self.gather_state.output.is_some() {
+            return;
+        }
+        let quorum = self.num_nodes - self.num_faults;
+
+        if !self.gather_state.sent_gather1 && self.term_instances.len() >= quorum {
+            self.gather_state.sent_gather1 = true;
+            let ids: HashSet<usize> = self.term_instances.clone();
+            self.gather_state.gather1_accepted.insert(self.myid, ids.clone());
+            log::info!("Delivered {} instances, sending Gather1", ids.len());
+            self.send_gather(ids, true).await;
+        }
+
+        self.gather_state.accept_pending(&self.term_instances);
+
+        if self.gather_state.sent_gather1
+            && !self.gather_state.sent_gather2
+            && self.gather_state.gather1_accepted.len() >= quorum
+        {
+            self.gather_state.sent_gather2 = true;
+            let mut ids: HashSet<usize> = HashSet::default();
+            for set in self.gather_state.gather1_accepted.values() {
+                ids.extend(set.iter().copied());
+            }
+            self.gather_state.gather2_accepted.insert(self.myid, ids.clone());
+            log::info!("Accepted {} Gather1 sets, sending Gather2", quorum);
+            self.send_gather(ids, false).await;
+        }
+
+        if self.gather_state.sent_gather2 && self.gather_state.gather2_accepted.len() >= quorum {
+            let mut output: HashSet<usize> = HashSet::default();
+            for set in self.gather_state.gather2_accepted.values() {
+                output.extend(set.iter().copied());
+            }
+            let mut sorted: Vec<usize> = output.iter().copied().collect();
+            sorted.sort();
+            log::info!("Gather terminated with output {:?}", sorted);
+            self.gather_state.output = Some(output);
+
+            if !self.sent_term {
+                self.sent_term = true;
+                // The payload is the syncer's RBCSyncMsg, which the Gather output and blame list follow
+                let report = SyncReport::Gather {
+                    gathered: sorted,
+                    blamed: self.blame_list.report(),
+                };
+                let value = report.append(self.gather_state.last_output.clone());
+                let cancel_handler = self
+                    .sync_send
+                    .send(
+                        0,
+                        SyncMsg {
+                            sender: self.myid,
+                            state: SyncState::COMPLETED,
+                            value,
+                        },
+                    )
+                    .await;
+                self.add_cancel_handler(cancel_handler);
+            }
+        }
+    }
+
+    async fn send_gather(&mut self, ids: HashSet<usize>, first: bool) {
+        if self.crash {
+            return;
+        }
+        let mut ids: Vec<usize> = ids.into_iter().collect();
+        ids.sort();
+        let gather_msg = GatherMsg {
+            ids,
+            origin: self.myid,
+        };
+        let protmsg = if first {
+            ProtMsg::Gather1(gather_msg, self.myid)
+        } else {
+            ProtMsg::Gather2(gather_msg, self.myid)
+        };
+        self.broadcast(protmsg).await;
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::{HashMap, HashSet};
+
+use types::Replica;
+
+pub struct GatherState {
+    pub sent_gather1: bool,
+    pub sent_gather2: bool,
+
+    // Sets received from each node, accepted once every instance in them was delivered locally
+    pub gather1_pending: HashMap<Replica, HashSet<usize>>,
+    pub gather1_accepted: HashMap<Replica, HashSet<usize>>,
+    pub gather2_pending: HashMap<Replica, HashSet<usize>>,
+    pub gather2_accepted: HashMap<Replica, HashSet<usize>>,
+
+    pub output: Option<HashSet<usize>>,
+    // Payload of the latest delivered instance, reported to the syncer along with the output
+    pub last_output: Vec<u8>,
+}
+
+impl GatherState {
+    pub fn new() -> GatherState {
+        GatherState {
+            sent_gather1: false,
+            sent_gather2: false,
+
+            gather1_pending: HashMap::default(),
+            gather1_accepted: HashMap::default(),
+            gather2_pending: HashMap::default(),
+            gather2_accepted: HashMap::default(),
+
+            output: None,
+            last_output: Vec::new(),
+        }
+    }
+
+    // Accept pending sets whose instances have all been delivered
+    pub fn accept_pending(&mut self, delivered: &HashSet<usize>) {
+        for (pending, accepted) in [
+            (&mut self.gather1_pending, &mut self.gather1_accepted),
+            (&mut self.gather2_pending, &mut self.gather2_accepted),
+        ] {
+            let ready: Vec<Replica> = pending
+                .iter()
+                .filter(|(_, ids)| ids.is_subset(delivered))
+                .map(|(rep, _)| *rep)
+                .collect();
+            for rep in ready {
+                let ids = pending.remove(&rep).unwrap();
+                accepted.insert(rep, ids);
+            }
+        }
+    }
+}
+
+impl Default for GatherState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,9 @@
 use crate::msg::SendMsg;
 use crate::Status;
-use crate::{Context, ProtMsg};
-use consensus::get_shards;
-use crypto::hash::do_hash;
+use crate::{Computed, Context, ProtMsg};
+use consensus::{hash_shards, offload, ErasureCode, HashKind};
+use crypto::hash::Hash;
 use reed_solomon_rs::fec::fec::Share;
-use types::WrapperMsg;
 
 impl Context {
     pub async fn start_init(&mut self, input_msg: Vec<u8>, instance_id: usize) {
@@ -18,28 +17,20 @@ impl Context {
         );
         rbc_context.status = Status::INIT;
 
-        let n = self.num_nodes;
-        let k = self.num_faults + 1;
-        // d
-        let shards = get_shards(input_msg.clone(), k, n - k);
-        assert_eq!(shards.len(), n);
-
-        // print input message and shards. input message and shards for instance_id:
-        // log::info!(
-        //     "INIT: Input message for instance_id {}: {:?}",
-        //     instance_id,
-        //     input_msg
-        // );
-        // log::info!("INIT: Shards for instance_id {}: {:?}", instance_id, shards);
-
-        // D
-        let d_hashes: Vec<_> = shards.iter().map(|s| do_hash(s)).collect();
-
-        // log::info!(
-        //     "INIT: D hashes for instance_id {}: {:?}",
-        //     instance_id,
-        //     d_hashes
-        // );
+        // The shards are dealt once, so the payload counts once
+        self.compression.account(&input_msg, 1);
+        // d and D. Encoding and hashing a large message takes a while, so do it off the event loop.
+        let codec = self.codec.clone();
+        let hash = self.hash;
+        offload(&self.compute_send, move || Computed::Dispersal {
+            instance_id,
+            result: disperse(codec.as_ref(), hash, &input_msg),
+        });
+    }
+
+    pub async fn send_init(&mut self, shards: Vec<Vec<u8>>, d_hashes: Vec<Hash>, instance_id: usize) {
+        assert_eq!(shards.len(), self.num_nodes);
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
 
         // Store our own share
         let my_share = Share {
@@ -58,7 +49,7 @@ impl Context {
         self.handle_init(my_msg.clone(), instance_id).await;
 
         // Send correct share to each replica
-        for (replica, sec_key) in self.sec_key_map.clone() {
+        for replica in 0..self.num_nodes {
             if replica == self.myid {
                 continue;
             }
@@ -85,24 +76,33 @@ impl Context {
             };
 
             let protmsg = ProtMsg::Init(send_msg, instance_id);
-            let wrapper = WrapperMsg::new(protmsg, self.myid, &sec_key);
+            let wrapper = self.auth.wrap(&protmsg, replica);
             let cancel_handler = self.net_send.send(replica, wrapper).await;
             self.add_cancel_handler(cancel_handler);
         }
     }
 
     pub async fn handle_init(&mut self, msg: SendMsg, instance_id: usize) {
-        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if msg.d_hashes.len() != self.num_nodes {
+            log::warn!("INIT of node {} carries {} shard digests, ignoring", msg.origin, msg.d_hashes.len());
+            self.blame_invalid_init(instance_id).await;
+            return;
+        }
 
-        assert_eq!(msg.d_hashes.len(), self.num_nodes);
+        // The INIT does not name its receiver, so a share of another position proves nothing against the dealer
+        if msg.d_j.number != self.myid {
+            log::warn!("INIT of node {} carries the share of node {}, ignoring", msg.origin, msg.d_j.number);
+            return;
+        }
 
         // H(di)
-        let computed_hash = do_hash(&msg.d_j.data);
+        let computed_hash = self.hash.digest(&msg.d_j.data);
         // Di
         let expected_hash = msg.d_hashes[self.myid];
 
         if computed_hash != expected_hash {
             log::info!("Hash mismatch in INIT: ignoring.");
+            self.blame_invalid_init(instance_id).await;
             // log::info!(
             //     "Computed hash: {:?}, Expected hashes: {:?}, instance_id: {}",
             //     computed_hash,
@@ -112,6 +112,7 @@ impl Context {
 
             return;
         }
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
         // let &mut status = &rbc_context.status;
         if (rbc_context.status == Status::INIT || rbc_context.status == Status::WAITING)
             && !self.crash
@@ -121,3 +122,10 @@ impl Context {
         }
     }
 }
+
+// d and D of the input message
+fn disperse(codec: &dyn ErasureCode, hash: HashKind, input_msg: &[u8]) -> Result<(Vec<Vec<u8>>, Vec<Hash>), String> {
+    let shards = codec.encode(input_msg)?;
+    let d_hashes = hash_shards(hash, &shards);
+    Ok((shards, d_hashes))
+}
@@ -4,5 +4,15 @@ mod echo;
 
 mod ready;
 
+mod gather;
+
+mod blame;
+
+mod compute;
+pub use compute::*;
+
 mod rbc_state;
 pub use rbc_state::*;
+
+mod gather_state;
+pub use gather_state::*;
@@ -1,3 +1,4 @@
+use consensus::{Envelope, OnlineDecoder};
 use crypto::hash::Hash;
 use reed_solomon_rs::fec::fec::*;
 use std::collections::{HashMap, HashSet};
@@ -26,11 +27,17 @@ pub struct RBCState {
     // CCBRB specific fields
     pub fragments_data: HashMap<(u64, Hash), Vec<Share>>,
     // pub fragments_hashes: HashMap<(u64, Hash), Vec<Vec<u8>>>,
-    pub fragments_hashes: HashMap<(u64, Hash), Vec<Share>>,
+    // Online error correction of D′ from the πᵢ in READY messages
+    pub fragments_hashes: HashMap<(u64, Hash), OnlineDecoder>,
+    // Set while M is being interpolated on the blocking pool
+    pub reconstructing: bool,
 
     pub e: usize,
     pub sent_ready: bool,       // needed to avoid sending the second READY multiple times
     pub sent_echo: HashSet<(u64, Hash, Vec<u8>)>, // no need because we're using STATUS
+
+    // First message of the dealer and the digest c it carries
+    pub dealer_binding: Option<(Hash, Envelope)>,
 }
 
 impl RBCState {
@@ -49,9 +56,12 @@ impl RBCState {
 
             fragments_data: HashMap::default(),
             fragments_hashes: HashMap::default(),
+            reconstructing: false,
             e: 0,
             sent_ready: false,
             sent_echo: HashSet::default(),
+
+            dealer_binding: None,
         }
     }
 
@@ -1,15 +1,14 @@
 use crate::{
     msg::{ProtMsg, ReadyMsg},
-    Context, Status,
+    Computed, Context, Status,
 };
 use bincode;
-use consensus::{reconstruct_data};
-use crypto::hash::{do_hash, Hash};
+use consensus::{hash_shards, offload, ErasureCode, HashKind, OnlineDecoder};
+use crypto::hash::Hash;
 
-use reed_solomon_rs::fec::fec::{Share, FEC};
+use reed_solomon_rs::fec::fec::Share;
 use std::collections::HashSet;
 use types::Replica;
-use types::WrapperMsg;
 
 impl Context {
     pub async fn start_ready(&mut self, c: Hash, pi_i: Share, instance_id: usize) {
@@ -29,13 +28,14 @@ impl Context {
         if self.crash {
             return;
         }
-        for (replica, sec_key) in self.sec_key_map.clone() {
+        let sealed = self.auth.seal(&proto);
+        for replica in 0..self.num_nodes {
             if replica == self.myid {
                 self.handle_ready(ready_msg.clone(), instance_id).await;
                 continue;
             }
 
-            let wrapper = WrapperMsg::new(proto.clone(), self.myid, &sec_key);
+            let wrapper = self.auth.envelope(&sealed, replica);
             let cancel_handler = self.net_send.send(replica, wrapper).await;
             self.add_cancel_handler(cancel_handler);
         }
@@ -48,6 +48,9 @@ impl Context {
         //     instance_id
         // );
         let mut cancel_handlers = vec![];
+        let hash_codec = self.hash_codec.clone();
+        let num_faults = self.num_faults;
+        let hash = self.hash;
 
         {
             let rbc_context = self.rbc_context.entry(instance_id).or_default();
@@ -80,11 +83,13 @@ impl Context {
                 return; // duplicate
             }
 
-            let hashes_entry = rbc_context
+            // πᵢ is indexed by its sender, which process_msg checks against the origin it names, so a faulty node cannot
+            // occupy the slot of an honest one
+            rbc_context
                 .fragments_hashes
                 .entry((instance_id as u64, msg.c))
-                .or_default();
-            hashes_entry.push(pi_i.clone());
+                .or_insert_with(|| OnlineDecoder::new(hash_codec.clone(), num_faults))
+                .add_share(msg.origin, pi_i.data.clone());
 
             log::debug!("About to process ready");
             //if (not yet sent ⟨𝑖𝑑, READY, 𝑐⟩ and received 𝑡 + 1 ⟨READY⟩ messages with the same 𝑐) then
@@ -115,8 +120,9 @@ impl Context {
                                     };
 
                                     let proto = ProtMsg::Ready(ready_msg.clone(), instance_id);
+                                    let sealed = self.auth.seal(&proto);
 
-                                    for (replica, sec_key) in self.sec_key_map.clone() {
+                                    for replica in 0..self.num_nodes {
                                         if replica == self.myid {
                                             let pi_i_serialized =
                                                 bincode::serialize(&pi_i_cloned.clone()).unwrap();
@@ -127,16 +133,17 @@ impl Context {
                                                 local_ready_map.entry(pi_i_serialized).or_default();
                                             local_senders.insert(self.myid);
 
-                                            let hashes_entry = rbc_context
+                                            rbc_context
                                                 .fragments_hashes
                                                 .entry((instance_id as u64, msg.c))
-                                                .or_default();
-                                            hashes_entry.push(pi_i_cloned.clone());
+                                                .or_insert_with(|| {
+                                                    OnlineDecoder::new(hash_codec.clone(), num_faults)
+                                                })
+                                                .add_share(self.myid, pi_i_cloned.data.clone());
                                             continue;
                                         }
 
-                                        let wrapper =
-                                            WrapperMsg::new(proto.clone(), self.myid, &sec_key);
+                                        let wrapper = self.auth.envelope(&sealed, replica);
                                         let cancel_handler =
                                             self.net_send.send(replica, wrapper).await;
                                         cancel_handlers.push(cancel_handler);
@@ -157,52 +164,19 @@ impl Context {
 
         let rbc_context = self.rbc_context.entry(instance_id).or_default();
 
-        // Online error correction
-        let hash_shares = rbc_context
+        // The running reconstruction delivers
+        if rbc_context.reconstructing {
+            return;
+        }
+
+        // Online error correction: the decoder retries with every new READY and tolerates t corrupted πᵢ
+        let d_prime = rbc_context
             .fragments_hashes
             .get(&(instance_id as u64, msg.c))
-            .cloned()
-            .unwrap_or_default();
-
-        // if 𝑓 𝑟𝑎𝑔𝑚𝑒𝑛𝑡𝑠ℎ𝑎𝑠ℎ𝑒𝑠 [(𝑖𝑑, 𝑐)] ≥ 2𝑡 + 1 then
-        if hash_shares.len() >= 2 * self.num_faults + 1 {
-            // log::info!(
-            //     "Received enough hash shares for instance_id: {}, c: {:?}, count: {}",
-            //     instance_id,
-            //     msg.c,
-            //     hash_shares.len()
-            // );
-            // check if the length of data of all shares is consistent
-            let data_length = hash_shares[0].data.len();
-            if !hash_shares
-                .iter()
-                .all(|share| share.data.len() == data_length)
-            {
-                log::warn!("Inconsistent data lengths in hash shares, cannot proceed");
-                return;
-            }
-
-            let f = FEC::new(self.num_faults, self.num_nodes).unwrap();
-            // log::info!(
-            //     "About to decode D′ for instance_id: {}, c: {:?}, hash_shares: {:?}",
-            //     instance_id,
-            //     msg.c,
-            //     hash_shares.clone()
-            // );
-
-            let mut d_prime = match f.decode(vec![], hash_shares.clone()) {
-                Ok(data) => data,
-                Err(_) => {
-                    log::warn!("Could not reconstruct D′ from hash shares, trying higher error tolerance later");
-                    return;
-                }
-            };
-
-            // while d prime is not empty and the last element is 95, pop the last element
-            while !d_prime.is_empty() && d_prime.last() == Some(&95) {
-                d_prime.pop();
-            }
+            .and_then(|decoder| decoder.output())
+            .cloned();
 
+        if let Some(d_prime) = d_prime {
             // log do hash d prime and msg.c
             // log::info!(
             //     "D′ decoded for instance_id: {}, c: {:?}, d_prime: {:?}",
@@ -218,7 +192,7 @@ impl Context {
             // );
 
             // if 𝐻(𝐷′) = 𝑐 then
-            if do_hash(&d_prime) == msg.c {
+            if hash.digest(&d_prime) == msg.c {
                 // log::info!(
                 //     "D′ matches c for instance_id: {}, c: {:?}",
                 //     instance_id,
@@ -247,7 +221,7 @@ impl Context {
                     .cloned()
                     .unwrap_or_default()
                     .into_iter()
-                    .filter(|share| valid_hashes.contains(&do_hash(&share.data)))
+                    .filter(|share| valid_hashes.contains(&hash.digest(&share.data)))
                     .collect::<Vec<_>>();
 
                 // wait for t+1 ⟨ECHO⟩ message where 𝐻(𝑑𝑗) ∈ 𝐷′and filter 𝑓𝑟𝑎𝑔𝑚𝑒𝑛𝑡𝑠𝑑𝑎𝑡𝑎[(𝑖𝑑, 𝑐)] accordingly
@@ -281,81 +255,16 @@ impl Context {
                 //     msg.c
                 // );
 
-                let n = self.num_nodes;
-                let k = self.num_faults + 1;
-
-                if reconstruct_data(&mut input_shares, k, n - k).is_err() {
-                    log::warn!("reconstruct_data failed");
-                    return;
-                }
-
-                // let mut reconstructed_data = vec![];
-                // for maybe in input_shares
-                //     .iter()
-                //     .take(self.num_nodes - self.num_faults - 1)
-                // {
-                //     if let Some(ref block) = maybe {
-                //         reconstructed_data.extend_from_slice(block);
-                //     }
-                // }
-
-                // let recomputed_shards = get_shards(&mut input_shares, k, n - k);
-                let recomputed_shards = input_shares
-                    .into_iter()
-                    .map(|maybe| {
-                        maybe.unwrap_or_else(|| {
-                            log::warn!("Missing data in input shares, returning empty shard");
-                            vec![]
-                        })
-                    })
-                    .collect::<Vec<_>>();
-
                 let d_prime_hashes: Vec<Hash> = bincode::deserialize(&d_prime).unwrap();
 
-                // log::info!(
-                //     "Recomputed shards: {:?} for instance_id: {}, c: {:?}",
-                //     recomputed_shards,
-                //     instance_id,
-                //     msg.c
-                // );
-
-                let recomputed_hashes: Vec<Hash> = recomputed_shards
-                    .iter()
-                    .map(|shard| do_hash(shard))
-                    .collect();
-
-                // log::info!(
-                //     "after D′ hashes: {:?}, recomputed hashes: {:?}",
-                //     d_prime_hashes,
-                //     recomputed_hashes
-                // );
-
-                let all_match = recomputed_hashes == d_prime_hashes;
-
-                if all_match {
-                    log::info!(
-                        " M is verified and consistent, delivering... for instance_id: {}",
-                        instance_id
-                    );
-                    rbc_context.status = Status::TERMINATED;
-                    let output_message = recomputed_shards.concat();
-                    log::info!(
-                        "RBC instance {} successfully terminated with output message of length {}",
-                        instance_id,
-                        output_message.len()
-                    );
-                    self.terminate(instance_id, output_message).await;
-                    return;
-                } else {
-                    log::warn!(" M failed verification against D′, discarding");
-                    // empty Vec<u8>
-                    // TEMPORARY ASSERT
-                    assert!(instance_id / 10000 < self.num_faults);
-                    rbc_context.status = Status::TERMINATED;
-                    let empty_output: Vec<u8> = vec![];
-                    self.terminate(instance_id, empty_output).await; // bottom
-                    return;
-                }
+                // Interpolating M and hashing its shards takes a while. Do it off the event loop and deliver in
+                // finish_reconstruction once done.
+                rbc_context.reconstructing = true;
+                let codec = self.codec.clone();
+                offload(&self.compute_send, move || Computed::Reconstruction {
+                    instance_id,
+                    result: reconstruct(codec.as_ref(), hash, input_shares, &d_prime_hashes),
+                });
             } else {
                 //log warn H(d prime ) != c
                 log::warn!(
@@ -366,4 +275,45 @@ impl Context {
             }
         }
     }
+
+    // Deliver M if it is consistent with D′, and ⊥ otherwise
+    pub async fn finish_reconstruction(&mut self, instance_id: usize, output_message: Vec<u8>, all_match: bool) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if all_match {
+            log::info!(
+                " M is verified and consistent, delivering... for instance_id: {}",
+                instance_id
+            );
+            rbc_context.status = Status::TERMINATED;
+            log::info!(
+                "RBC instance {} successfully terminated with output message of length {}",
+                instance_id,
+                output_message.len()
+            );
+            self.terminate(instance_id, output_message).await;
+        } else {
+            log::warn!(" M failed verification against D′, discarding");
+            // empty Vec<u8>
+            // TEMPORARY ASSERT
+            assert!(instance_id / 10000 < self.num_faults);
+            rbc_context.status = Status::TERMINATED;
+            let empty_output: Vec<u8> = vec![];
+            self.terminate(instance_id, empty_output).await; // bottom
+        }
+    }
+}
+
+// M and whether the hashes of its re-encoded shards match D′
+fn reconstruct(
+    codec: &dyn ErasureCode,
+    hash: HashKind,
+    mut input_shares: Vec<Option<Vec<u8>>>,
+    d_prime_hashes: &[Hash],
+) -> Result<(Vec<u8>, bool), String> {
+    let output_message = codec.reconstruct(&mut input_shares)?;
+    // Reconstruction leaves the present shares as they came, so only shards encoded from M again tell whether D′
+    // commits to M rather than to shares that encode no single message
+    let recomputed_shards = codec.encode(&output_message)?;
+    let recomputed_hashes = hash_shards(hash, &recomputed_shards);
+    Ok((output_message, recomputed_hashes == d_prime_hashes))
 }
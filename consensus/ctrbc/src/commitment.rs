@@ -0,0 +1,150 @@
+use consensus::{hash_shards, HashKind, MerkleProof, MerkleTree};
+use crypto::hash::{do_hash, Hash};
+use serde::{Deserialize, Serialize};
+
+use crate::KzgSetup;
+
+// Commitment schemes binding the shards of a broadcast
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CommitmentKind {
+    // Merkle tree over the shard digests, with log n hashes per opening
+    Merkle,
+    // KZG commitment to the shard digests, with one G1 element per opening
+    Kzg,
+}
+
+impl CommitmentKind {
+    pub fn from_flag(commitment: &str) -> Option<CommitmentKind> {
+        match commitment {
+            "merkle" => Some(CommitmentKind::Merkle),
+            "kzg" => Some(CommitmentKind::Kzg),
+            _ => None,
+        }
+    }
+}
+
+// Opening of a single shard against the commitment of the broadcast
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ShardProof {
+    Merkle(MerkleProof),
+    Kzg {
+        commitment: Vec<u8>,
+        index: usize,
+        opening: Vec<u8>,
+    },
+}
+
+impl ShardProof {
+    // Identifies the committed shards. Shards opened against the same root belong to the same encoding.
+    pub fn root(&self) -> Hash {
+        match self {
+            ShardProof::Merkle(proof) => proof.root(),
+            ShardProof::Kzg { commitment, .. } => do_hash(commitment),
+        }
+    }
+
+    // Position of the shard the proof opens
+    pub fn index(&self) -> usize {
+        match self {
+            ShardProof::Merkle(proof) => proof.index(),
+            ShardProof::Kzg { index, .. } => *index,
+        }
+    }
+}
+
+// Commitment to all shards of a broadcast, from which the dealer hands out openings
+pub enum ShardCommitment {
+    Merkle(MerkleTree),
+    Kzg { commitment: Vec<u8>, openings: Vec<Vec<u8>> },
+}
+
+impl ShardCommitment {
+    pub fn gen_proof(&self, index: usize) -> ShardProof {
+        match self {
+            ShardCommitment::Merkle(tree) => ShardProof::Merkle(tree.gen_proof(index)),
+            ShardCommitment::Kzg { commitment, openings } => ShardProof::Kzg {
+                commitment: commitment.clone(),
+                index,
+                opening: openings[index].clone(),
+            },
+        }
+    }
+}
+
+// Commits to shards and verifies openings with the scheme of this run
+pub struct Committer {
+    hash: HashKind,
+    // Shards per commitment, one for each node
+    num_nodes: usize,
+    kzg: Option<KzgSetup>,
+}
+
+impl Committer {
+    pub fn new(kind: CommitmentKind, hash: HashKind, num_nodes: usize) -> Committer {
+        Committer {
+            hash,
+            num_nodes,
+            kzg: match kind {
+                CommitmentKind::Merkle => None,
+                CommitmentKind::Kzg => Some(KzgSetup::test_setup(num_nodes)),
+            },
+        }
+    }
+
+    pub fn commit(&self, shards: &[Vec<u8>]) -> ShardCommitment {
+        self.commit_digests(hash_shards(self.hash, shards))
+    }
+
+    // Commit to shards hashed beforehand, as they were streamed
+    pub fn commit_digests(&self, digests: Vec<Hash>) -> ShardCommitment {
+        match &self.kzg {
+            None => ShardCommitment::Merkle(MerkleTree::new(digests, self.hash)),
+            Some(kzg) => {
+                let (commitment, openings) = kzg.commit(&digests);
+                ShardCommitment::Kzg { commitment, openings }
+            }
+        }
+    }
+
+    // Opening of one shard only, which saves computing the others under KZG
+    pub fn prove(&self, shards: &[Vec<u8>], index: usize) -> ShardProof {
+        self.prove_digests(hash_shards(self.hash, shards), index)
+    }
+
+    // Opening of one shard against the commitment to shards hashed beforehand
+    pub fn prove_digests(&self, digests: Vec<Hash>, index: usize) -> ShardProof {
+        match &self.kzg {
+            None => self.commit_digests(digests).gen_proof(index),
+            Some(kzg) => {
+                let (commitment, opening) = kzg.commit_and_open(&digests, index);
+                ShardProof::Kzg {
+                    commitment,
+                    index,
+                    opening,
+                }
+            }
+        }
+    }
+
+    // Whether the proof opens the shard at the given position. A valid opening at another position would let a node
+    // pass off someone else's shard as its own.
+    pub fn verify(&self, shard: &[u8], proof: &ShardProof, index: usize) -> bool {
+        if proof.index() != index {
+            return false;
+        }
+        let digest = self.hash.digest(shard);
+        match (proof, &self.kzg) {
+            (ShardProof::Merkle(proof), None) => digest == proof.item() && proof.validate(self.hash, self.num_nodes),
+            (
+                ShardProof::Kzg {
+                    commitment,
+                    index,
+                    opening,
+                },
+                Some(kzg),
+            ) => kzg.verify(commitment, *index, &digest, opening),
+            // Openings of the other scheme are not valid in this run
+            _ => false,
+        }
+    }
+}
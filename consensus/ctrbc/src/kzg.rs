@@ -0,0 +1,184 @@
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup, Group, VariableBaseMSM};
+use ark_ff::{PrimeField, Zero};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{
+    rand::{rngs::StdRng, SeedableRng},
+    UniformRand,
+};
+use crypto::hash::Hash;
+use rayon::prelude::*;
+
+// Seed of the trapdoor of the test setup. Everyone can derive it, so the setup is only fit for benchmarks.
+const TEST_SETUP_SEED: u64 = 0x6b7a67;
+
+// KZG commitments over BLS12-381 to a vector of digests, one per node. The vector is interpolated over a radix-2
+// domain, and the opening at position i proves the evaluation at the i-th root of unity with a single G1 element.
+pub struct KzgSetup {
+    domain: Radix2EvaluationDomain<Fr>,
+    // [τ^k]G1 for k below the domain size
+    powers: Vec<G1Affine>,
+    g2: G2Affine,
+    tau_g2: G2Affine,
+}
+
+impl KzgSetup {
+    // Test setup for vectors of up to num_nodes entries, with a trapdoor derived from a fixed seed
+    pub fn test_setup(num_nodes: usize) -> KzgSetup {
+        let domain = Radix2EvaluationDomain::<Fr>::new(num_nodes).expect("No radix-2 domain for this many nodes");
+        let mut rng = StdRng::seed_from_u64(TEST_SETUP_SEED);
+        let tau = Fr::rand(&mut rng);
+
+        let g1 = G1Projective::generator();
+        let mut power = Fr::from(1u64);
+        let mut powers = Vec::with_capacity(domain.size());
+        for _ in 0..domain.size() {
+            powers.push(g1 * power);
+            power *= tau;
+        }
+        let g2 = G2Projective::generator();
+        KzgSetup {
+            domain,
+            powers: G1Projective::normalize_batch(&powers),
+            g2: g2.into_affine(),
+            tau_g2: (g2 * tau).into_affine(),
+        }
+    }
+
+    // Coefficients of the polynomial through the digests, which are zero beyond the vector
+    fn interpolate(&self, digests: &[Hash]) -> Vec<Fr> {
+        let mut evals: Vec<Fr> = digests.iter().map(|digest| Fr::from_le_bytes_mod_order(digest)).collect();
+        evals.resize(self.domain.size(), Fr::zero());
+        self.domain.ifft(&evals)
+    }
+
+    fn commit_coeffs(&self, coeffs: &[Fr]) -> G1Affine {
+        G1Projective::msm_unchecked(&self.powers[..coeffs.len()], coeffs).into_affine()
+    }
+
+    fn open_coeffs(&self, coeffs: &[Fr], index: usize) -> G1Affine {
+        let quotient = divide_by_linear(coeffs, self.domain.element(index));
+        self.commit_coeffs(&quotient)
+    }
+
+    // Commitment to the digests and the openings at every position
+    pub fn commit(&self, digests: &[Hash]) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let coeffs = self.interpolate(digests);
+        let commitment = to_bytes(&self.commit_coeffs(&coeffs));
+        let openings = (0..digests.len())
+            .into_par_iter()
+            .map(|index| to_bytes(&self.open_coeffs(&coeffs, index)))
+            .collect();
+        (commitment, openings)
+    }
+
+    // Commitment to the digests and the opening at one position
+    pub fn commit_and_open(&self, digests: &[Hash], index: usize) -> (Vec<u8>, Vec<u8>) {
+        let coeffs = self.interpolate(digests);
+        (
+            to_bytes(&self.commit_coeffs(&coeffs)),
+            to_bytes(&self.open_coeffs(&coeffs, index)),
+        )
+    }
+
+    // Check e(C - [v]G1, G2) = e(π, [τ - ω^i]G2)
+    pub fn verify(&self, commitment: &[u8], index: usize, digest: &Hash, opening: &[u8]) -> bool {
+        if index >= self.domain.size() {
+            return false;
+        }
+        let (commitment, opening) = match (
+            G1Affine::deserialize_compressed(commitment),
+            G1Affine::deserialize_compressed(opening),
+        ) {
+            (Ok(commitment), Ok(opening)) => (commitment, opening),
+            _ => return false,
+        };
+        let value = Fr::from_le_bytes_mod_order(digest);
+        let lhs = Bls12_381::pairing(commitment.into_group() - G1Projective::generator() * value, self.g2);
+        let rhs = Bls12_381::pairing(
+            opening,
+            self.tau_g2.into_group() - self.g2.into_group() * self.domain.element(index),
+        );
+        lhs == rhs
+    }
+}
+
+// Quotient of (p(X) - p(point)) / (X - point), by synthetic division
+fn divide_by_linear(coeffs: &[Fr], point: Fr) -> Vec<Fr> {
+    let mut quotient = vec![Fr::zero(); coeffs.len().saturating_sub(1)];
+    let mut acc = Fr::zero();
+    for k in (1..coeffs.len()).rev() {
+        acc = coeffs[k] + acc * point;
+        quotient[k - 1] = acc;
+    }
+    quotient
+}
+
+fn to_bytes(point: &G1Affine) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(point.compressed_size());
+    point
+        .serialize_compressed(&mut bytes)
+        .expect("Serializing a G1 point into a vector cannot fail");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommitmentKind, Committer};
+    use consensus::HashKind;
+
+    fn digests(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| HashKind::Sha256.digest(&[i as u8])).collect()
+    }
+
+    #[test]
+    fn openings_verify_at_their_position() {
+        for n in [4, 7] {
+            let kzg = KzgSetup::test_setup(n);
+            let digests = digests(n);
+            let (commitment, openings) = kzg.commit(&digests);
+            for (index, opening) in openings.iter().enumerate() {
+                assert!(kzg.verify(&commitment, index, &digests[index], opening));
+                // Opening a single position gives the same commitment and opening
+                assert_eq!(kzg.commit_and_open(&digests, index), (commitment.clone(), opening.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn openings_fail_at_other_positions() {
+        let kzg = KzgSetup::test_setup(7);
+        let digests = digests(7);
+        let (commitment, openings) = kzg.commit(&digests);
+        assert!(!kzg.verify(&commitment, 3, &digests[2], &openings[2]));
+        assert!(!kzg.verify(&commitment, 2, &digests[3], &openings[2]));
+        // Beyond the domain
+        assert!(!kzg.verify(&commitment, 8, &digests[2], &openings[2]));
+    }
+
+    #[test]
+    fn tampered_openings_fail() {
+        let kzg = KzgSetup::test_setup(4);
+        let digests = digests(4);
+        let (commitment, openings) = kzg.commit(&digests);
+        let (other, _) = kzg.commit(&digests[..3]);
+        assert!(!kzg.verify(&other, 1, &digests[1], &openings[1]));
+        assert!(!kzg.verify(&commitment, 1, &digests[0], &openings[1]));
+        assert!(!kzg.verify(&commitment, 1, &digests[1], &openings[0]));
+        assert!(!kzg.verify(&commitment, 1, &digests[1], &[0u8; 3]));
+        assert!(!kzg.verify(&[0u8; 48], 1, &digests[1], &openings[1]));
+    }
+
+    #[test]
+    fn committer_checks_the_expected_position() {
+        let committer = Committer::new(CommitmentKind::Kzg, HashKind::Sha256, 4);
+        let shards: Vec<Vec<u8>> = (0..4).map(|i| vec![i as u8; 16]).collect();
+        let proof = committer.commit(&shards).gen_proof(1);
+        assert!(committer.verify(&shards[1], &proof, 1));
+        // A node passing off the shard of node 1 as its own
+        assert!(!committer.verify(&shards[1], &proof, 2));
+        assert_eq!(committer.prove(&shards, 1).index(), 1);
+    }
+}
@@ -6,8 +6,11 @@ mod process;
 mod msg;
 use msg::*;
 
-mod handlers;
-pub use handlers::*;
+mod commitment;
+pub use commitment::*;
+
+mod kzg;
+pub use kzg::*;
 
 mod protocol;
 pub use protocol::*;
\ No newline at end of file
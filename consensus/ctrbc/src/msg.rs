@@ -1,25 +1,33 @@
-use crypto::aes_hash::{HashState, Proof};
-
-use crypto::hash::{do_hash};
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+use consensus::Evidence;
 use types::{Replica};
 
+use crate::{Committer, ShardProof};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CTRBCMsg {
-    pub shard: Vec<u8>,
-    pub mp: Proof,
+    pub shard: Bytes,
+    pub mp: ShardProof,
     pub origin: Replica,
 }
 
 impl CTRBCMsg {
-    pub fn verify_mr_proof(&self, hf: &HashState) -> bool {
-        // 2. Validate Merkle Proof
-        let hash_of_shard: [u8; 32] = do_hash(&self.shard.as_slice());
-        let state: bool = hash_of_shard == self.mp.item().clone() && self.mp.validate(hf);
-        return state;
+    pub fn verify_proof(&self, committer: &Committer, index: usize) -> bool {
+        // 2. Validate the opening of the shard at its position against the commitment
+        committer.verify(&self.shard, &self.mp, index)
     }
 }
+// Segment of the shard of a streamed INIT. The INIT itself then carries an empty shard.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StripeMsg {
+    pub index: usize,
+    pub count: usize,
+    pub data: Bytes,
+    pub origin: Replica,
+}
+
 /*
 this is how the rbc protocol works
 1. <sendall, m> (this is broadcast)
@@ -35,4 +43,7 @@ pub enum ProtMsg {
     Init(CTRBCMsg, usize), // Init
     Echo(CTRBCMsg, usize),
     Ready(CTRBCMsg, usize),
+    Stripe(StripeMsg, usize),
+    // Evidence against the dealer of an instance
+    Blame(Evidence, usize),
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use consensus::{Envelope, Evidence};
+use crypto::hash::Hash;
+
+use crate::{Context, ProtMsg};
+
+// Instance and commitment a message binds its sender to
+fn binding(protmsg: &ProtMsg) -> Option<(usize, Hash)> {
+    match protmsg {
+        ProtMsg::Init(msg, instance_id) | ProtMsg::Echo(msg, instance_id) | ProtMsg::Ready(msg, instance_id) => {
+            Some((*instance_id, msg.mp.root()))
+        }
+        ProtMsg::Stripe(..) | ProtMsg::Blame(..) => None,
+    }
+}
+
+impl Context {
+    pub(crate) fn dealer(&self, instance_id: usize) -> usize {
+        instance_id / self.threshold
+    }
+
+    // Every message of the dealer must carry the root of its INIT. Keep the first one to prove equivocation later.
+    pub(crate) async fn check_dealer(&mut self, envelope: &Envelope, protmsg: &ProtMsg) {
+        let (instance_id, root) = match binding(protmsg) {
+            Some(binding) => binding,
+            None => return,
+        };
+        if envelope.sender != self.dealer(instance_id) || envelope.sender == self.myid {
+            return;
+        }
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        let first = match &rbc_context.dealer_binding {
+            None => {
+                rbc_context.dealer_binding = Some((root, envelope.clone()));
+                return;
+            }
+            Some((first_root, _)) if *first_root == root => return,
+            Some((_, first)) => first.clone(),
+        };
+        log::error!("Dealer {} equivocated in RBC instance {}", envelope.sender, instance_id);
+        self.blame(instance_id, Evidence::Equivocation(first, envelope.clone())).await;
+    }
+
+    // The INIT being handled fails verification, so it proves the dealer faulty
+    pub(crate) async fn blame_invalid_init(&mut self, instance_id: usize) {
+        if let Some(init) = self.init_envelope.take() {
+            self.blame(instance_id, Evidence::InvalidInit(init)).await;
+        }
+    }
+
+    async fn blame(&mut self, instance_id: usize, evidence: Evidence) {
+        if !self.record_blame(instance_id, evidence.clone()) {
+            return;
+        }
+        // MACs only convince their receiver, so the evidence is worth sending only when messages are signed
+        if self.auth.transferable() {
+            self.broadcast(ProtMsg::Blame(evidence, instance_id)).await;
+        }
+    }
+
+    fn record_blame(&mut self, instance_id: usize, evidence: Evidence) -> bool {
+        let culprit = evidence.culprit();
+        if !self.blame_list.add(instance_id, evidence.clone()) {
+            return false;
+        }
+        log::warn!("Blaming node {} in RBC instance {}", culprit, instance_id);
+        if let Some(blame_send) = &self.blame_send {
+            if let Err(e) = blame_send.send((instance_id, evidence)) {
+                log::error!("Failed to hand evidence against node {} to the upper layer: {:?}", culprit, e);
+            }
+        }
+        true
+    }
+
+    pub async fn handle_blame(&mut self, evidence: Evidence, instance_id: usize) {
+        if self.blame_list.is_blamed(instance_id, evidence.culprit()) {
+            return;
+        }
+        if !self.verify_evidence(&evidence, instance_id) {
+            log::warn!("Invalid evidence against node {} in RBC instance {}", evidence.culprit(), instance_id);
+            return;
+        }
+        // The node that found the evidence already sent it to everyone
+        self.record_blame(instance_id, evidence);
+    }
+
+    fn verify_evidence(&self, evidence: &Evidence, instance_id: usize) -> bool {
+        let dealer = self.dealer(instance_id);
+        match evidence {
+            Evidence::Equivocation(first, second) => {
+                if first.sender != dealer || second.sender != dealer {
+                    return false;
+                }
+                let first = self.auth.open::<ProtMsg>(first).as_ref().and_then(binding);
+                let second = self.auth.open::<ProtMsg>(second).as_ref().and_then(binding);
+                match (first, second) {
+                    (Some((first_id, first_root)), Some((second_id, second_root))) => {
+                        first_id == instance_id && second_id == instance_id && first_root != second_root
+                    }
+                    _ => false,
+                }
+            }
+            Evidence::InvalidInit(init) => {
+                if init.sender != dealer {
+                    return false;
+                }
+                match self.auth.open::<ProtMsg>(init) {
+                    // The INIT of a streamed shard carries none. It does not name its receiver either, so the opening
+                    // is checked at the position it claims.
+                    Some(ProtMsg::Init(msg, id)) => {
+                        id == instance_id
+                            && !msg.shard.is_empty()
+                            && !msg.verify_proof(&self.committer, msg.mp.index())
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,6 @@
-use consensus::reconstruct_data;
+use crypto::hash::Hash;
 
-use super::init::construct_merkle_tree;
-use crate::ProtMsg;
-use crate::{CTRBCMsg, Context};
+use crate::{CTRBCMsg, Context, Phase, ProtMsg, Reconstructed};
 
 impl Context {
     pub async fn handle_echo(self: &mut Context, msg: CTRBCMsg, instance_id: usize) {
@@ -24,7 +22,7 @@ impl Context {
         let echo_senders = rbc_context.echos.entry(root).or_default();
 
         // check if verifies
-        if !echo_senders.contains_key(&msg.origin) && !msg.verify_mr_proof(&self.hash_context) {
+        if !echo_senders.contains_key(&msg.origin) && !msg.verify_proof(&self.committer, msg.origin) {
             log::error!(
                 "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                 msg.origin,
@@ -50,98 +48,47 @@ impl Context {
             let mut shards: Vec<Option<Vec<u8>>> = Vec::new();
             for rep in 0..self.num_nodes {
                 if senders.contains_key(&rep) {
-                    shards.push(Some(senders.get(&rep).unwrap().clone()));
+                    shards.push(Some(senders.get(&rep).unwrap().to_vec()));
                 } else {
                     shards.push(None);
                 }
             }
-
-            let status = reconstruct_data(&mut shards, self.num_faults + 1, 2 * self.num_faults);
-
-            if status.is_err() {
-                log::error!(
-                    "FATAL: Error in Lagrange interpolation {}",
-                    status.err().unwrap()
-                );
-                return;
-            }
-
-            let shards: Vec<Vec<u8>> = shards.into_iter().map(|opt| opt.unwrap()).collect();
-
-            let mut message = Vec::new();
-            for i in 0..self.num_faults + 1 {
-                message.extend(shards.get(i).clone().unwrap());
-            }
-
-            // Hashes on large messages are very expensive. Do as much as you can to avoid recomputing them.
-            let my_share: Vec<u8> = shards[self.myid].clone();
-            let my_proof;
-            if rbc_context.fragment.is_some() && rbc_context.fragment.clone().unwrap().1.root() == root {
-                my_proof = rbc_context.fragment.clone().unwrap().1;
-            } else {
-                my_proof = construct_merkle_tree(shards.clone(), &self.hash_context).gen_proof(self.myid);
-            }
-            // Reconstruct Merkle Root
-            //let merkle_tree = construct_merkle_tree(shards, &self.hash_context);
-            //log::info!("Reconstructing tree in echo phase for RBC instance id {}", instance_id);
-
-            //if merkle_tree.root() == root {
-                // ECHO phase is completed. Save our share and the root for later purposes and quick access.
-                rbc_context.echo_root = Some(root);
-                rbc_context.fragment = Some((my_share.clone(), my_proof.clone()));
-                rbc_context.message = Some(message);
-
-                // Send ready message
-                let ctrbc_msg = CTRBCMsg {
-                    shard: my_share,
-                    mp: my_proof,
-                    origin: self.myid,
-                };
-
-                self.handle_ready(ctrbc_msg.clone(), instance_id).await;
-                let ready_msg = ProtMsg::Ready(ctrbc_msg, instance_id);
-                self.broadcast(ready_msg).await;
-            //}
+            // Interpolation continues in finish_echo once the blocking pool is done
+            self.start_reconstruction(instance_id, root, Phase::Echo, shards);
         }
         // Go for optimistic termination if all n shares have appeared
         else if size == self.num_nodes {
-            // log::info!(
-            //     "Echo senders: {:?} for RBC instance id {}, size: {}",
-            //     echo_senders,
-            //     instance_id,
-            //     size
-            // );
             log::info!(
                 "Received n ECHO messages for RBC instance id {}, terminating",
                 instance_id
             );
-            // Do not reconstruct the entire root again. Just send the merkle proof
-
-            let echo_root = rbc_context.echo_root.clone();
-
-            if echo_root.is_some() && !rbc_context.terminated {
-                rbc_context.terminated = true;
-                // Send Ready and terminate
-                if self.crash {
-                    return;
-                }
-                // Sending READY again is not necessary because we already sent one
-                // let fragment = rbc_context.fragment.clone().unwrap();
-                // let ctrbc_msg = CTRBCMsg {
-                //     shard: fragment.0,
-                //     mp: fragment.1,
-                //     origin: self.myid,
-                // };
-
-                let message = rbc_context.message.clone().unwrap();
-
-                // let ready_msg = ProtMsg::Ready(ctrbc_msg, instance_id);
-
-                // self.broadcast(ready_msg).await;
-                log::info!("Terminated RBC with message length {}",message.len());
-                self.terminate(instance_id, message).await;
-            }
+            // Do not reconstruct the entire root again. The message is known once the echo phase completed.
+            self.try_terminate(instance_id, root).await;
         }
         log::info!("Handled echo sent by node {} for RBC instance id {}", echo_sender, instance_id);
     }
+
+    // ECHO phase is completed. Save our share and the root for later purposes and quick access.
+    pub async fn finish_echo(self: &mut Context, instance_id: usize, root: Hash, reconstructed: Reconstructed) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if rbc_context.terminated {
+            return;
+        }
+        rbc_context.echo_root = Some(root);
+        rbc_context.fragment = Some((reconstructed.share.clone(), reconstructed.proof.clone()));
+        rbc_context.message = Some(reconstructed.message);
+
+        // Send ready message
+        let ctrbc_msg = CTRBCMsg {
+            shard: reconstructed.share,
+            mp: reconstructed.proof,
+            origin: self.myid,
+        };
+
+        self.handle_ready(ctrbc_msg.clone(), instance_id).await;
+        let ready_msg = ProtMsg::Ready(ctrbc_msg, instance_id);
+        self.broadcast(ready_msg).await;
+        // The remaining ECHOs or READYs may have arrived while interpolating
+        self.try_terminate(instance_id, root).await;
+    }
 }
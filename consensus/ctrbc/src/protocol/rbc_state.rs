@@ -1,15 +1,25 @@
 use std::collections::HashMap;
 
-use crypto::{aes_hash::{Proof}, hash::Hash};
+use bytes::Bytes;
+
+use consensus::Envelope;
+use crypto::hash::Hash;
+
+use crate::{CTRBCMsg, ShardProof};
 
 pub struct RBCState{
-    pub echos: HashMap<Hash, HashMap<usize,Vec<u8>>>,
+    pub echos: HashMap<Hash, HashMap<usize,Bytes>>,
     pub echo_root: Option<Hash>,
 
-    pub readys: HashMap<Hash, HashMap<usize,Vec<u8>>>,
+    pub readys: HashMap<Hash, HashMap<usize,Bytes>>,
     
-    pub fragment: Option<(Vec<u8>, Proof)>,
-    pub message: Option<Vec<u8>>,
+    pub fragment: Option<(Bytes, ShardProof)>,
+    pub message: Option<Bytes>,
+
+    // First message of the dealer and the root it carries
+    pub dealer_binding: Option<(Hash, Envelope)>,
+    // Shard of a streamed INIT, gathered from its stripes
+    pub streamed: StreamedShard,
 
     pub terminated: bool,
 }
@@ -26,6 +36,9 @@ impl RBCState{
             fragment: None, 
             message: None,
 
+            dealer_binding: None,
+            streamed: StreamedShard::default(),
+
             terminated:false
         }
     }
@@ -35,4 +48,13 @@ impl Default for RBCState {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[derive(Default)]
+pub struct StreamedShard {
+    pub count: Option<usize>,
+    pub stripes: HashMap<usize, Bytes>,
+    // INIT with the opening of the shard
+    pub init: Option<CTRBCMsg>,
+    pub assembled: bool,
+}
@@ -1,8 +1,6 @@
-use consensus::reconstruct_data;
+use crypto::hash::Hash;
 
-use crate::protocol::init::construct_merkle_tree;
-
-use crate::{CTRBCMsg, ProtMsg};
+use crate::{CTRBCMsg, Phase, ProtMsg, Reconstructed};
 
 use crate::Context;
 impl Context {
@@ -22,7 +20,7 @@ impl Context {
 
         // Hashes on large messages are very expensive. Do as much as you can to avoid recomputing them.
         if !echo_senders.contains_key(&msg.origin) {
-            if !msg.verify_mr_proof(&self.hash_context){
+            if !msg.verify_proof(&self.committer, msg.origin){
                 log::error!(
                     "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                     msg.origin,
@@ -34,7 +32,7 @@ impl Context {
         else{
             let msg_echo = echo_senders.get(&msg.origin).unwrap().clone();
             if msg_echo != msg.shard {
-                if !msg.verify_mr_proof(&self.hash_context){
+                if !msg.verify_proof(&self.committer, msg.origin){
                     log::error!(
                         "Invalid Merkle Proof sent by node {}, abandoning RBC instance {}",
                         msg.origin,
@@ -68,74 +66,76 @@ impl Context {
             let mut shards: Vec<Option<Vec<u8>>> = Vec::new();
             for rep in 0..self.num_nodes {
                 if ready_senders.contains_key(&rep) {
-                    shards.push(Some(ready_senders.get(&rep).unwrap().clone()));
+                    shards.push(Some(ready_senders.get(&rep).unwrap().to_vec()));
                 } else {
                     shards.push(None);
                 }
             }
 
-            let status = reconstruct_data(&mut shards, self.num_faults + 1, 2 * self.num_faults);
-
-            if status.is_err() {
-                log::error!(
-                    "FATAL: Error in Lagrange interpolation {}",
-                    status.err().unwrap()
-                );
-                return;
-            }
-
-            let shards: Vec<Vec<u8>> = shards.into_iter().map(|opt| opt.unwrap()).collect();
-
-            let mut message = Vec::new();
-            for i in 0..self.num_faults + 1 {
-                message.extend(shards.get(i).clone().unwrap());
-            }
-
-            let my_share: Vec<u8> = shards[self.myid].clone();
-            let my_proof;
-            if rbc_context.fragment.is_some() && rbc_context.fragment.clone().unwrap().1.root() == root {
-                my_proof = rbc_context.fragment.clone().unwrap().1;
-            } else {
-                my_proof = construct_merkle_tree(shards.clone(), &self.hash_context).gen_proof(self.myid);
-            }
-            // Reconstruct Merkle Root
-            //let merkle_tree = construct_merkle_tree(shards, &self.hash_context);
-            //if merkle_tree.root() == root {
-                // Ready phase is completed. Save our share for later purposes and quick access.
-                rbc_context.fragment = Some((my_share.clone(), my_proof.clone()));
-
-                rbc_context.message = Some(message);
-
-                // Insert own ready share
-                rbc_context
-                    .readys
-                    .get_mut(&root)
-                    .unwrap()
-                    .insert(self.myid, my_share.clone());
-                // Send ready message
-                let ctrbc_msg = CTRBCMsg {
-                    shard: my_share,
-                    mp: my_proof,
-                    origin: self.myid,
-                };
-
-                let ready_msg = ProtMsg::Ready(ctrbc_msg.clone(), instance_id);
-
-                if !self.crash {
-                    self.broadcast(ready_msg).await;
-                }
-            //}
+            // Interpolation continues in finish_ready once the blocking pool is done
+            self.start_reconstruction(instance_id, root, Phase::Ready, shards);
         } else if size == self.num_nodes - self.num_faults && !rbc_context.terminated {
             log::info!(
-                "Received n-f READY messages for RBC instance id {} and message length {}, terminating",
-                instance_id,
-                rbc_context.message.clone().unwrap().len()
+                "Received n-f READY messages for RBC instance id {}, terminating",
+                instance_id
             );
-            // Terminate protocol
-            rbc_context.terminated = true;
-            let term_msg = rbc_context.message.clone().unwrap();
-            self.terminate(instance_id, term_msg).await;
+            // Terminate protocol, or once the message is interpolated if that is still running
+            self.try_terminate(instance_id, root).await;
         }
         log::info!("Handled ready sent by node {} for RBC instance id {}", msg.origin, instance_id);
     }
+
+    // Ready phase is completed. Save our share for later purposes and quick access.
+    pub async fn finish_ready(self: &mut Context, instance_id: usize, root: Hash, reconstructed: Reconstructed) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if rbc_context.terminated {
+            return;
+        }
+        // The echo phase completed for this root in the meantime and already sent our READY
+        if rbc_context.echo_root == Some(root) {
+            self.try_terminate(instance_id, root).await;
+            return;
+        }
+        rbc_context.fragment = Some((reconstructed.share.clone(), reconstructed.proof.clone()));
+        rbc_context.message = Some(reconstructed.message);
+
+        // Insert own ready share
+        rbc_context
+            .readys
+            .entry(root)
+            .or_default()
+            .insert(self.myid, reconstructed.share.clone());
+        // Send ready message
+        let ctrbc_msg = CTRBCMsg {
+            shard: reconstructed.share,
+            mp: reconstructed.proof,
+            origin: self.myid,
+        };
+
+        let ready_msg = ProtMsg::Ready(ctrbc_msg, instance_id);
+
+        if !self.crash {
+            self.broadcast(ready_msg).await;
+        }
+        self.try_terminate(instance_id, root).await;
+    }
+
+    // Terminate once the message is known and either n-f READYs or n ECHOs on our echo root are in.
+    // Interpolation runs off the event loop, so these quorums can complete before the message does.
+    pub async fn try_terminate(self: &mut Context, instance_id: usize, root: Hash) {
+        let rbc_context = self.rbc_context.entry(instance_id).or_default();
+        if rbc_context.terminated || rbc_context.message.is_none() {
+            return;
+        }
+        let readys = rbc_context.readys.get(&root).map_or(0, |senders| senders.len());
+        let echos = rbc_context.echos.get(&root).map_or(0, |senders| senders.len());
+        let echoed = rbc_context.echo_root == Some(root) && echos == self.num_nodes;
+        if readys < self.num_nodes - self.num_faults && !echoed {
+            return;
+        }
+        rbc_context.terminated = true;
+        let message = rbc_context.message.clone().unwrap();
+        log::info!("Terminated RBC instance id {} with message length {}", instance_id, message.len());
+        self.terminate(instance_id, message).await;
+    }
 }
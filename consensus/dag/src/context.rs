@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, SocketAddrV4},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use config::Node;
+
+use consensus::{listen_sync, sync_sender, Evidence, NetworkConfig, RBCServiceHandle, Transport};
+use fnv::FnvHashMap;
+use network::{
+    plaintcp::CancelHandler,
+    Acknowledgement,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use types::{Replica, SyncMsg, SyncState};
+
+use super::DAGState;
+
+pub struct Context {
+    /// RBC service used to disseminate vertices
+    pub rbc_send: UnboundedSender<SyncMsg>,
+    pub rbc_recv: UnboundedReceiver<(usize, Vec<u8>)>,
+    pub blame_recv: UnboundedReceiver<(usize, Evidence)>,
+    /// Networking context
+    pub sync_send: Box<dyn Transport<SyncMsg>>,
+    pub sync_recv: UnboundedReceiver<SyncMsg>,
+    /// Data context
+    pub num_nodes: usize,
+    pub myid: usize,
+    pub num_faults: usize,
+    pub inp_message: Vec<u8>,
+
+    /// Cancel Handlers
+    pub cancel_handlers: HashMap<u64, Vec<CancelHandler<Acknowledgement>>>,
+    exit_rx: oneshot::Receiver<()>,
+
+    pub dag_state: DAGState,
+
+    // Identifier space of the RBC service: instance ids of node i are threshold * i + round
+    pub threshold: usize,
+    pub max_rounds: usize,
+    pub start_time: u128,
+    pub sent_term: bool,
+}
+
+impl Context {
+    pub fn spawn(config: Node, rbc: RBCServiceHandle, max_rounds: usize, network: NetworkConfig) -> anyhow::Result<oneshot::Sender<()>> {
+        let mut syncer_map: FnvHashMap<Replica, SocketAddr> = FnvHashMap::default();
+        syncer_map.insert(0, config.client_addr);
+
+        let syncer_listen_port = config.client_port;
+        let syncer_l_address = to_socket_address("0.0.0.0", syncer_listen_port);
+
+        // The server must listen to the client's messages on some port that is not being used to listen to other servers
+        let (tx_net_to_client, rx_net_from_client) = unbounded_channel();
+        listen_sync(&network, syncer_l_address, tx_net_to_client).map_err(anyhow::Error::msg)?;
+
+        let sync_net = sync_sender(&network, syncer_map);
+        let (exit_tx, exit_rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let mut c = Context {
+                rbc_send: rbc.req_send,
+                rbc_recv: rbc.out_recv,
+                blame_recv: rbc.blame_recv,
+                sync_send: sync_net,
+                sync_recv: rx_net_from_client,
+                num_nodes: config.num_nodes,
+                myid: config.id,
+                num_faults: config.num_faults,
+                inp_message: Vec::new(),
+                cancel_handlers: HashMap::default(),
+                exit_rx,
+
+                dag_state: DAGState::default(),
+
+                threshold: 10000,
+                max_rounds,
+                start_time: 0,
+                sent_term: false,
+            };
+
+            // Run the consensus context
+            if let Err(e) = c.run().await {
+                log::error!("Consensus error: {}", e);
+            }
+        });
+        Ok(exit_tx)
+    }
+
+    pub fn add_cancel_handler(&mut self, canc: CancelHandler<Acknowledgement>) {
+        self.cancel_handlers.entry(0).or_default().push(canc);
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        // The process starts listening to messages in this process.
+        // First, the node sends an alive message
+        let cancel_handler = self
+            .sync_send
+            .send(
+                0,
+                SyncMsg {
+                    sender: self.myid,
+                    state: SyncState::ALIVE,
+                    value: "".to_string().into_bytes(),
+                },
+            )
+            .await;
+        self.add_cancel_handler(cancel_handler);
+        loop {
+            tokio::select! {
+                // Receive exit handlers
+                exit_val = &mut self.exit_rx => {
+                    exit_val.map_err(anyhow::Error::new)?;
+                    log::info!("Termination signal received by the server. Exiting.");
+                    break
+                },
+                delivered = self.rbc_recv.recv() => {
+                    // Vertices delivered by the RBC service are processed here
+                    let (instance_id, data) = delivered.ok_or_else(||
+                        anyhow!("RBC service has closed")
+                    )?;
+                    self.handle_vertex(instance_id, data).await;
+                },
+                // RBC services without blame support drop their end, which disables this branch
+                Some((instance_id, evidence)) = self.blame_recv.recv() => {
+                    // The RBC service found evidence against the author of a vertex
+                    log::warn!("Node {} misbehaved broadcasting its vertex of round {}", evidence.culprit(), instance_id % self.threshold);
+                },
+                sync_msg = self.sync_recv.recv() =>{
+                    let sync_msg = sync_msg.ok_or_else(||
+                        anyhow!("Networking layer has closed")
+                    )?;
+                    match sync_msg.state {
+                        SyncState::START =>{
+                            self.start_time = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_millis();
+                            log::info!("Consensus Start time: {:?}", self.start_time);
+                            // Every vertex carries the syncer's payload
+                            self.inp_message = sync_msg.value;
+                            self.start_dag().await;
+                        },
+                        SyncState::STOP =>{
+                            // Code used for internal purposes
+                            log::info!("Consensus Stop time: {:?}", SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_millis());
+                            log::info!("Termination signal received by the server. Exiting.");
+                            // Stop the RBC service along with us
+                            let _ = self.rbc_send.send(sync_msg);
+                            break
+                        },
+                        _=>{}
+                    }
+                },
+            };
+        }
+        Ok(())
+    }
+}
+
+pub fn to_socket_address(ip_str: &str, port: u16) -> SocketAddr {
+    let addr = SocketAddrV4::new(ip_str.parse().unwrap(), port);
+    addr.into()
+}
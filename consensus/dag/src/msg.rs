@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use types::Replica;
+
+// A DAG vertex is reliably broadcast by its origin in instance threshold * origin + round,
+// so RBC agreement guarantees at most one vertex per (round, origin).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Vertex {
+    pub round: usize,
+    pub origin: Replica,
+    // Origins of the vertices of round - 1 this vertex references
+    pub parents: Vec<Replica>,
+    pub payload: Vec<u8>,
+}
+
+/*
+This is how the DAG is built and committed (rounds start at 1):
+1. In round r, reliably broadcast a vertex referencing n-f vertices of round r-1
+2. Add a delivered vertex to the DAG once all its parents are in the DAG
+3. Move to round r+1 once the DAG holds n-f vertices of round r
+4. Every even round r has a leader (r/2) mod n. The leader vertex of round r is committed
+   when t+1 vertices of round r+1 reference it.
+5. On committing a leader, first commit earlier uncommitted leaders it has a path to, then
+   order the causal history of each leader deterministically by (round, origin).
+*/
+
+// Output reported to the syncer. Laid out like the syncer's RBCSyncMsg, where msg is the digest
+// of the committed sequence, followed by the DAG statistics as a SyncReport.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DAGSyncMsg {
+    pub id: usize,
+    pub msg: Vec<u8>,
+}
@@ -0,0 +1,71 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use consensus::SyncReport;
+use crypto::hash::do_hash;
+use types::{SyncMsg, SyncState};
+
+use super::DAGSyncMsg;
+use crate::context::Context;
+
+impl Context {
+    // Invoke this function once a leader at or beyond the target round is committed
+    pub async fn report(&mut self) {
+        self.sent_term = true;
+
+        // Digest of the committed sequence up to the last leader within the target round.
+        // Honest nodes commit consistent sequences, so this digest matches across nodes.
+        let target_round = self.target_round();
+        let committed = self
+            .dag_state
+            .leader_marks
+            .iter()
+            .filter(|(round, _)| *round <= target_round)
+            .map(|(_, committed)| *committed)
+            .last()
+            .unwrap_or(0);
+        let sequence = &self.dag_state.ordered[..committed];
+        let digest = do_hash(&bincode::serialize(sequence).expect("Failed to serialize committed sequence"));
+
+        let latencies = &self.dag_state.commit_latencies;
+        let commit_latency = if latencies.is_empty() {
+            0
+        } else {
+            latencies.iter().sum::<u128>() / latencies.len() as u128
+        };
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            - self.start_time;
+        log::info!(
+            "Committed {} vertices in {} rounds and {} ms, average commit latency of own vertices {} ms",
+            committed,
+            self.dag_state.round,
+            elapsed,
+            commit_latency
+        );
+
+        let sync_dag_msg = DAGSyncMsg {
+            id: target_round,
+            msg: digest.to_vec(),
+        };
+        let report = SyncReport::Dag {
+            rounds: self.dag_state.round,
+            committed_vertices: committed,
+            commit_latency,
+        };
+        let value = bincode::serialize(&sync_dag_msg).expect("Failed to serialize DAG report");
+        let cancel_handler = self
+            .sync_send
+            .send(
+                0,
+                SyncMsg {
+                    sender: self.myid,
+                    state: SyncState::COMPLETED,
+                    value: report.append(value),
+                },
+            )
+            .await;
+        self.add_cancel_handler(cancel_handler);
+    }
+}
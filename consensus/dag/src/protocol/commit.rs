@@ -0,0 +1,105 @@
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use types::Replica;
+
+use crate::Context;
+
+impl Context {
+    pub fn leader(&self, round: usize) -> Replica {
+        (round / 2) % self.num_nodes
+    }
+
+    // Commit the leader of an even round once t+1 vertices of the next round reference it
+    pub async fn try_commit(self: &mut Context, round: usize) {
+        if round % 2 != 0 || round <= self.dag_state.last_leader_round {
+            return;
+        }
+        let leader = self.leader(round);
+        if self.dag_state.get_vertex(round, leader).is_none() {
+            return;
+        }
+        let votes = match self.dag_state.vertices.get(&(round + 1)) {
+            Some(next_round) => next_round
+                .values()
+                .filter(|vertex| vertex.parents.contains(&leader))
+                .count(),
+            None => 0,
+        };
+        if votes < self.num_faults + 1 {
+            return;
+        }
+
+        // Earlier leaders reachable from this one are committed first
+        let mut leaders = vec![round];
+        let mut head = round;
+        let mut prev = round - 2;
+        while prev > self.dag_state.last_leader_round {
+            let prev_leader = self.leader(prev);
+            if self.dag_state.get_vertex(prev, prev_leader).is_some()
+                && self.dag_state.has_path(head, self.leader(head), prev, prev_leader)
+            {
+                leaders.push(prev);
+                head = prev;
+            }
+            prev -= 2;
+        }
+        for leader_round in leaders.into_iter().rev() {
+            self.order_history(leader_round);
+        }
+        self.dag_state.last_leader_round = round;
+
+        if !self.sent_term && round >= self.target_round() {
+            self.report().await;
+        }
+    }
+
+    // Append the uncommitted causal history of a leader to the committed sequence
+    fn order_history(self: &mut Context, round: usize) {
+        let leader = self.leader(round);
+        let mut history: HashSet<(usize, Replica)> = HashSet::default();
+        let mut stack = vec![(round, leader)];
+        while let Some((r, rep)) = stack.pop() {
+            if self.dag_state.committed.contains(&(r, rep)) || !history.insert((r, rep)) {
+                continue;
+            }
+            if let Some(vertex) = self.dag_state.get_vertex(r, rep) {
+                for parent in vertex.parents.iter() {
+                    stack.push((r - 1, *parent));
+                }
+            }
+        }
+        let mut history: Vec<(usize, Replica)> = history.into_iter().collect();
+        history.sort();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        for (r, rep) in history.iter() {
+            if *rep == self.myid {
+                if let Some(created_at) = self.dag_state.created_at.get(r) {
+                    self.dag_state.commit_latencies.push(now - created_at);
+                }
+            }
+            self.dag_state.committed.insert((*r, *rep));
+        }
+        log::info!(
+            "Committed leader {} of round {} with {} vertices",
+            leader,
+            round,
+            history.len()
+        );
+        self.dag_state.ordered.extend(history);
+        let committed = self.dag_state.ordered.len();
+        self.dag_state.leader_marks.push((round, committed));
+    }
+
+    // The last even round whose leader can gather votes within max_rounds
+    pub fn target_round(&self) -> usize {
+        let round = self.max_rounds.saturating_sub(1);
+        round - round % 2
+    }
+}
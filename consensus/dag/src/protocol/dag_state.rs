@@ -0,0 +1,76 @@
+use std::collections::{HashMap, HashSet};
+
+use types::Replica;
+
+use crate::Vertex;
+
+pub struct DAGState {
+    // Current round of this node
+    pub round: usize,
+
+    // Vertices whose causal history is complete: round -> origin -> vertex
+    pub vertices: HashMap<usize, HashMap<Replica, Vertex>>,
+    // Delivered vertices still waiting for some of their parents
+    pub buffer: Vec<Vertex>,
+
+    pub committed: HashSet<(usize, Replica)>,
+    pub ordered: Vec<(usize, Replica)>,
+    // Length of the committed sequence after each committed leader round
+    pub leader_marks: Vec<(usize, usize)>,
+    pub last_leader_round: usize,
+
+    // Creation time of our own vertex in each round
+    pub created_at: HashMap<usize, u128>,
+    pub commit_latencies: Vec<u128>,
+}
+
+impl DAGState {
+    pub fn new() -> DAGState {
+        DAGState {
+            round: 0,
+
+            vertices: HashMap::default(),
+            buffer: Vec::new(),
+
+            committed: HashSet::default(),
+            ordered: Vec::new(),
+            leader_marks: Vec::new(),
+            last_leader_round: 0,
+
+            created_at: HashMap::default(),
+            commit_latencies: Vec::new(),
+        }
+    }
+
+    pub fn get_vertex(&self, round: usize, origin: Replica) -> Option<&Vertex> {
+        self.vertices.get(&round).and_then(|round_vertices| round_vertices.get(&origin))
+    }
+
+    pub fn round_size(&self, round: usize) -> usize {
+        self.vertices.get(&round).map_or(0, |round_vertices| round_vertices.len())
+    }
+
+    // Whether the vertex (round, origin) has a path of parent references to (target_round, target)
+    pub fn has_path(&self, round: usize, origin: Replica, target_round: usize, target: Replica) -> bool {
+        let mut frontier: HashSet<Replica> = HashSet::default();
+        frontier.insert(origin);
+        let mut r = round;
+        while r > target_round {
+            let mut next: HashSet<Replica> = HashSet::default();
+            for rep in frontier.iter() {
+                if let Some(vertex) = self.get_vertex(r, *rep) {
+                    next.extend(vertex.parents.iter().copied());
+                }
+            }
+            frontier = next;
+            r -= 1;
+        }
+        r == target_round && frontier.contains(&target)
+    }
+}
+
+impl Default for DAGState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
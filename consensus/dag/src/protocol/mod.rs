@@ -0,0 +1,6 @@
+mod vertex;
+
+mod commit;
+
+mod dag_state;
+pub use dag_state::*;
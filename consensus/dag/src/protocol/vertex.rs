@@ -0,0 +1,148 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use types::{Replica, SyncMsg, SyncState};
+
+use crate::{Context, Vertex};
+
+impl Context {
+    pub async fn start_dag(self: &mut Context) {
+        log::info!("Starting DAG construction for {} rounds", self.max_rounds);
+        self.dag_state.round = 1;
+        self.create_vertex(1, Vec::new());
+    }
+
+    // Hand a new vertex of this node to the RBC service
+    pub fn create_vertex(self: &mut Context, round: usize, parents: Vec<Replica>) {
+        let vertex = Vertex {
+            round,
+            origin: self.myid,
+            parents,
+            payload: self.inp_message.clone(),
+        };
+        log::info!(
+            "Broadcasting vertex of round {} with {} parents",
+            round,
+            vertex.parents.len()
+        );
+        self.dag_state.created_at.insert(
+            round,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+        );
+
+        // The RBC context numbers our broadcasts threshold * myid + 1, + 2, ..., which matches the round
+        let status = self.rbc_send.send(SyncMsg {
+            sender: self.myid,
+            state: SyncState::START,
+            value: bincode::serialize(&vertex).expect("Failed to serialize vertex"),
+        });
+        if status.is_err() {
+            log::error!(
+                "Failed to hand vertex of round {} to the RBC service because of {:?}",
+                round,
+                status.err().unwrap()
+            );
+        }
+    }
+
+    pub async fn handle_vertex(self: &mut Context, instance_id: usize, data: Vec<u8>) {
+        // The RBC service only accepts the INIT of an instance from the dealer its id names,
+        // so a vertex is only attributed to its origin if the origin broadcast it
+        let dealer = instance_id / self.threshold;
+        let round = instance_id % self.threshold;
+
+        let vertex: Vertex = match bincode::deserialize(&data) {
+            Ok(vertex) => vertex,
+            Err(e) => {
+                log::warn!(
+                    "Unable to deserialize vertex delivered in RBC instance {}: {:?}",
+                    instance_id,
+                    e
+                );
+                return;
+            }
+        };
+        if !self.verify_vertex(&vertex, dealer, round) {
+            log::warn!(
+                "Invalid vertex delivered in RBC instance {} by dealer {}, ignoring",
+                instance_id,
+                dealer
+            );
+            return;
+        }
+        log::debug!("Delivered vertex of round {} from node {}", round, dealer);
+        self.dag_state.buffer.push(vertex);
+
+        self.process_buffer().await;
+    }
+
+    fn verify_vertex(&self, vertex: &Vertex, dealer: Replica, round: usize) -> bool {
+        if dealer >= self.num_nodes || vertex.origin != dealer || vertex.round != round || round == 0 {
+            return false;
+        }
+        if round == 1 {
+            return vertex.parents.is_empty();
+        }
+        let mut parents = vertex.parents.clone();
+        parents.sort();
+        parents.dedup();
+        parents.len() == vertex.parents.len()
+            && parents.len() >= self.num_nodes - self.num_faults
+            && parents.iter().all(|rep| *rep < self.num_nodes)
+    }
+
+    // Move vertices whose parents are all in the DAG from the buffer into the DAG
+    async fn process_buffer(self: &mut Context) {
+        loop {
+            let position = self.dag_state.buffer.iter().position(|vertex| {
+                vertex.round == 1
+                    || vertex
+                        .parents
+                        .iter()
+                        .all(|rep| self.dag_state.get_vertex(vertex.round - 1, *rep).is_some())
+            });
+            let vertex = match position {
+                Some(position) => self.dag_state.buffer.swap_remove(position),
+                None => break,
+            };
+            let round = vertex.round;
+            self.dag_state
+                .vertices
+                .entry(round)
+                .or_default()
+                .insert(vertex.origin, vertex);
+
+            // A vertex of round r votes for the leader of round r - 1
+            if round > 1 {
+                self.try_commit(round - 1).await;
+            }
+        }
+        self.try_advance();
+    }
+
+    fn try_advance(self: &mut Context) {
+        loop {
+            let round = self.dag_state.round;
+            if self.dag_state.round_size(round) < self.num_nodes - self.num_faults {
+                return;
+            }
+            // Keep building rounds until a leader beyond the target round is committed
+            if round >= self.max_rounds && self.sent_term {
+                return;
+            }
+            let mut parents: Vec<Replica> = self
+                .dag_state
+                .vertices
+                .get(&round)
+                .unwrap()
+                .keys()
+                .copied()
+                .collect();
+            parents.sort();
+            self.dag_state.round = round + 1;
+            self.create_vertex(round + 1, parents);
+        }
+    }
+}
@@ -5,8 +5,5 @@ mod process;
 
 mod ping;
 
-mod handlers;
-pub use handlers::*;
-
 mod protocol;
 pub use protocol::*;
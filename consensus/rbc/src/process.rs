@@ -1,37 +1,67 @@
-use std::sync::Arc;
+use bytes::Bytes;
 
 use crate::context::Context;
-use crypto::hash::verf_mac;
+use consensus::{validated, Envelope};
 use types::{
-    SyncMsg, SyncState, {ProtMsg, WrapperMsg},
+    Replica, SyncMsg, SyncState, ProtMsg,
 };
-impl Context {
-    // This function verifies the Message Authentication Code (MAC) of a sent message
-    // A node cannot impersonate as another node because of MACs
-    pub fn check_proposal(&self, wrapper_msg: Arc<WrapperMsg<ProtMsg>>) -> bool {
-        // validate MAC
-        let byte_val =
-            bincode::serialize(&wrapper_msg.protmsg).expect("Failed to serialize object");
-        let sec_key = match self.sec_key_map.get(&wrapper_msg.clone().sender) {
-            Some(val) => val,
-            None => {
-                panic!("Secret key not available, this shouldn't happen")
-            }
-        };
-        if !verf_mac(&byte_val, &sec_key.as_slice(), &wrapper_msg.mac) {
-            log::warn!("MAC Verification failed.");
-            return false;
+
+// Instance whose state a message makes this node keep. Pings and outputs keep none.
+fn instance(protmsg: &ProtMsg) -> Option<usize> {
+    match protmsg {
+        ProtMsg::Sendall(_, instance_id) | ProtMsg::Echo(_, instance_id) | ProtMsg::Ready(_, instance_id) => {
+            Some(*instance_id)
         }
-        true
+        ProtMsg::Ping(..) | ProtMsg::Output(..) => None,
     }
+}
 
-    pub(crate) async fn process_msg(&mut self, wrapper_msg: WrapperMsg<ProtMsg>) {
-        log::trace!("Received protocol msg: {:?}", wrapper_msg);
-        let msg = Arc::new(wrapper_msg.clone());
+// Origin an ECHO or READY claims, under which it is counted
+fn claimed_origin(protmsg: &ProtMsg) -> Option<Replica> {
+    match protmsg {
+        ProtMsg::Echo(msg, _) | ProtMsg::Ready(msg, _) => Some(msg.origin),
+        ProtMsg::Sendall(..) | ProtMsg::Ping(..) | ProtMsg::Output(..) => None,
+    }
+}
+
+impl Context {
+    // This function verifies the MAC or signature of a sent message
+    // A node cannot impersonate as another node because of them
+    pub fn check_proposal(&self, envelope: &Envelope) -> Option<ProtMsg> {
+        // validate the MAC or signature on the received bytes, and only then deserialize them
+        let protmsg = self.auth.open(envelope);
+        if protmsg.is_none() {
+            log::warn!("Authentication of a message from node {} failed.", envelope.sender);
+        }
+        protmsg
+    }
 
+    pub(crate) async fn process_msg(&mut self, envelope: Envelope) {
         // Verify the message's authenticity before proceeding
-        if self.check_proposal(msg) {
-            match wrapper_msg.clone().protmsg {
+        if let Some(protmsg) = self.check_proposal(&envelope) {
+            log::trace!("Received protocol msg: {:?}", protmsg);
+            // Echoes and readies are counted under the origin they name, which must be the node that sent them
+            if let Some(origin) = claimed_origin(&protmsg) {
+                if origin != envelope.sender {
+                    log::warn!("Node {} sent a message in the name of node {}", envelope.sender, origin);
+                    return;
+                }
+            }
+            // Only the dealer an instance id names may start that instance
+            if let ProtMsg::Sendall(_, instance_id) = &protmsg {
+                if envelope.sender != instance_id / self.delivery.threshold {
+                    log::warn!("Node {} sent an INIT for instance {} of another dealer", envelope.sender, instance_id);
+                    return;
+                }
+            }
+            // Fabricated instances and floods are held back before they take any state
+            let instance_id = instance(&protmsg);
+            let known = instance_id.is_none_or(|instance_id| self.rbc_context.contains_key(&instance_id));
+            let envelope = match self.admission.admit(envelope.sender, instance_id, known, envelope) {
+                Some(envelope) => envelope,
+                None => return,
+            };
+            match protmsg {
                 ProtMsg::Ping(main_msg, rep) => {
                     // RBC initialized
                     log::info!(
@@ -77,27 +107,38 @@ impl Context {
                     self.handle_init(main_msg, rep).await;
                 }
             }
-        } else {
-            log::warn!(
-                "MAC Verification failed for message {:?}",
-                wrapper_msg.protmsg
-            );
         }
     }
 
     // Invoke this function once you terminate the protocol
-    pub async fn terminate(&mut self, data: Vec<u8>) {
-        let cancel_handler = self
-            .sync_send
-            .send(
-                0,
-                SyncMsg {
-                    sender: self.myid,
-                    state: SyncState::COMPLETED,
-                    value: data,
-                },
-            )
-            .await;
-        self.add_cancel_handler(cancel_handler);
+    pub async fn terminate(&mut self, instance_id: usize, data: Bytes) {
+        self.admission.delivered(instance_id);
+        // Honest nodes never deliver payloads failing the application's validity predicate.
+        // All honest nodes terminate with the same payload, so they all deliver nothing for this instance.
+        let data = validated(&self.compression, &self.validator, &data);
+        if data.is_none() {
+            log::warn!("Output of RBC instance {} failed validation, delivering nothing", instance_id);
+        }
+        // The ordering layer may hold this instance back or release earlier ones along with it
+        for (delivered_id, payload) in self.delivery.deliver(instance_id, data) {
+            if let Some(out_send) = &self.out_send {
+                if let Err(e) = out_send.send((delivered_id, payload)) {
+                    log::error!("Failed to hand RBC instance {} to the upper layer: {:?}", delivered_id, e);
+                }
+                continue;
+            }
+            let cancel_handler = self
+                .sync_send
+                .send(
+                    0,
+                    SyncMsg {
+                        sender: self.myid,
+                        state: SyncState::COMPLETED,
+                        value: payload,
+                    },
+                )
+                .await;
+            self.add_cancel_handler(cancel_handler);
+        }
     }
 }
@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use types::{Msg, ProtMsg};
 
 use crate::Context;
@@ -16,8 +17,9 @@ impl Context {
         // Use different types of messages like INIT, ECHO, .... for the Bracha's RBC implementation
         let protocol_msg = ProtMsg::Echo(msg, instance_id);
         // Broadcast the message to everyone
+        self.compression.account(&msg_content, self.num_nodes - 1);
         self.broadcast(protocol_msg).await;
-        self.echo_self(msg_content.clone(), instance_id).await;
+        self.echo_self(msg_content, instance_id).await;
     }
 
     pub async fn handle_echo(self: &mut Context, msg: Msg, instance_id: usize) {
@@ -28,16 +30,17 @@ impl Context {
             return;
         }
 
+        let content = Bytes::from(msg.content);
         let senders = rbc_context
             .echo_senders
-            .entry(msg.content.clone())
+            .entry(content.clone())
             .or_default();
 
         // Only count if we haven't seen an echo from this sender for this message
         if senders.insert(msg.origin) {
             *rbc_context
                 .received_echo_count
-                .entry(msg.content.clone())
+                .entry(content.clone())
                 .or_default() += 1;
 
             // log::info!(
@@ -48,7 +51,7 @@ impl Context {
             // );
 
             // let count = self.received_echo_count.get(&msg.content).unwrap();
-            let mut mode_content: Option<Vec<u8>> = None;
+            let mut mode_content: Option<Bytes> = None;
             let mut max_count = 0;
 
             for (content, &count) in rbc_context.received_echo_count.iter() {
@@ -69,7 +72,7 @@ impl Context {
                     // );
                     rbc_context.first_ready = true;
 
-                    self.start_ready(msg.content.clone(), instance_id).await;
+                    self.start_ready(content.to_vec(), instance_id).await;
                 }
             }
         }
@@ -22,12 +22,26 @@ impl Context {
         // Use different types of messages like INIT, ECHO, .... for the Bracha's RBC implementation
         let protocol_msg = ProtMsg::Sendall(msg, instance_id);
         // Broadcast the message to everyone
+        self.compression.account(&input_msg, self.num_nodes - 1);
         self.broadcast(protocol_msg).await;
     }
 
     pub async fn handle_init(self: &mut Context, msg: Msg, instance_id: usize) {
+        // Only echo payloads the application accepts
+        let valid = match self.compression.decompress(&msg.content) {
+            Some(payload) => (self.validator)(&payload),
+            None => false,
+        };
+        if !valid {
+            log::warn!(
+                "Payload sent by node {} failed validation, abandoning RBC instance {}",
+                msg.origin,
+                instance_id
+            );
+            return;
+        }
         //send echo
-        self.start_echo(msg.content.clone(), instance_id).await;
+        self.start_echo(msg.content, instance_id).await;
 
         // log::info!(
         //     "Received Sendall message {:?} from node {}. Instance Id: {}",
@@ -1,11 +1,14 @@
 use std::collections::{HashMap, HashSet};
 
+use bytes::Bytes;
+
 pub struct RBCState {
-    pub received_echo_count: HashMap<Vec<u8>, usize>,
-    pub received_ready_count: HashMap<Vec<u8>, usize>,
+    // Keyed by the content, which all votes for it share
+    pub received_echo_count: HashMap<Bytes, usize>,
+    pub received_ready_count: HashMap<Bytes, usize>,
 
-    pub echo_senders: HashMap<Vec<u8>, HashSet<usize>>,
-    pub ready_senders: HashMap<Vec<u8>, HashSet<usize>>,
+    pub echo_senders: HashMap<Bytes, HashSet<usize>>,
+    pub ready_senders: HashMap<Bytes, HashSet<usize>>,
 
     pub first_ready: bool,
     pub second_ready: bool,
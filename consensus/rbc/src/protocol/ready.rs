@@ -1,4 +1,5 @@
 use async_recursion::async_recursion;
+use bytes::Bytes;
 use types::{Msg, ProtMsg};
 
 use crate::Context;
@@ -17,8 +18,9 @@ impl Context {
         // Use different types of messages like INIT, ECHO, .... for the Bracha's RBC implementation
         let protocol_msg = ProtMsg::Ready(msg, instance_id);
         // Broadcast the message to everyone
+        self.compression.account(&msg_content, self.num_nodes - 1);
         self.broadcast(protocol_msg).await;
-        self.ready_self(msg_content.clone(), instance_id).await;
+        self.ready_self(msg_content, instance_id).await;
     }
 
     #[async_recursion]
@@ -38,16 +40,17 @@ impl Context {
             return;
         }
 
+        let content = Bytes::from(msg.content);
         let senders = rbc_context
             .ready_senders
-            .entry(msg.content.clone())
+            .entry(content.clone())
             .or_default();
 
         // Only count if we haven't seen a ready from this sender for this message
         if senders.insert(msg.origin) {
             *rbc_context
                 .received_ready_count
-                .entry(msg.content.clone())
+                .entry(content.clone())
                 .or_default() += 1;
 
             // log::info!(
@@ -59,7 +62,7 @@ impl Context {
             // );
 
             // let count = self.received_ready_count.get(&msg.content).unwrap();
-            let mut mode_content: Option<Vec<u8>> = None;
+            let mut mode_content: Option<Bytes> = None;
             let mut max_count = 0;
 
             for (content, &count) in rbc_context.received_ready_count.iter() {
@@ -100,7 +103,7 @@ impl Context {
                 }; // rbc_context goes out of scope here
 
                 if should_terminate {
-                    self.terminate(msg.content.clone()).await;
+                    self.terminate(instance_id, content).await;
                 }
             }
         }
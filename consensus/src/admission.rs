@@ -0,0 +1,343 @@
+use std::{
+    collections::{BTreeSet, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use fnv::FnvHashMap;
+use types::Replica;
+
+// Limits on what a peer can make this node keep
+#[derive(Debug, Clone, Copy)]
+pub struct AdmissionConfig {
+    // Instances of a dealer accepted beyond the last one delivered in sequence
+    pub window: usize,
+    // Undelivered instances of each dealer a peer may open. Defaults to four.
+    pub open_quota: Option<usize>,
+    // Messages per second of each peer, with bursts of up to a second's worth. Unlimited if absent.
+    pub rate: Option<f64>,
+    // Messages of each peer held back until the window, quota or rate lets them in
+    pub park: usize,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        AdmissionConfig {
+            window: 64,
+            open_quota: None,
+            rate: None,
+            park: 1024,
+        }
+    }
+}
+
+// Why a message was held back before reaching the protocol
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum DropReason {
+    Rate,
+    // Too far ahead of what the dealer delivered
+    Window,
+    Quota,
+    // Instance id of no dealer, dropped
+    Invalid,
+    // Held back while the buffer of the peer was full, dropped
+    Park,
+}
+
+// Admission of authenticated messages into the state of a protocol, so that a faulty peer cannot make it allocate
+// instances without bound. Instance ids are threshold * dealer + k with k counting from 1.
+// The network already acknowledged the messages held back, so they are parked rather than dropped and handed back
+// once deliveries move the window or release quota, or the peer has tokens again.
+pub struct Admission<T> {
+    window: usize,
+    open_quota: usize,
+    rate: Option<f64>,
+    num_nodes: usize,
+    threshold: usize,
+    // Delivered instances of each dealer: all k up to the first, and those in the set beyond it
+    delivered: FnvHashMap<Replica, (usize, BTreeSet<usize>)>,
+    // Undelivered instances each peer opened, by dealer. A faulty dealer whose instances never terminate then only
+    // takes the quota peers have for it, and the instances of other dealers still open.
+    opened: FnvHashMap<(Replica, Replica), HashSet<usize>>,
+    // Tokens of each peer and when they were last refilled
+    buckets: FnvHashMap<Replica, (f64, Instant)>,
+    park: usize,
+    parked: FnvHashMap<Replica, VecDeque<T>>,
+    // Whether deliveries happened since the parked messages were last handed back
+    moved: bool,
+    // When peers with messages parked for the rate have a token again
+    refills: FnvHashMap<Replica, Instant>,
+    dropped: FnvHashMap<(Replica, DropReason), u64>,
+}
+
+impl<T> Admission<T> {
+    pub fn new(config: AdmissionConfig, num_nodes: usize, threshold: usize) -> Admission<T> {
+        Admission {
+            window: config.window,
+            open_quota: config.open_quota.unwrap_or(4),
+            rate: config.rate,
+            num_nodes,
+            threshold,
+            delivered: FnvHashMap::default(),
+            opened: FnvHashMap::default(),
+            buckets: FnvHashMap::default(),
+            park: config.park,
+            parked: FnvHashMap::default(),
+            moved: false,
+            refills: FnvHashMap::default(),
+            dropped: FnvHashMap::default(),
+        }
+    }
+
+    // The message if it is to be processed now. Messages of an instance the node keeps state for are only rate
+    // limited, while those opening an instance must fall in the window of its dealer and the quota the peer has for it.
+    // Others are parked, and dropped only if the peer already has its share of parked messages.
+    pub fn admit(&mut self, peer: Replica, instance_id: Option<usize>, known: bool, msg: T) -> Option<T> {
+        let reason = match self.check(peer, instance_id, known) {
+            Ok(()) => return Some(msg),
+            Err(reason) => reason,
+        };
+        let parked = self.parked.entry(peer).or_default();
+        let reason = if reason == DropReason::Invalid {
+            reason
+        } else if parked.len() < self.park {
+            parked.push_back(msg);
+            if reason == DropReason::Rate {
+                let at = self.refill(peer);
+                self.refills.insert(peer, at);
+            }
+            reason
+        } else {
+            DropReason::Park
+        };
+        let count = self.dropped.entry((peer, reason)).or_default();
+        *count += 1;
+        // The first of each kind says who, the counters say how many
+        if *count == 1 {
+            log::warn!("Holding back or dropping messages of node {} ({:?}), instance {:?}", peer, reason, instance_id);
+        }
+        None
+    }
+
+    // Parked messages that may pass now, in the order they came. Deliveries hand back all of them, a refill as many
+    // messages of the peer as it has tokens. Those still held back are parked again when offered to admit.
+    pub fn released(&mut self) -> Vec<T> {
+        let mut released = Vec::new();
+        if std::mem::take(&mut self.moved) {
+            self.refills.clear();
+            for parked in self.parked.values_mut() {
+                released.extend(parked.drain(..));
+            }
+            return released;
+        }
+        let now = Instant::now();
+        let due: Vec<Replica> = self.refills.iter().filter(|(_, at)| **at <= now).map(|(peer, _)| *peer).collect();
+        for peer in due {
+            self.refills.remove(&peer);
+            let (tokens, rate) = match (self.rate, self.buckets.get(&peer)) {
+                (Some(rate), Some((tokens, refilled))) => {
+                    (tokens + rate * now.duration_since(*refilled).as_secs_f64(), rate)
+                }
+                _ => continue,
+            };
+            if let Some(parked) = self.parked.get_mut(&peer) {
+                let take = (tokens.max(1.0) as usize).min(parked.len());
+                released.extend(parked.drain(..take));
+                if !parked.is_empty() {
+                    self.refills.insert(peer, now + Duration::from_secs_f64(1.0 / rate));
+                }
+            }
+        }
+        released
+    }
+
+    // When the next peer with messages parked for the rate gets a token
+    pub fn next_refill(&self) -> Option<Instant> {
+        self.refills.values().min().copied()
+    }
+
+    fn check(&mut self, peer: Replica, instance_id: Option<usize>, known: bool) -> Result<(), DropReason> {
+        if !self.take_token(peer) {
+            return Err(DropReason::Rate);
+        }
+        let instance_id = match instance_id {
+            Some(instance_id) if !known => instance_id,
+            _ => return Ok(()),
+        };
+        let (dealer, k) = (instance_id / self.threshold, instance_id % self.threshold);
+        let (floor, delivered) = match self.delivered.get(&dealer) {
+            Some((floor, above)) => (*floor, k <= *floor || above.contains(&k)),
+            None => (0, false),
+        };
+        if dealer >= self.num_nodes || k == 0 {
+            return Err(DropReason::Invalid);
+        }
+        if k > floor + self.window {
+            return Err(DropReason::Window);
+        }
+        // Late messages of delivered instances hold no quota, there is no delivery left to release it
+        if delivered {
+            return Ok(());
+        }
+        let opened = self.opened.entry((peer, dealer)).or_default();
+        if !opened.contains(&instance_id) && opened.len() >= self.open_quota {
+            return Err(DropReason::Quota);
+        }
+        opened.insert(instance_id);
+        Ok(())
+    }
+
+    fn take_token(&mut self, peer: Replica) -> bool {
+        let rate = match self.rate {
+            Some(rate) => rate,
+            None => return true,
+        };
+        let burst = rate.max(1.0);
+        let now = Instant::now();
+        let (tokens, refilled) = self.buckets.entry(peer).or_insert((burst, now));
+        *tokens = (*tokens + rate * now.duration_since(*refilled).as_secs_f64()).min(burst);
+        *refilled = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+
+    fn refill(&self, peer: Replica) -> Instant {
+        match (self.rate, self.buckets.get(&peer)) {
+            (Some(rate), Some((tokens, refilled))) => {
+                *refilled + Duration::from_secs_f64((1.0 - tokens).max(0.0) / rate)
+            }
+            _ => Instant::now(),
+        }
+    }
+
+    // Move the window of the dealer and release the quota the instance took
+    pub fn delivered(&mut self, instance_id: usize) {
+        self.moved = true;
+        let (dealer, k) = (instance_id / self.threshold, instance_id % self.threshold);
+        let (floor, above) = self.delivered.entry(dealer).or_default();
+        if k > *floor {
+            above.insert(k);
+        }
+        while above.remove(&(*floor + 1)) {
+            *floor += 1;
+        }
+        for ((_, opener), opened) in self.opened.iter_mut() {
+            if *opener == dealer {
+                opened.remove(&instance_id);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Admission<T> {
+    fn drop(&mut self) {
+        if self.dropped.is_empty() {
+            return;
+        }
+        let mut counters: Vec<_> = self.dropped.iter().collect();
+        counters.sort_by_key(|((peer, reason), _)| (*peer, format!("{:?}", reason)));
+        for ((peer, reason), count) in counters {
+            log::warn!("Held back or dropped {} messages of node {} ({:?})", count, peer, reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLD: usize = 10000;
+
+    fn admission(window: usize, open_quota: usize, rate: Option<f64>, park: usize) -> Admission<usize> {
+        let config = AdmissionConfig {
+            window,
+            open_quota: Some(open_quota),
+            rate,
+            park,
+        };
+        Admission::new(config, 4, THRESHOLD)
+    }
+
+    // Offer a message naming the instance and carrying its id
+    fn offer(admission: &mut Admission<usize>, peer: Replica, instance_id: usize) -> Option<usize> {
+        admission.admit(peer, Some(instance_id), false, instance_id)
+    }
+
+    #[test]
+    fn parks_messages_beyond_the_window_until_deliveries_move_it() {
+        let mut admission = admission(2, 16, None, 8);
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 2), Some(THRESHOLD + 2));
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 3), None);
+        assert!(admission.released().is_empty());
+
+        // Delivering out of order does not move the window
+        admission.delivered(THRESHOLD + 2);
+        let released = admission.released();
+        assert_eq!(released, vec![THRESHOLD + 3]);
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 3), None);
+
+        admission.delivered(THRESHOLD + 1);
+        assert_eq!(admission.released(), vec![THRESHOLD + 3]);
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 3), Some(THRESHOLD + 3));
+    }
+
+    #[test]
+    fn drops_instances_of_no_dealer() {
+        let mut admission = admission(2, 16, None, 8);
+        assert_eq!(offer(&mut admission, 0, 4 * THRESHOLD + 1), None);
+        assert_eq!(offer(&mut admission, 0, THRESHOLD), None);
+        admission.delivered(1);
+        assert!(admission.released().is_empty());
+    }
+
+    #[test]
+    fn charges_the_quota_to_the_dealer() {
+        let mut admission = admission(64, 1, None, 8);
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 1), Some(THRESHOLD + 1));
+        // A second instance of dealer 1 exceeds the quota peer 0 has for it
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 2), None);
+        // Instances of other dealers and the quota of other peers are not affected
+        assert_eq!(offer(&mut admission, 0, 2 * THRESHOLD + 1), Some(2 * THRESHOLD + 1));
+        assert_eq!(offer(&mut admission, 1, THRESHOLD + 2), Some(THRESHOLD + 2));
+        // Nor are instances the node keeps state for
+        assert_eq!(admission.admit(0, Some(THRESHOLD + 3), true, 3), Some(3));
+
+        admission.delivered(THRESHOLD + 1);
+        assert_eq!(admission.released(), vec![THRESHOLD + 2]);
+        assert_eq!(offer(&mut admission, 0, THRESHOLD + 2), Some(THRESHOLD + 2));
+    }
+
+    #[test]
+    fn drops_what_does_not_fit_in_the_parked_messages_of_the_peer() {
+        let mut admission = admission(1, 16, None, 2);
+        for k in 2..5 {
+            assert_eq!(offer(&mut admission, 0, THRESHOLD + k), None);
+        }
+        // Other peers park their own messages
+        assert_eq!(offer(&mut admission, 1, THRESHOLD + 2), None);
+        admission.delivered(2 * THRESHOLD + 1);
+        let mut released = admission.released();
+        released.sort();
+        assert_eq!(released, vec![THRESHOLD + 2, THRESHOLD + 2, THRESHOLD + 3]);
+    }
+
+    #[test]
+    fn releases_messages_parked_for_the_rate_once_tokens_refill() {
+        let mut admission = admission(64, 16, Some(2.0), 8);
+        assert_eq!(admission.admit(0, None, true, 1), Some(1));
+        assert_eq!(admission.admit(0, None, true, 2), Some(2));
+        assert_eq!(admission.admit(0, None, true, 3), None);
+        // Other peers have tokens of their own
+        assert_eq!(admission.admit(1, None, true, 4), Some(4));
+
+        let refill = admission.next_refill().unwrap();
+        assert!(refill <= Instant::now() + Duration::from_millis(500));
+        assert!(admission.released().is_empty());
+
+        std::thread::sleep(Duration::from_millis(600));
+        assert_eq!(admission.released(), vec![3]);
+        assert_eq!(admission.next_refill(), None);
+    }
+}
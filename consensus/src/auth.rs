@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use config::Node;
+use crypto::hash::{do_hash, Hash};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use types::Replica;
+
+// How nodes authenticate the messages they send each other
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AuthKind {
+    // Pairwise symmetric keys from the sk_map of the config
+    Mac,
+    // ed25519 signatures checked against the pk_map of the config
+    Ed25519,
+}
+
+impl AuthKind {
+    pub fn from_flag(auth: &str) -> Option<AuthKind> {
+        match auth {
+            "mac" => Some(AuthKind::Mac),
+            "ed25519" => Some(AuthKind::Ed25519),
+            _ => None,
+        }
+    }
+}
+
+// A protocol message on the wire. The tag covers the digest of the serialized body, so the body is serialized and
+// hashed once per broadcast, and receivers check the tag on the received bytes before deserializing them. The
+// envelopes of a broadcast share one buffer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Envelope {
+    pub sender: Replica,
+    pub body: Bytes,
+    pub tag: Vec<u8>,
+    // Set on frames whose body is a batch of envelopes for one destination
+    pub batch: bool,
+    // Set on envelopes taken out of a batch whose MAC checked out. Never read from the wire.
+    #[serde(skip)]
+    verified: bool,
+}
+
+// A message serialized and hashed once, to be tagged for each destination
+pub struct Sealed {
+    body: Bytes,
+    digest: Hash,
+    // Signatures do not depend on the destination
+    signature: Option<Vec<u8>>,
+}
+
+// Tags outgoing messages and checks incoming ones
+#[derive(Clone)]
+pub struct Authenticator {
+    myid: Replica,
+    keys: Keys,
+    // MACs are left to the batches of the transport, one per frame
+    batched: bool,
+}
+
+#[derive(Clone)]
+enum Keys {
+    // A MAC only convinces its receiver, and every pair of nodes needs a shared secret
+    Mac(HashMap<Replica, Vec<u8>>),
+    // A signature convinces anyone, so messages can be forwarded as evidence
+    Ed25519 {
+        signing_key: SigningKey,
+        public_keys: HashMap<Replica, VerifyingKey>,
+    },
+}
+
+impl Authenticator {
+    pub fn new(kind: AuthKind, config: &Node, batched: bool) -> Result<Authenticator, String> {
+        let keys = match kind {
+            AuthKind::Mac => Keys::Mac(config.sk_map.clone().into_iter().collect()),
+            AuthKind::Ed25519 => {
+                // The secret key may be stored alone or followed by the public key
+                let secret: [u8; 32] = config
+                    .secret_key_bytes
+                    .get(..32)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| format!("Secret key of node {} is not an ed25519 key", config.id))?;
+                let mut public_keys = HashMap::default();
+                for (replica, pk) in config.pk_map.iter() {
+                    let bytes: [u8; 32] = pk
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| format!("Public key of node {} is not a 32-byte ed25519 key", replica))?;
+                    let key = VerifyingKey::from_bytes(&bytes)
+                        .map_err(|e| format!("Invalid public key of node {}: {}", replica, e))?;
+                    public_keys.insert(*replica, key);
+                }
+                Keys::Ed25519 {
+                    signing_key: SigningKey::from_bytes(&secret),
+                    public_keys,
+                }
+            }
+        };
+        Ok(Authenticator {
+            myid: config.id,
+            keys,
+            batched,
+        })
+    }
+
+    // Whether third parties can check the tags, so that messages can serve as evidence
+    pub fn transferable(&self) -> bool {
+        matches!(self.keys, Keys::Ed25519 { .. })
+    }
+
+    pub fn seal<T: Serialize>(&self, msg: &T) -> Sealed {
+        let body = Bytes::from(bincode::serialize(msg).expect("Failed to serialize object"));
+        let digest = do_hash(&body);
+        let signature = match &self.keys {
+            Keys::Mac(_) => None,
+            Keys::Ed25519 { signing_key, .. } => Some(signing_key.sign(&digest).to_bytes().to_vec()),
+        };
+        Sealed { body, digest, signature }
+    }
+
+    // Tag a sealed message for the given destination
+    pub fn envelope(&self, sealed: &Sealed, replica: Replica) -> Envelope {
+        let tag = match (&self.keys, &sealed.signature) {
+            (Keys::Ed25519 { .. }, Some(signature)) => signature.clone(),
+            (Keys::Mac(_), _) if self.batched => Vec::new(),
+            (Keys::Mac(sec_key_map), _) => match sec_key_map.get(&replica) {
+                Some(sec_key) => mac(sec_key, &sealed.digest),
+                None => {
+                    log::warn!("No MAC key shared with node {}", replica);
+                    Vec::new()
+                }
+            },
+            (Keys::Ed25519 { .. }, None) => unreachable!("Messages are signed when sealed"),
+        };
+        Envelope {
+            sender: self.myid,
+            body: sealed.body.clone(),
+            tag,
+            batch: false,
+            verified: false,
+        }
+    }
+
+    // Coalesce envelopes for one destination into a single frame. With MACs the frame carries the only tag, while
+    // signed envelopes keep their own signatures so they can still serve as evidence.
+    pub fn batch(&self, replica: Replica, envelopes: &[Envelope]) -> Envelope {
+        let body = Bytes::from(bincode::serialize(envelopes).expect("Failed to serialize object"));
+        let tag = match &self.keys {
+            Keys::Mac(sec_key_map) => match sec_key_map.get(&replica) {
+                Some(sec_key) => mac(sec_key, &do_hash(&body)),
+                None => {
+                    log::warn!("No MAC key shared with node {}", replica);
+                    Vec::new()
+                }
+            },
+            Keys::Ed25519 { .. } => Vec::new(),
+        };
+        Envelope {
+            sender: self.myid,
+            body,
+            tag,
+            batch: true,
+            verified: false,
+        }
+    }
+
+    // Split a received frame into the envelopes it carries
+    pub fn unbatch(&self, envelope: Envelope) -> Vec<Envelope> {
+        if !envelope.batch {
+            return vec![envelope];
+        }
+        let mac = matches!(self.keys, Keys::Mac(_));
+        if mac && !self.check_tag(&envelope) {
+            log::warn!("Authentication of a batch from node {} failed.", envelope.sender);
+            return Vec::new();
+        }
+        let envelopes: Vec<Envelope> = match bincode::deserialize(&envelope.body) {
+            Ok(envelopes) => envelopes,
+            Err(e) => {
+                log::warn!("Batch from node {} does not deserialize: {}", envelope.sender, e);
+                return Vec::new();
+            }
+        };
+        envelopes
+            .into_iter()
+            .filter(|inner| {
+                // A batch vouches for its own sender only, and batches do not nest
+                let valid = inner.sender == envelope.sender && !inner.batch;
+                if !valid {
+                    log::warn!("Batch from node {} carries a message it cannot vouch for", envelope.sender);
+                }
+                valid
+            })
+            .map(|inner| Envelope { verified: mac, ..inner })
+            .collect()
+    }
+
+    // Split a received frame and check the tag of every envelope it carries, so that each is known to come from its
+    // sender. The envelopes come back marked as verified.
+    pub fn authenticate(&self, envelope: Envelope) -> Vec<Envelope> {
+        self.unbatch(envelope)
+            .into_iter()
+            .filter(|envelope| {
+                let valid = self.verify(envelope);
+                if !valid {
+                    log::warn!("Authentication of a message from node {} failed.", envelope.sender);
+                }
+                valid
+            })
+            .map(|envelope| Envelope { verified: true, ..envelope })
+            .collect()
+    }
+
+    // Seal and tag a message for a single destination
+    pub fn wrap<T: Serialize>(&self, msg: &T, replica: Replica) -> Envelope {
+        self.envelope(&self.seal(msg), replica)
+    }
+
+    // Check the tag of a received message against the key of its claimed sender
+    pub fn verify(&self, envelope: &Envelope) -> bool {
+        if envelope.verified {
+            return true;
+        }
+        // Frames are only checked by unbatch
+        !envelope.batch && self.check_tag(envelope)
+    }
+
+    fn check_tag(&self, envelope: &Envelope) -> bool {
+        let digest = do_hash(&envelope.body);
+        match &self.keys {
+            Keys::Mac(sec_key_map) => match sec_key_map.get(&envelope.sender) {
+                Some(sec_key) => {
+                    let mut verifier = new_mac(sec_key);
+                    verifier.update(&digest);
+                    verifier.verify_slice(&envelope.tag).is_ok()
+                }
+                None => {
+                    log::warn!("No MAC key shared with node {}", envelope.sender);
+                    false
+                }
+            },
+            Keys::Ed25519 { public_keys, .. } => {
+                let public_key = match public_keys.get(&envelope.sender) {
+                    Some(public_key) => public_key,
+                    None => {
+                        log::warn!("No public key of node {}", envelope.sender);
+                        return false;
+                    }
+                };
+                match Signature::from_slice(&envelope.tag) {
+                    Ok(signature) => public_key.verify(&digest, &signature).is_ok(),
+                    Err(_) => false,
+                }
+            }
+        }
+    }
+
+    // Verify and deserialize a received message
+    pub fn open<T: DeserializeOwned>(&self, envelope: &Envelope) -> Option<T> {
+        if !self.verify(envelope) {
+            return None;
+        }
+        match bincode::deserialize(&envelope.body) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                log::warn!("Authenticated message from node {} does not deserialize: {}", envelope.sender, e);
+                None
+            }
+        }
+    }
+}
+
+fn new_mac(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length")
+}
+
+fn mac(key: &[u8], digest: &Hash) -> Vec<u8> {
+    let mut mac = new_mac(key);
+    mac.update(digest);
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,72 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use types::Replica;
+
+use crate::{Envelope, SyncReport};
+
+// Evidence that the dealer of an RBC instance misbehaved. It consists of the dealer's own authenticated messages,
+// so it convinces other nodes when messages are signed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Evidence {
+    // Two messages of the dealer binding the instance to different commitments
+    Equivocation(Envelope, Envelope),
+    // An INIT of the dealer whose share does not match the commitment it carries
+    InvalidInit(Envelope),
+}
+
+impl Evidence {
+    pub fn culprit(&self) -> Replica {
+        match self {
+            Evidence::Equivocation(first, _) => first.sender,
+            Evidence::InvalidInit(init) => init.sender,
+        }
+    }
+}
+
+// Nodes blamed in each RBC instance, with the evidence against them
+#[derive(Default)]
+pub struct BlameList {
+    evidence: BTreeMap<usize, HashMap<Replica, Evidence>>,
+}
+
+impl BlameList {
+    // Returns false if the culprit was already blamed in this instance
+    pub fn add(&mut self, instance_id: usize, evidence: Evidence) -> bool {
+        let blamed = self.evidence.entry(instance_id).or_default();
+        if blamed.contains_key(&evidence.culprit()) {
+            return false;
+        }
+        blamed.insert(evidence.culprit(), evidence);
+        true
+    }
+
+    pub fn is_blamed(&self, instance_id: usize, replica: Replica) -> bool {
+        self.evidence
+            .get(&instance_id)
+            .is_some_and(|blamed| blamed.contains_key(&replica))
+    }
+
+    // (instance id, culprit) pairs in instance order
+    pub fn report(&self) -> Vec<(usize, Replica)> {
+        let mut report = Vec::new();
+        for (instance_id, blamed) in self.evidence.iter() {
+            let mut culprits: Vec<Replica> = blamed.keys().copied().collect();
+            culprits.sort();
+            report.extend(culprits.into_iter().map(|culprit| (*instance_id, culprit)));
+        }
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.evidence.is_empty()
+    }
+}
+
+// Append the blame list to the payload reported to the syncer, which is the RBCSyncMsg of the syncer
+pub fn append_blame(payload: Vec<u8>, blame_list: &BlameList) -> Vec<u8> {
+    if blame_list.is_empty() {
+        return payload;
+    }
+    SyncReport::Blame(blame_list.report()).append(payload)
+}
@@ -0,0 +1,115 @@
+// First byte of a framed payload
+const RAW: u8 = 0;
+const ZSTD: u8 = 1;
+const LZ4: u8 = 2;
+
+const ZSTD_LEVEL: i32 = 3;
+
+// How dealers compress the payloads they broadcast. Frames name their codec, so nodes read payloads of any kind.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CompressionKind {
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl CompressionKind {
+    pub fn from_flag(compression: &str) -> Option<CompressionKind> {
+        match compression {
+            "none" => Some(CompressionKind::None),
+            "zstd" => Some(CompressionKind::Zstd),
+            "lz4" => Some(CompressionKind::Lz4),
+            _ => None,
+        }
+    }
+}
+
+// Frames payloads and counts the bytes this node sent before and after compression
+pub struct Compression {
+    kind: CompressionKind,
+    // Largest payload a frame may claim to decompress to. The decoders allocate the claimed length up front.
+    max_payload: usize,
+    raw_bytes: u64,
+    sent_bytes: u64,
+}
+
+impl Compression {
+    pub fn new(kind: CompressionKind, max_payload: usize) -> Compression {
+        Compression {
+            kind,
+            max_payload,
+            raw_bytes: 0,
+            sent_bytes: 0,
+        }
+    }
+
+    // A payload that does not shrink is sent as is
+    pub fn compress(&self, payload: &[u8]) -> Vec<u8> {
+        let compressed = match self.kind {
+            CompressionKind::None => None,
+            CompressionKind::Zstd => zstd::bulk::compress(payload, ZSTD_LEVEL).ok().map(|data| (ZSTD, data)),
+            CompressionKind::Lz4 => Some((LZ4, lz4_flex::block::compress(payload))),
+        };
+        match compressed {
+            Some((codec, data)) if data.len() + 8 < payload.len() => {
+                let mut frame = Vec::with_capacity(9 + data.len());
+                frame.push(codec);
+                frame.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+                frame.extend_from_slice(&data);
+                frame
+            }
+            _ => {
+                let mut frame = Vec::with_capacity(1 + payload.len());
+                frame.push(RAW);
+                frame.extend_from_slice(payload);
+                frame
+            }
+        }
+    }
+
+    // None if the frame is malformed, which only a faulty dealer produces
+    pub fn decompress(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let (&codec, rest) = frame.split_first()?;
+        if codec == RAW {
+            return Some(rest.to_vec());
+        }
+        let raw_len = u64::from_le_bytes(rest.get(..8)?.try_into().ok()?) as usize;
+        if raw_len > self.max_payload {
+            return None;
+        }
+        let payload = match codec {
+            ZSTD => zstd::bulk::decompress(&rest[8..], raw_len).ok()?,
+            LZ4 => lz4_flex::block::decompress(&rest[8..], raw_len).ok()?,
+            _ => return None,
+        };
+        (payload.len() == raw_len).then_some(payload)
+    }
+
+    // Count a framed payload sent to the given number of nodes
+    pub fn account(&mut self, frame: &[u8], copies: usize) {
+        let raw_len = match frame.split_first() {
+            Some((&RAW, rest)) => rest.len(),
+            Some((_, rest)) => rest
+                .get(..8)
+                .and_then(|len| len.try_into().ok())
+                .map(|len| u64::from_le_bytes(len) as usize)
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.raw_bytes += (raw_len * copies) as u64;
+        self.sent_bytes += (frame.len() * copies) as u64;
+    }
+}
+
+impl Drop for Compression {
+    fn drop(&mut self) {
+        if self.raw_bytes > 0 {
+            log::info!(
+                "Sent {} bytes of payloads as {} bytes with {:?} compression",
+                self.raw_bytes,
+                self.sent_bytes,
+                self.kind
+            );
+        }
+    }
+}
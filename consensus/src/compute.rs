@@ -0,0 +1,41 @@
+use crypto::hash::Hash;
+use rayon::prelude::*;
+use tokio::sync::mpsc::{Sender, UnboundedSender};
+
+use crate::HashKind;
+
+// Run CPU-heavy work (encoding, interpolation, Merkle trees) on tokio's blocking pool and post the result to the
+// event loop of the context, which keeps processing messages in the meantime. The result is dropped if the context
+// exited before the job finished.
+pub fn offload<T, F>(results: &UnboundedSender<T>, job: F)
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let results = results.clone();
+    tokio::task::spawn_blocking(move || {
+        let _ = results.send(job());
+    });
+}
+
+// Offload a job that hands over results as it goes, and a last one when done. They go through a bounded channel, so
+// the job waits while the event loop has not taken them and never holds more than the channel does.
+pub fn offload_streamed<T, F>(results: &Sender<T>, job: F)
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn Fn(T)) -> T + Send + 'static,
+{
+    let results = results.clone();
+    tokio::task::spawn_blocking(move || {
+        let emit = |result| {
+            let _ = results.blocking_send(result);
+        };
+        let last = job(&emit);
+        emit(last);
+    });
+}
+
+// Hash every shard, in parallel across the rayon pool
+pub fn hash_shards(hash: HashKind, shards: &[Vec<u8>]) -> Vec<Hash> {
+    shards.par_iter().map(|shard| hash.digest(shard.as_slice())).collect()
+}
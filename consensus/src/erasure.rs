@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use reed_solomon_rs::fec::fec::{Share, FEC};
+
+use crate::{decode_with_errors, get_shards, reconstruct_data, GF8_MAX_SHARDS};
+
+// Erasure codes the coded protocols can run with
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CodecKind {
+    // reed_solomon_erasure, over GF(2^8) up to 256 shards and over GF(2^16) beyond
+    ReedSolomon,
+    // reed_solomon_rs FEC over GF(2^8)
+    Fec,
+}
+
+impl CodecKind {
+    pub fn from_flag(codec: &str) -> Option<CodecKind> {
+        match codec {
+            "rs" => Some(CodecKind::ReedSolomon),
+            "fec" => Some(CodecKind::Fec),
+            _ => None,
+        }
+    }
+}
+
+// A code of data_shards + parity_shards equally long shards, any data_shards of which determine the data.
+// Shard i goes to node i. The shards encode the data behind its length, so decoding returns exactly the encoded bytes.
+pub trait ErasureCode: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn data_shards(&self) -> usize;
+    fn parity_shards(&self) -> usize;
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, String>;
+    // Fill in the missing shards from at least data_shards present ones and return the data
+    fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<Vec<u8>, String>;
+    // Decode the data when up to (present - data_shards) / 2 of the present shards are corrupted
+    fn decode_with_errors(&self, shards: &[Option<Vec<u8>>]) -> Result<Vec<u8>, String>;
+}
+
+// Length of the header in front of the encoded data
+pub const LENGTH_HEADER: usize = 8;
+
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(LENGTH_HEADER + data.len());
+    framed.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    framed.extend_from_slice(data);
+    framed
+}
+
+// Strip the length header and the padding of the codec
+fn unframe(mut decoded: Vec<u8>) -> Result<Vec<u8>, String> {
+    if decoded.len() < LENGTH_HEADER {
+        return Err(format!("Decoded {} bytes, shorter than the length header", decoded.len()));
+    }
+    let mut header = [0u8; LENGTH_HEADER];
+    header.copy_from_slice(&decoded[..LENGTH_HEADER]);
+    let length = u64::from_le_bytes(header);
+    if length > (decoded.len() - LENGTH_HEADER) as u64 {
+        return Err(format!(
+            "Length header claims {} bytes but only {} were decoded",
+            length,
+            decoded.len() - LENGTH_HEADER
+        ));
+    }
+    decoded.drain(..LENGTH_HEADER);
+    decoded.truncate(length as usize);
+    Ok(decoded)
+}
+
+pub fn new_codec(kind: CodecKind, data_shards: usize, parity_shards: usize) -> Result<Arc<dyn ErasureCode>, String> {
+    match kind {
+        CodecKind::ReedSolomon => Ok(Arc::new(ReedSolomonCode {
+            data_shards,
+            parity_shards,
+        })),
+        CodecKind::Fec => {
+            let total = data_shards + parity_shards;
+            if total > GF8_MAX_SHARDS {
+                return Err(format!(
+                    "The FEC codec supports at most {} shards, found {}. Use the rs codec instead",
+                    GF8_MAX_SHARDS, total
+                ));
+            }
+            FEC::new(data_shards, total).map_err(|e| format!("{:?}", e))?;
+            Ok(Arc::new(FecCode {
+                required: data_shards,
+                total,
+            }))
+        }
+    }
+}
+
+pub struct ReedSolomonCode {
+    pub data_shards: usize,
+    pub parity_shards: usize,
+}
+
+impl ErasureCode for ReedSolomonCode {
+    fn name(&self) -> &'static str {
+        "rs"
+    }
+
+    fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        Ok(get_shards(frame(data), self.data_shards, self.parity_shards))
+    }
+
+    fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<Vec<u8>, String> {
+        reconstruct_data(shards, self.data_shards, self.parity_shards).map_err(|e| format!("{:?}", e))?;
+        let mut message = Vec::new();
+        for shard in shards.iter().take(self.data_shards) {
+            message.extend_from_slice(shard.as_ref().unwrap());
+        }
+        unframe(message)
+    }
+
+    fn decode_with_errors(&self, shards: &[Option<Vec<u8>>]) -> Result<Vec<u8>, String> {
+        let decoded = decode_with_errors(shards, self.data_shards, self.parity_shards).map_err(|e| format!("{:?}", e))?;
+        unframe(decoded)
+    }
+}
+
+pub struct FecCode {
+    pub required: usize,
+    pub total: usize,
+}
+
+impl ErasureCode for FecCode {
+    fn name(&self) -> &'static str {
+        "fec"
+    }
+
+    fn data_shards(&self) -> usize {
+        self.required
+    }
+
+    fn parity_shards(&self) -> usize {
+        self.total - self.required
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        let f = FEC::new(self.required, self.total).map_err(|e| format!("{:?}", e))?;
+        let mut shards: Vec<Vec<u8>> = vec![Vec::new(); self.total];
+        f.encode(&frame(data), |s: Share| {
+            let number = s.number;
+            shards[number] = s.data;
+        })
+        .map_err(|e| format!("{:?}", e))?;
+        Ok(shards)
+    }
+
+    fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<Vec<u8>, String> {
+        let data = self.decode_with_errors(shards)?;
+        let encoded = self.encode(&data)?;
+        for (slot, shard) in shards.iter_mut().zip(encoded.into_iter()) {
+            if slot.is_none() {
+                *slot = Some(shard);
+            }
+        }
+        Ok(data)
+    }
+
+    fn decode_with_errors(&self, shards: &[Option<Vec<u8>>]) -> Result<Vec<u8>, String> {
+        let f = FEC::new(self.required, self.total).map_err(|e| format!("{:?}", e))?;
+        let shares: Vec<Share> = shards
+            .iter()
+            .enumerate()
+            .filter_map(|(number, shard)| {
+                shard.as_ref().map(|data| Share {
+                    number,
+                    data: data.clone(),
+                })
+            })
+            .collect();
+        let decoded = f.decode(vec![], shares).map_err(|e| e.to_string())?;
+        unframe(decoded)
+    }
+}
@@ -0,0 +1,71 @@
+use blake3::Hasher;
+use crypto::hash::Hash;
+use sha2::{Digest, Sha256};
+
+// Inputs at least this long are hashed across the rayon pool with BLAKE3
+const BLAKE3_PARALLEL_THRESHOLD: usize = 128 * 1024;
+
+// Hash functions the protocols can hash shards and payloads with. Both produce 32-byte digests.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum HashKind {
+    // sha2, which uses the SHA extensions of the CPU when it detects them at runtime
+    Sha256,
+    // BLAKE3 with SIMD, and multithreaded on large inputs
+    Blake3,
+}
+
+impl HashKind {
+    pub fn from_flag(hash: &str) -> Option<HashKind> {
+        match hash {
+            "sha256" => Some(HashKind::Sha256),
+            "blake3" => Some(HashKind::Blake3),
+            _ => None,
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Hash {
+        match self {
+            HashKind::Sha256 => Sha256::digest(data).into(),
+            HashKind::Blake3 => {
+                let mut hasher = Hasher::new();
+                if data.len() >= BLAKE3_PARALLEL_THRESHOLD {
+                    hasher.update_rayon(data);
+                } else {
+                    hasher.update(data);
+                }
+                hasher.finalize().into()
+            }
+        }
+    }
+
+    pub fn hasher(&self) -> StreamHasher {
+        match self {
+            HashKind::Sha256 => StreamHasher::Sha256(Sha256::new()),
+            HashKind::Blake3 => StreamHasher::Blake3(Hasher::new()),
+        }
+    }
+}
+
+// Digest of data fed in pieces, equal to the digest of their concatenation
+pub enum StreamHasher {
+    Sha256(Sha256),
+    Blake3(Hasher),
+}
+
+impl StreamHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            StreamHasher::Sha256(hasher) => hasher.update(data),
+            StreamHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> Hash {
+        match self {
+            StreamHasher::Sha256(hasher) => hasher.finalize().into(),
+            StreamHasher::Blake3(hasher) => hasher.finalize().into(),
+        }
+    }
+}
@@ -1,2 +1,47 @@
+mod admission;
+pub use admission::*;
+
+mod auth;
+pub use auth::*;
+
+mod blame;
+pub use blame::*;
+
 mod reed_solomon;
-pub use reed_solomon::*;
\ No newline at end of file
+pub use reed_solomon::*;
+
+mod compression;
+pub use compression::*;
+
+mod compute;
+pub use compute::*;
+
+mod hashing;
+pub use hashing::*;
+
+mod merkle;
+pub use merkle::*;
+
+mod erasure;
+pub use erasure::*;
+
+mod stripe;
+pub use stripe::*;
+
+mod oec;
+pub use oec::*;
+
+mod ordering;
+pub use ordering::*;
+
+mod report;
+pub use report::*;
+
+mod service;
+pub use service::*;
+
+mod transport;
+pub use transport::*;
+
+mod validator;
+pub use validator::*;
@@ -0,0 +1,191 @@
+use crypto::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::HashKind;
+
+// Leaves and inner nodes hash different prefixes first, so that no inner node doubles as a leaf
+const LEAF_PREFIX: [u8; 1] = [0];
+const INNER_PREFIX: [u8; 1] = [1];
+
+fn leaf(hash: HashKind, digest: &Hash) -> Hash {
+    let mut hasher = hash.hasher();
+    hasher.update(&LEAF_PREFIX);
+    hasher.update(digest);
+    hasher.finalize()
+}
+
+fn parent(hash: HashKind, left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = hash.hasher();
+    hasher.update(&INNER_PREFIX);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize()
+}
+
+// Levels of a tree over this many leaves
+fn depth(leaves: usize) -> usize {
+    leaves.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+// Merkle tree over the digests of the shards, hashed with the hash function of the run. The digests are padded with
+// zeros to a power of two.
+pub struct MerkleTree {
+    // Digests of the shards
+    digests: Vec<Hash>,
+    // Hashed leaves first and the root last
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(digests: Vec<Hash>, hash: HashKind) -> MerkleTree {
+        let mut digests = digests;
+        digests.resize(1 << depth(digests.len()), [0u8; 32]);
+        let leaves = digests.iter().map(|digest| leaf(hash, digest)).collect();
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| parent(hash, &pair[0], &pair[1]))
+                .collect();
+            levels.push(level);
+        }
+        MerkleTree { digests, levels }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels.last().unwrap()[0]
+    }
+
+    pub fn gen_proof(&self, index: usize) -> MerkleProof {
+        let mut path = Vec::with_capacity(self.levels.len() - 1);
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            path.push(level[position ^ 1]);
+            position >>= 1;
+        }
+        MerkleProof {
+            index,
+            item: self.digests[index],
+            path,
+            root: self.root(),
+        }
+    }
+}
+
+// Path from the digest of one shard to the root
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MerkleProof {
+    index: usize,
+    item: Hash,
+    path: Vec<Hash>,
+    root: Hash,
+}
+
+impl MerkleProof {
+    // Position of the shard in the tree
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn item(&self) -> Hash {
+        self.item
+    }
+
+    pub fn root(&self) -> Hash {
+        self.root
+    }
+
+    // Whether the path leads from the item at its position to the root of a tree over this many leaves. Shorter paths
+    // would start at an inner node, and positions beyond the leaves would alias those inside.
+    pub fn validate(&self, hash: HashKind, leaves: usize) -> bool {
+        if self.path.len() != depth(leaves) || self.index >= leaves {
+            return false;
+        }
+        let mut node = leaf(hash, &self.item);
+        let mut position = self.index;
+        for sibling in &self.path {
+            node = if position & 1 == 0 {
+                parent(hash, &node, sibling)
+            } else {
+                parent(hash, sibling, &node)
+            };
+            position >>= 1;
+        }
+        node == self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digests(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| HashKind::Sha256.digest(&[i as u8])).collect()
+    }
+
+    #[test]
+    fn proofs_validate_at_their_position() {
+        for n in [1, 4, 7, 16] {
+            let tree = MerkleTree::new(digests(n), HashKind::Blake3);
+            for index in 0..n {
+                let proof = tree.gen_proof(index);
+                assert_eq!(proof.index(), index);
+                assert_eq!(proof.root(), tree.root());
+                assert!(proof.validate(HashKind::Blake3, n));
+                // A tree hashed with one function does not validate under the other
+                assert!(!proof.validate(HashKind::Sha256, n));
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_fail() {
+        let tree = MerkleTree::new(digests(7), HashKind::Sha256);
+        let proof = tree.gen_proof(2);
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!moved.validate(HashKind::Sha256, 7));
+
+        let mut aliased = proof.clone();
+        aliased.index = 2 + 8;
+        assert!(!aliased.validate(HashKind::Sha256, 7));
+
+        let mut item = proof.clone();
+        item.item = digests(7)[3];
+        assert!(!item.validate(HashKind::Sha256, 7));
+
+        let mut path = proof;
+        path.path[0][0] ^= 1;
+        assert!(!path.validate(HashKind::Sha256, 7));
+    }
+
+    #[test]
+    fn shortened_and_inner_node_proofs_fail() {
+        let tree = MerkleTree::new(digests(8), HashKind::Sha256);
+        let proof = tree.gen_proof(2);
+        assert!(proof.validate(HashKind::Sha256, 8));
+        // A tree over fewer leaves is not as deep
+        assert!(!proof.validate(HashKind::Sha256, 4));
+
+        // The inner node over shards 2 and 3, passed off as the digest of a shard with the path above it
+        let inner = MerkleProof {
+            index: 1,
+            item: tree.levels[1][1],
+            path: proof.path[1..].to_vec(),
+            root: tree.root(),
+        };
+        assert!(!inner.validate(HashKind::Sha256, 8));
+        assert!(!inner.validate(HashKind::Sha256, 4));
+
+        let mut shortened = proof.clone();
+        shortened.path.pop();
+        assert!(!shortened.validate(HashKind::Sha256, 8));
+
+        let mut longer = proof;
+        longer.path.push([0u8; 32]);
+        assert!(!longer.validate(HashKind::Sha256, 8));
+    }
+}
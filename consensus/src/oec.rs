@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::ErasureCode;
+
+// Online error correction. Shares arrive one at a time and up to num_faults of them may be corrupted.
+// Once data_shards + num_faults + r shares are in, decoding tolerates r errors, and the output is accepted when at
+// least data_shards + num_faults received shares agree with its encoding. At least data_shards of those come from
+// honest nodes, so the output is the data honest nodes hold. A failed attempt is retried with the next share.
+pub struct OnlineDecoder {
+    codec: Arc<dyn ErasureCode>,
+    num_faults: usize,
+    // Shares grouped by length: faulty nodes cannot stall decoding of the honest group with shares of another length
+    groups: HashMap<usize, Vec<Option<Vec<u8>>>>,
+    counts: HashMap<usize, usize>,
+    senders: Vec<bool>,
+    output: Option<Vec<u8>>,
+    // Set while an attempt runs off the event loop, along with the groups that gained shares in the meantime
+    pending: bool,
+    stale: HashSet<usize>,
+}
+
+// A decoding attempt on a snapshot of one group of shares, which can run on the blocking pool
+pub struct DecodeAttempt {
+    codec: Arc<dyn ErasureCode>,
+    quorum: usize,
+    shards: Vec<Option<Vec<u8>>>,
+}
+
+impl DecodeAttempt {
+    // Returns the data if at least the quorum of received shares agrees with its encoding
+    pub fn run(self) -> Option<Vec<u8>> {
+        let count = self.shards.iter().filter(|shard| shard.is_some()).count();
+        let data = match self.codec.decode_with_errors(&self.shards) {
+            Ok(data) => data,
+            Err(e) => {
+                log::debug!("Online error correction failed with {} shares: {}, waiting for more", count, e);
+                return None;
+            }
+        };
+        let encoded = self.codec.encode(&data).ok()?;
+        let agreeing = self
+            .shards
+            .iter()
+            .zip(encoded.iter())
+            .filter(|(received, expected)| received.as_ref() == Some(*expected))
+            .count();
+        if agreeing < self.quorum {
+            log::debug!("Decoded data agrees with only {} of {} shares, waiting for more", agreeing, count);
+            return None;
+        }
+        Some(data)
+    }
+}
+
+impl OnlineDecoder {
+    pub fn new(codec: Arc<dyn ErasureCode>, num_faults: usize) -> OnlineDecoder {
+        let total = codec.data_shards() + codec.parity_shards();
+        OnlineDecoder {
+            codec,
+            num_faults,
+            groups: HashMap::default(),
+            counts: HashMap::default(),
+            senders: vec![false; total],
+            output: None,
+            pending: false,
+            stale: HashSet::default(),
+        }
+    }
+
+    // Add the share with the given index and decode in place. Returns true when this share completes decoding.
+    pub fn add_share(&mut self, index: usize, share: Vec<u8>) -> bool {
+        let mut attempt = self.offer_share(index, share);
+        let attempted = attempt.is_some();
+        while let Some(next) = attempt {
+            let output = next.run();
+            attempt = self.finish_attempt(output);
+        }
+        attempted && self.output.is_some()
+    }
+
+    // Add the share with the given index. Returns an attempt to run if this share gives enough shares to decode and
+    // no attempt is running already. Pass its outcome to finish_attempt.
+    pub fn offer_share(&mut self, index: usize, share: Vec<u8>) -> Option<DecodeAttempt> {
+        if self.output.is_some() || index >= self.senders.len() || self.senders[index] {
+            return None;
+        }
+        self.senders[index] = true;
+
+        let total = self.senders.len();
+        let length = share.len();
+        self.groups.entry(length).or_insert_with(|| vec![None; total])[index] = Some(share);
+        *self.counts.entry(length).or_default() += 1;
+
+        if self.pending {
+            self.stale.insert(length);
+            return None;
+        }
+        self.attempt(length)
+    }
+
+    // Record the outcome of the running attempt. Returns the next attempt if shares arrived while it ran.
+    pub fn finish_attempt(&mut self, output: Option<Vec<u8>>) -> Option<DecodeAttempt> {
+        self.pending = false;
+        if self.output.is_some() {
+            return None;
+        }
+        if output.is_some() {
+            self.output = output;
+            self.stale.clear();
+            return None;
+        }
+        let stale: Vec<usize> = self.stale.drain().collect();
+        let mut attempt = None;
+        for length in stale {
+            if attempt.is_none() {
+                attempt = self.attempt(length);
+            } else {
+                self.stale.insert(length);
+            }
+        }
+        attempt
+    }
+
+    fn attempt(&mut self, length: usize) -> Option<DecodeAttempt> {
+        let quorum = self.codec.data_shards() + self.num_faults;
+        if self.counts.get(&length).copied().unwrap_or(0) < quorum {
+            return None;
+        }
+        self.pending = true;
+        Some(DecodeAttempt {
+            codec: self.codec.clone(),
+            quorum,
+            shards: self.groups.get(&length).unwrap().clone(),
+        })
+    }
+
+    pub fn output(&self) -> Option<&Vec<u8>> {
+        self.output.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{new_codec, CodecKind};
+
+    // Code the data for n = 3t + 1 nodes, feed the shares in order with the corrupted ones changed, and return the
+    // output along with the data
+    fn online_decode(num_faults: usize, corrupted: &[usize]) -> (Option<Vec<u8>>, Vec<u8>) {
+        let codec = new_codec(CodecKind::ReedSolomon, num_faults + 1, 2 * num_faults).unwrap();
+        let data: Vec<u8> = (0..1100).map(|i| (i * 7 + 3) as u8).collect();
+        let shares = codec.encode(&data).unwrap();
+        let mut decoder = OnlineDecoder::new(codec, num_faults);
+        for (index, mut share) in shares.into_iter().enumerate() {
+            if corrupted.contains(&index) {
+                for byte in share.iter_mut() {
+                    *byte = byte.wrapping_add(1);
+                }
+            }
+            decoder.add_share(index, share);
+        }
+        (decoder.output().cloned(), data)
+    }
+
+    #[test]
+    fn decodes_with_up_to_t_corrupted_shares() {
+        // The corrupted shares come first, so every attempt before the last one sees them
+        for errors in 0..=3 {
+            let corrupted: Vec<usize> = (0..errors).collect();
+            let (output, data) = online_decode(3, &corrupted);
+            assert_eq!(output, Some(data));
+        }
+    }
+
+    #[test]
+    fn outputs_nothing_with_more_than_t_corrupted_shares() {
+        let (output, _) = online_decode(3, &[0, 1, 2, 3]);
+        assert_eq!(output, None);
+        let (output, _) = online_decode(3, &[2, 4, 6, 8, 9]);
+        assert_eq!(output, None);
+    }
+
+    #[test]
+    fn decodes_above_256_shares() {
+        // n = 259 codes over GF(2^16)
+        let (output, data) = online_decode(86, &[0, 1, 2]);
+        assert_eq!(output, Some(data));
+    }
+
+    #[test]
+    fn ignores_repeated_senders_and_other_lengths() {
+        let codec = new_codec(CodecKind::ReedSolomon, 2, 2).unwrap();
+        let data = b"online error correction".to_vec();
+        let shares = codec.encode(&data).unwrap();
+        let mut decoder = OnlineDecoder::new(codec, 1);
+        // A faulty node sends a share of another length and then tries to replace it
+        assert!(!decoder.add_share(0, vec![0; 3]));
+        assert!(!decoder.add_share(0, shares[0].clone()));
+        assert!(!decoder.add_share(1, shares[1].clone()));
+        assert!(!decoder.add_share(2, shares[2].clone()));
+        assert!(decoder.add_share(3, shares[3].clone()));
+        assert_eq!(decoder.output(), Some(&data));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+// Order in which terminated broadcast instances are handed to the application.
+// Instance ids are allocated as threshold * dealer + sequence number, starting from sequence number 1.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DeliveryMode {
+    // Deliver as soon as the instance terminates
+    Unordered,
+    // Deliver each dealer's broadcasts in sequence number order
+    Fifo,
+    // Deliver a broadcast only after all broadcasts it depends on
+    Causal,
+}
+
+impl DeliveryMode {
+    pub fn from_flag(mode: &str) -> Option<DeliveryMode> {
+        match mode {
+            "none" => Some(DeliveryMode::Unordered),
+            "fifo" => Some(DeliveryMode::Fifo),
+            "causal" => Some(DeliveryMode::Causal),
+            _ => None,
+        }
+    }
+}
+
+// Payload of a broadcast in causal mode. deps[k] is the number of broadcasts of dealer k
+// the dealer had delivered when it started this broadcast.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CausalPayload {
+    pub deps: Vec<usize>,
+    pub payload: Vec<u8>,
+}
+
+pub struct OrderedDelivery {
+    pub mode: DeliveryMode,
+    pub num_nodes: usize,
+    pub threshold: usize,
+
+    // Number of broadcasts delivered per dealer
+    pub delivered: Vec<usize>,
+    // Terminated but not yet deliverable broadcasts: dealer -> sequence number -> (deps, payload), without a payload
+    // if it was rejected
+    pub pending: HashMap<usize, BTreeMap<usize, (Vec<usize>, Option<Vec<u8>>)>>,
+}
+
+impl OrderedDelivery {
+    pub fn new(mode: DeliveryMode, num_nodes: usize, threshold: usize) -> OrderedDelivery {
+        OrderedDelivery {
+            mode,
+            num_nodes,
+            threshold,
+
+            delivered: vec![0; num_nodes],
+            pending: HashMap::default(),
+        }
+    }
+
+    // Attach the dependencies of a broadcast to its payload before dispersal
+    pub fn wrap(&self, payload: Vec<u8>) -> Vec<u8> {
+        if self.mode != DeliveryMode::Causal {
+            return payload;
+        }
+        let causal_payload = CausalPayload {
+            deps: self.delivered.clone(),
+            payload,
+        };
+        bincode::serialize(&causal_payload).expect("Failed to serialize causal payload")
+    }
+
+    // Id of the broadcast a dealer starts after instance max_id, None once its sequence numbers reached the threshold
+    pub fn next_instance(&self, max_id: usize) -> Option<usize> {
+        let next = max_id + 1;
+        if next % self.threshold == 0 {
+            return None;
+        }
+        Some(next)
+    }
+
+    // Register the output of a terminated instance, None if its payload was rejected. Returns the instances that
+    // became deliverable, in delivery order. A rejected instance takes its turn in the order of its dealer but is
+    // never handed out.
+    pub fn deliver(&mut self, instance_id: usize, data: Option<Vec<u8>>) -> Vec<(usize, Vec<u8>)> {
+        if self.mode == DeliveryMode::Unordered {
+            return data.map(|data| (instance_id, data)).into_iter().collect();
+        }
+        let dealer = instance_id / self.threshold;
+        let seq = instance_id % self.threshold;
+        if dealer >= self.num_nodes || seq == 0 {
+            log::warn!("Instance id {} is not one a dealer allocates, dropping output", instance_id);
+            return vec![];
+        }
+
+        let (deps, payload) = match (self.mode, data) {
+            (_, None) => (vec![0; self.num_nodes], None),
+            (DeliveryMode::Causal, Some(data)) => match bincode::deserialize::<CausalPayload>(&data) {
+                Ok(causal_payload) if causal_payload.deps.len() == self.num_nodes => {
+                    (causal_payload.deps, Some(causal_payload.payload))
+                }
+                _ => {
+                    // All honest nodes deliver the same malformed payload, so treating it as
+                    // independent keeps delivery consistent across nodes.
+                    log::warn!("Malformed causal payload for instance id {}, delivering without dependencies", instance_id);
+                    (vec![0; self.num_nodes], Some(data))
+                }
+            },
+            (_, Some(data)) => (vec![0; self.num_nodes], Some(data)),
+        };
+        self.pending.entry(dealer).or_default().insert(seq, (deps, payload));
+
+        // Keep delivering until no pending broadcast is ready
+        let mut output = Vec::new();
+        loop {
+            let mut progress = false;
+            for dealer in 0..self.num_nodes {
+                let next_seq = self.delivered[dealer] + 1;
+                let ready = match self.pending.get(&dealer).and_then(|queue| queue.get(&next_seq)) {
+                    Some((deps, _)) => self.deps_satisfied(dealer, deps),
+                    None => false,
+                };
+                if ready {
+                    let (_, payload) = self.pending.get_mut(&dealer).unwrap().remove(&next_seq).unwrap();
+                    self.delivered[dealer] = next_seq;
+                    if let Some(payload) = payload {
+                        output.push((dealer * self.threshold + next_seq, payload));
+                    }
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        output
+    }
+
+    fn deps_satisfied(&self, dealer: usize, deps: &[usize]) -> bool {
+        (0..self.num_nodes).all(|rep| rep == dealer || self.delivered[rep] >= deps[rep])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{validated, Compression, CompressionKind, Validator};
+
+    const THRESHOLD: usize = 10000;
+
+    fn ids(output: &[(usize, Vec<u8>)]) -> Vec<usize> {
+        output.iter().map(|(instance_id, _)| *instance_id).collect()
+    }
+
+    // Payloads of the form the validator expects start with 1
+    fn validator() -> Validator {
+        Arc::new(|payload: &[u8]| payload.first() == Some(&1))
+    }
+
+    fn output(payload: &[u8]) -> Option<Vec<u8>> {
+        let compression = Compression::new(CompressionKind::None, 1024);
+        validated(&compression, &validator(), &compression.compress(payload))
+    }
+
+    #[test]
+    fn rejected_payloads_are_not_delivered() {
+        assert_eq!(output(&[1, 2]), Some(vec![1, 2]));
+        assert_eq!(output(&[0, 2]), None);
+        // Malformed frames are rejected too
+        let compression = Compression::new(CompressionKind::None, 1024);
+        assert_eq!(validated(&compression, &validator(), &[]), None);
+
+        let mut delivery = OrderedDelivery::new(DeliveryMode::Unordered, 4, THRESHOLD);
+        assert!(delivery.deliver(THRESHOLD + 1, output(&[0])).is_empty());
+        assert_eq!(delivery.deliver(THRESHOLD + 2, output(&[1])), vec![(THRESHOLD + 2, vec![1])]);
+    }
+
+    #[test]
+    fn fifo_skips_rejected_payloads_without_holding_back_later_ones() {
+        let mut delivery = OrderedDelivery::new(DeliveryMode::Fifo, 4, THRESHOLD);
+        assert!(delivery.deliver(THRESHOLD + 2, output(&[1, 2])).is_empty());
+        assert!(delivery.deliver(THRESHOLD + 3, output(&[0, 3])).is_empty());
+        let delivered = delivery.deliver(THRESHOLD + 1, output(&[1, 1]));
+        assert_eq!(ids(&delivered), vec![THRESHOLD + 1, THRESHOLD + 2]);
+        assert_eq!(delivery.delivered[1], 3);
+        assert_eq!(ids(&delivery.deliver(THRESHOLD + 4, output(&[1, 4]))), vec![THRESHOLD + 4]);
+    }
+
+    #[test]
+    fn instance_ids_do_not_run_into_the_next_dealer() {
+        let mut delivery = OrderedDelivery::new(DeliveryMode::Fifo, 4, THRESHOLD);
+        assert_eq!(delivery.next_instance(THRESHOLD), Some(THRESHOLD + 1));
+        assert_eq!(delivery.next_instance(2 * THRESHOLD - 2), Some(2 * THRESHOLD - 1));
+        assert_eq!(delivery.next_instance(2 * THRESHOLD - 1), None);
+        // Sequence number 0 would never become deliverable
+        assert!(delivery.deliver(2 * THRESHOLD, Some(vec![1])).is_empty());
+        assert!(delivery.pending.is_empty());
+    }
+
+    #[test]
+    fn causal_order_counts_rejected_payloads_of_dependencies() {
+        let mut delivery = OrderedDelivery::new(DeliveryMode::Causal, 2, THRESHOLD);
+        // Dealer 1 broadcast after delivering the first broadcast of dealer 0, which was rejected
+        let dependent = bincode::serialize(&CausalPayload {
+            deps: vec![1, 0],
+            payload: vec![1],
+        })
+        .unwrap();
+        let validator: Validator = Arc::new(|payload: &[u8]| {
+            bincode::deserialize::<CausalPayload>(payload).is_ok_and(|causal| causal.payload.first() == Some(&1))
+        });
+        let compression = Compression::new(CompressionKind::None, 1024);
+        let data = validated(&compression, &validator, &compression.compress(&dependent));
+        assert!(delivery.deliver(THRESHOLD + 1, data).is_empty());
+        assert_eq!(ids(&delivery.deliver(1, None)), vec![THRESHOLD + 1]);
+    }
+}
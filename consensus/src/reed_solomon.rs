@@ -1,9 +1,21 @@
-use reed_solomon_erasure::{galois_8::ReedSolomon, Error};
+use crypto::hash::do_hash;
+use reed_solomon_erasure::{galois_16, galois_8::{self, ReedSolomon}, Error, Field};
+
+// GF(2^8) codes support at most this many shards in total. Larger systems code over GF(2^16).
+pub const GF8_MAX_SHARDS: usize = 256;
+
+pub fn uses_gf16(shards: usize, parity_shards: usize) -> bool {
+    shards + parity_shards > GF8_MAX_SHARDS
+}
 
 pub fn get_shards(data:Vec<u8>,shards:usize,parity_shards:usize)->Vec<Vec<u8>>{
-    let reed_solomon:ReedSolomon<> = ReedSolomon::new(shards,parity_shards).unwrap();
+    let wide = uses_gf16(shards, parity_shards);
     let mut vec_vecs = Vec::new();
-    let size_of_vec = (data.len()/shards)+1;
+    let mut size_of_vec = (data.len()/shards)+1;
+    if wide && size_of_vec % 2 == 1 {
+        // GF(2^16) symbols span two bytes
+        size_of_vec += 1;
+    }
     for b in 0..shards{
         let mut indi_vec:Vec<u8> = Vec::new();
         for x in 0..size_of_vec{
@@ -26,18 +38,361 @@ pub fn get_shards(data:Vec<u8>,shards:usize,parity_shards:usize)->Vec<Vec<u8>>{
         }
         vec_vecs.push(parity_vec);
     }
-    // Use Reed solomon library to generate parity shards. 
-    reed_solomon.encode(&mut vec_vecs).unwrap();
+    // Use Reed solomon library to generate parity shards.
+    if wide {
+        vec_vecs = encode_in::<galois_16::Field>(vec_vecs, shards, parity_shards);
+    }
+    else {
+        let reed_solomon:ReedSolomon<> = ReedSolomon::new(shards,parity_shards).unwrap();
+        reed_solomon.encode(&mut vec_vecs).unwrap();
+    }
     log::trace!("Vec_vecs for Erasure codes: {:?}",vec_vecs);
     vec_vecs
 }
 
 // The shards are reconstructed inline with the variable data
-pub fn reconstruct_data(data:&mut Vec<Option<Vec<u8>>>, shards:usize, parity_shards:usize) -> Result<(),Error>{
+pub fn reconstruct_data(data:&mut [Option<Vec<u8>>], shards:usize, parity_shards:usize) -> Result<(),Error>{
+    if uses_gf16(shards, parity_shards) {
+        return reconstruct_in::<galois_16::Field>(data, shards, parity_shards);
+    }
     let reed_solomon:ReedSolomon<> = ReedSolomon::new(shards,parity_shards).unwrap();
     if let Err(error) = reed_solomon.reconstruct(data) {
         return Err(error)
     } else {
         return Ok(());
     };
-}
\ No newline at end of file
+}
+
+// Decode the data shards from the present shards of a codeword produced by get_shards, when up to
+// (present - shards) / 2 of the present shards may be corrupted. Returns the data shards concatenated.
+pub fn decode_with_errors(data: &[Option<Vec<u8>>], shards: usize, parity_shards: usize) -> Result<Vec<u8>, Error> {
+    if uses_gf16(shards, parity_shards) {
+        decode_in::<galois_16::Field>(data, shards, parity_shards)
+    } else {
+        decode_in::<galois_8::Field>(data, shards, parity_shards)
+    }
+}
+
+// Conversion between shard bytes and field elements
+trait Symbols: Field {
+    // Number of bytes per field element
+    const SYMBOL_SIZE: usize;
+    fn to_elems(bytes: &[u8]) -> Vec<Self::Elem>;
+    fn to_bytes(elems: &[Self::Elem]) -> Vec<u8>;
+}
+
+impl Symbols for galois_8::Field {
+    const SYMBOL_SIZE: usize = 1;
+
+    fn to_elems(bytes: &[u8]) -> Vec<Self::Elem> {
+        bytes.to_vec()
+    }
+
+    fn to_bytes(elems: &[Self::Elem]) -> Vec<u8> {
+        elems.to_vec()
+    }
+}
+
+impl Symbols for galois_16::Field {
+    const SYMBOL_SIZE: usize = 2;
+
+    fn to_elems(bytes: &[u8]) -> Vec<Self::Elem> {
+        bytes.chunks(2).map(|pair| [pair[0], pair[1]]).collect()
+    }
+
+    fn to_bytes(elems: &[Self::Elem]) -> Vec<u8> {
+        elems.iter().flat_map(|elem| elem.iter().copied()).collect()
+    }
+}
+
+fn encode_in<F: Symbols>(vec_vecs: Vec<Vec<u8>>, shards: usize, parity_shards: usize) -> Vec<Vec<u8>> {
+    let reed_solomon = reed_solomon_erasure::ReedSolomon::<F>::new(shards, parity_shards).unwrap();
+    let mut elems: Vec<Vec<F::Elem>> = vec_vecs.iter().map(|shard| F::to_elems(shard)).collect();
+    reed_solomon.encode(&mut elems).unwrap();
+    elems.iter().map(|shard| F::to_bytes(shard)).collect()
+}
+
+fn reconstruct_in<F: Symbols>(data: &mut [Option<Vec<u8>>], shards: usize, parity_shards: usize) -> Result<(), Error> {
+    let reed_solomon = reed_solomon_erasure::ReedSolomon::<F>::new(shards, parity_shards)?;
+    if data.iter().flatten().any(|shard| shard.len() % F::SYMBOL_SIZE != 0) {
+        return Err(Error::IncorrectShardSize);
+    }
+    let mut elems: Vec<Option<Vec<F::Elem>>> = data
+        .iter()
+        .map(|shard| shard.as_ref().map(|bytes| F::to_elems(bytes)))
+        .collect();
+    reed_solomon.reconstruct(&mut elems)?;
+    for (slot, shard) in data.iter_mut().zip(elems.into_iter()) {
+        *slot = shard.map(|shard| F::to_bytes(&shard));
+    }
+    Ok(())
+}
+
+fn decode_in<F: Symbols>(data: &[Option<Vec<u8>>], shards: usize, parity_shards: usize) -> Result<Vec<u8>, Error> {
+    let reed_solomon = reed_solomon_erasure::ReedSolomon::<F>::new(shards, parity_shards)?;
+    if data.len() != shards + parity_shards {
+        return Err(Error::TooFewShards);
+    }
+    let present: Vec<usize> = (0..data.len()).filter(|index| data[*index].is_some()).collect();
+    if present.len() < shards {
+        return Err(Error::TooFewShardsPresent);
+    }
+    let shard_len = data[present[0]].as_ref().unwrap().len();
+    if shard_len == 0 {
+        return Err(Error::EmptyShard);
+    }
+    if shard_len % F::SYMBOL_SIZE != 0
+        || present.iter().any(|index| data[*index].as_ref().unwrap().len() != shard_len)
+    {
+        return Err(Error::IncorrectShardSize);
+    }
+    let received: Vec<Vec<F::Elem>> = present
+        .iter()
+        .map(|index| F::to_elems(data[*index].as_ref().unwrap()))
+        .collect();
+    let max_errors = (present.len() - shards) / 2;
+
+    // Optimistically assume the first shards are correct
+    let mut codeword = reconstruct_checked::<F>(&reed_solomon, &present, &received, &[], shards, max_errors);
+    if codeword.is_none() && max_errors > 0 {
+        // Locate the corrupted shards on a random linear combination of the symbol positions.
+        // The coefficients depend on the received shards, so a faulty sender cannot choose a corruption that cancels out.
+        let seed: Vec<u8> = data.iter().flatten().flat_map(|shard| shard.iter().copied()).collect();
+        let coefficients = coefficients::<F>(&seed, received[0].len());
+        let column: Vec<F::Elem> = received
+            .iter()
+            .map(|shard| {
+                shard
+                    .iter()
+                    .zip(coefficients.iter())
+                    .fold(F::zero(), |acc, (symbol, coefficient)| F::add(acc, F::mul(*symbol, *coefficient)))
+            })
+            .collect();
+        if let Some(suspects) = locate_errors::<F>(&present, &column, shards, max_errors) {
+            codeword = reconstruct_checked::<F>(&reed_solomon, &present, &received, &suspects, shards, max_errors);
+        }
+    }
+    let codeword = codeword.ok_or(Error::TooFewShardsPresent)?;
+    Ok(codeword[..shards].iter().flat_map(|shard| F::to_bytes(shard)).collect())
+}
+
+// Reconstruct the codeword from the first present shards outside the excluded positions. The codeword is
+// accepted if all but max_errors of the present shards agree with it, which makes it the unique such codeword.
+fn reconstruct_checked<F: Symbols>(
+    reed_solomon: &reed_solomon_erasure::ReedSolomon<F>,
+    present: &[usize],
+    received: &[Vec<F::Elem>],
+    excluded: &[usize],
+    shards: usize,
+    max_errors: usize,
+) -> Option<Vec<Vec<F::Elem>>> {
+    let total = reed_solomon.total_shard_count();
+    let mut codeword: Vec<Option<Vec<F::Elem>>> = vec![None; total];
+    for (index, shard) in present
+        .iter()
+        .zip(received.iter())
+        .filter(|(index, _)| !excluded.contains(*index))
+        .take(shards)
+    {
+        codeword[*index] = Some(shard.clone());
+    }
+    reed_solomon.reconstruct(&mut codeword).ok()?;
+    let agreeing = present
+        .iter()
+        .zip(received.iter())
+        .filter(|(index, shard)| codeword[**index].as_ref() == Some(*shard))
+        .count();
+    if agreeing + max_errors < present.len() {
+        return None;
+    }
+    Some(codeword.into_iter().map(|shard| shard.unwrap()).collect())
+}
+
+fn coefficients<F: Symbols>(seed: &[u8], count: usize) -> Vec<F::Elem> {
+    let seed = do_hash(seed);
+    let mut coefficients = Vec::with_capacity(count);
+    let mut block: u64 = 0;
+    while coefficients.len() < count {
+        let mut input = seed.to_vec();
+        input.extend_from_slice(&block.to_be_bytes());
+        for chunk in do_hash(&input).chunks(F::SYMBOL_SIZE) {
+            let value = chunk.iter().fold(0usize, |acc, byte| (acc << 8) | *byte as usize);
+            coefficients.push(F::nth(value % F::ORDER));
+        }
+        block += 1;
+    }
+    coefficients.truncate(count);
+    coefficients
+}
+
+// Berlekamp-Welch on one symbol per shard: find a monic E of degree max_errors and a Q of degree
+// below shards + max_errors with Q(x_i) = y_i E(x_i) at every present position. Every corrupted
+// position is a root of E.
+fn locate_errors<F: Symbols>(present: &[usize], column: &[F::Elem], shards: usize, max_errors: usize) -> Option<Vec<usize>> {
+    let q_len = shards + max_errors;
+    let unknowns = q_len + max_errors;
+    let rows: Vec<Vec<F::Elem>> = present
+        .iter()
+        .zip(column.iter())
+        .map(|(index, y)| {
+            let x = F::nth(*index);
+            let mut powers = Vec::with_capacity(q_len + 1);
+            let mut power = F::one();
+            for _ in 0..=q_len {
+                powers.push(power);
+                power = F::mul(power, x);
+            }
+            // Subtraction is addition in characteristic 2
+            let mut row = powers[..q_len].to_vec();
+            row.extend(powers[..max_errors].iter().map(|power| F::mul(*y, *power)));
+            row.push(F::mul(*y, powers[max_errors]));
+            row
+        })
+        .collect();
+    let solution = solve::<F>(rows, unknowns)?;
+
+    let mut locator = solution[q_len..].to_vec();
+    locator.push(F::one());
+    Some(
+        present
+            .iter()
+            .copied()
+            .filter(|index| evaluate::<F>(&locator, F::nth(*index)) == F::zero())
+            .collect(),
+    )
+}
+
+// Gauss-Jordan elimination over the augmented rows. Returns a solution with free variables set to zero.
+fn solve<F: Field>(mut rows: Vec<Vec<F::Elem>>, unknowns: usize) -> Option<Vec<F::Elem>> {
+    let mut pivots = Vec::new();
+    for col in 0..unknowns {
+        let rank = pivots.len();
+        let pivot = match (rank..rows.len()).find(|row| rows[*row][col] != F::zero()) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        rows.swap(rank, pivot);
+        let inverse = F::div(F::one(), rows[rank][col]);
+        for value in rows[rank][col..].iter_mut() {
+            *value = F::mul(*value, inverse);
+        }
+        let pivot_row = rows[rank].clone();
+        for (index, row) in rows.iter_mut().enumerate() {
+            let factor = row[col];
+            if index == rank || factor == F::zero() {
+                continue;
+            }
+            for (value, pivot_value) in row[col..].iter_mut().zip(pivot_row[col..].iter()) {
+                *value = F::add(*value, F::mul(factor, *pivot_value));
+            }
+        }
+        pivots.push(col);
+    }
+    // The system is inconsistent if an eliminated row keeps a nonzero right-hand side
+    if rows[pivots.len()..].iter().any(|row| row[unknowns] != F::zero()) {
+        return None;
+    }
+    let mut solution = vec![F::zero(); unknowns];
+    for (row, col) in pivots.into_iter().enumerate() {
+        solution[col] = rows[row][unknowns];
+    }
+    Some(solution)
+}
+
+fn evaluate<F: Field>(coefficients: &[F::Elem], x: F::Elem) -> F::Elem {
+    coefficients
+        .iter()
+        .rev()
+        .fold(F::zero(), |acc, coefficient| F::add(F::mul(acc, x), *coefficient))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    // Encode, drop the missing shards and change every byte of the corrupted ones, then decode. Returns the outcome
+    // along with the data shards decoding should give.
+    fn round_trip(
+        shards: usize,
+        parity_shards: usize,
+        missing: &[usize],
+        corrupted: &[usize],
+    ) -> (Result<Vec<u8>, Error>, Vec<u8>) {
+        let encoded = get_shards(data(1100), shards, parity_shards);
+        let expected: Vec<u8> = encoded[..shards].concat();
+        let mut received: Vec<Option<Vec<u8>>> = encoded.into_iter().map(Some).collect();
+        for index in missing {
+            received[*index] = None;
+        }
+        for index in corrupted {
+            for (position, byte) in received[*index].as_mut().unwrap().iter_mut().enumerate() {
+                *byte = byte.wrapping_add(1 + (position % 251) as u8);
+            }
+        }
+        (decode_with_errors(&received, shards, parity_shards), expected)
+    }
+
+    #[test]
+    fn decodes_up_to_max_errors() {
+        // n = 10 and t = 3, with t + 1 data shards as in the coded protocols
+        let spread = [1, 5, 8];
+        for errors in 0..=3 {
+            let (decoded, expected) = round_trip(4, 6, &[], &spread[..errors]);
+            assert_eq!(decoded.ok(), Some(expected));
+        }
+        // Every missing pair of shards costs one error
+        let (decoded, expected) = round_trip(4, 6, &[0, 9], &[2, 6]);
+        assert_eq!(decoded.ok(), Some(expected));
+    }
+
+    #[test]
+    fn rejects_more_than_max_errors() {
+        let (decoded, _) = round_trip(4, 6, &[], &[0, 3, 5, 8]);
+        assert!(decoded.is_err());
+        let (decoded, _) = round_trip(4, 6, &[0, 9], &[2, 4, 6]);
+        assert!(decoded.is_err());
+    }
+
+    #[test]
+    fn switches_to_gf16_above_256_shards() {
+        assert!(!uses_gf16(86, 170));
+        assert!(uses_gf16(86, 171));
+        // 1100 bytes over 86 shards take 13 bytes each, padded to whole GF(2^16) symbols
+        assert!(get_shards(data(1100), 86, 170).iter().all(|shard| shard.len() == 13));
+        assert!(get_shards(data(1100), 86, 171).iter().all(|shard| shard.len() == 14));
+        for (shards, parity_shards) in vec![(86, 170), (86, 171)] {
+            let max_errors = parity_shards / 2;
+            let corrupted: Vec<usize> = (0..=max_errors).map(|i| i * 3).collect();
+            let (decoded, expected) = round_trip(shards, parity_shards, &[], &corrupted[..max_errors]);
+            assert_eq!(decoded.ok(), Some(expected));
+            let (decoded, _) = round_trip(shards, parity_shards, &[], &corrupted);
+            assert!(decoded.is_err());
+        }
+    }
+
+    #[test]
+    fn locates_corrupted_positions() {
+        let encoded = get_shards(data(1100), 4, 6);
+        let present: Vec<usize> = (0..10).collect();
+        let mut column: Vec<u8> = encoded.iter().map(|shard| shard[0]).collect();
+        column[2] ^= 1;
+        column[7] ^= 0x55;
+        // The locator has degree 3, so besides the two errors it may have one more root
+        let suspects = locate_errors::<galois_8::Field>(&present, &column, 4, 3).unwrap();
+        assert!(suspects.contains(&2) && suspects.contains(&7));
+        assert!(suspects.len() <= 3);
+    }
+
+    #[test]
+    fn solves_consistent_systems_only() {
+        // x + y = 3 and x = 1, where addition is XOR
+        let solution = solve::<galois_8::Field>(vec![vec![1, 1, 3], vec![1, 0, 1]], 2);
+        assert_eq!(solution, Some(vec![1, 2]));
+        // x + y = 3 and x + y = 4
+        assert_eq!(solve::<galois_8::Field>(vec![vec![1, 1, 3], vec![1, 1, 4]], 2), None);
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use types::Replica;
+
+use crate::HealthReport;
+
+// What a node reports to the syncer beyond the fixed layout of its message. The states of a SyncMsg come from the
+// types crate, so the kind of report is told by its tag here rather than by a state of its own.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SyncReport {
+    // Sent with ALIVE every heartbeat period
+    Heartbeat(HealthReport),
+    // The reports below follow the syncer's RBCSyncMsg in COMPLETED
+    // Rounds an ABA node took to decide
+    Rounds(usize),
+    // Rounds a DAG node built, the vertices it committed and the average commit latency of its own vertices in ms
+    Dag {
+        rounds: usize,
+        committed_vertices: usize,
+        commit_latency: u128,
+    },
+    // (instance id, dealer) pairs an RBC node blamed
+    Blame(Vec<(usize, Replica)>),
+    // Instance ids in the Gather output of a CCRBC node and the pairs it blamed
+    Gather {
+        gathered: Vec<usize>,
+        blamed: Vec<(usize, Replica)>,
+    },
+}
+
+impl SyncReport {
+    // Append the report to the value of a SyncMsg
+    pub fn append(&self, value: Vec<u8>) -> Vec<u8> {
+        let mut value = value;
+        value.extend(bincode::serialize(self).expect("Failed to serialize report to the syncer"));
+        value
+    }
+}
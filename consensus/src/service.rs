@@ -0,0 +1,45 @@
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use types::SyncMsg;
+
+use crate::{AuthKind, CodecKind, DeliveryMode, Evidence, HashKind, NetworkConfig, Validator};
+
+// Settings a node runs any of the RBC protocols with. Bracha's RBC codes nothing and ignores the codec and hash.
+#[derive(Clone)]
+pub struct RBCOptions {
+    pub byz: bool,
+    pub crash: bool,
+    pub delivery_mode: DeliveryMode,
+    pub codec: CodecKind,
+    pub hash: HashKind,
+    pub auth: AuthKind,
+    pub network: NetworkConfig,
+    // Application validity predicate on broadcast payloads
+    pub validator: Validator,
+}
+
+// Lets an upper-layer protocol drive an RBC context in the same process instead of the syncer.
+// Broadcast requests are handed to the RBC context as START messages, and every delivered
+// instance is returned as (instance id, payload) instead of being reported to the syncer.
+// Evidence against misbehaving dealers is handed over as (instance id, evidence).
+pub struct RBCService {
+    pub req_recv: UnboundedReceiver<SyncMsg>,
+    pub out_send: UnboundedSender<(usize, Vec<u8>)>,
+    pub blame_send: UnboundedSender<(usize, Evidence)>,
+}
+
+// The upper layer's end of an RBC service
+pub struct RBCServiceHandle {
+    pub req_send: UnboundedSender<SyncMsg>,
+    pub out_recv: UnboundedReceiver<(usize, Vec<u8>)>,
+    pub blame_recv: UnboundedReceiver<(usize, Evidence)>,
+}
+
+pub fn rbc_service() -> (RBCService, RBCServiceHandle) {
+    let (req_send, req_recv) = unbounded_channel();
+    let (out_send, out_recv) = unbounded_channel();
+    let (blame_send, blame_recv) = unbounded_channel();
+    (
+        RBCService { req_recv, out_send, blame_send },
+        RBCServiceHandle { req_send, out_recv, blame_recv },
+    )
+}
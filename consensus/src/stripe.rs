@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use crypto::hash::Hash;
+
+use crate::{ErasureCode, HashKind};
+
+// Length of the header in front of each segment of a shard
+pub const SEGMENT_HEADER: usize = 4;
+
+// Erasure coding of a payload one stripe at a time. Node i's shard is the sequence of the i-th shards of all stripes,
+// each behind its length, so encoding never holds more than a stripe of shards beyond the payload, and the dealer
+// can send them as they come.
+#[derive(Clone)]
+pub struct Striping {
+    code: Arc<dyn ErasureCode>,
+    // Bytes of payload per stripe. The whole payload is one stripe if absent.
+    stripe_size: Option<usize>,
+}
+
+impl Striping {
+    pub fn new(code: Arc<dyn ErasureCode>, stripe_size: Option<usize>) -> Striping {
+        Striping {
+            code,
+            stripe_size: stripe_size.filter(|size| *size > 0),
+        }
+    }
+
+    // Stripes a payload of this length is cut into. An empty payload still takes one.
+    pub fn stripes(&self, length: usize) -> usize {
+        match self.stripe_size {
+            Some(stripe_size) if length > stripe_size => (length + stripe_size - 1) / stripe_size,
+            _ => 1,
+        }
+    }
+
+    // Encode the payload stripe by stripe, handing sink the index of each stripe and its segment for every node.
+    // Returns the digest of each node's shard, hashed as its segments came.
+    pub fn encode<F>(&self, data: &[u8], hash: HashKind, mut sink: F) -> Result<Vec<Hash>, String>
+    where
+        F: FnMut(usize, Vec<Vec<u8>>),
+    {
+        let total = self.code.data_shards() + self.code.parity_shards();
+        let mut hashers: Vec<_> = (0..total).map(|_| hash.hasher()).collect();
+        let count = self.stripes(data.len());
+        let stripe_size = (data.len() + count - 1) / count;
+        for index in 0..count {
+            let stripe = &data[(index * stripe_size).min(data.len())..((index + 1) * stripe_size).min(data.len())];
+            let segments: Vec<Vec<u8>> = self.code.encode(stripe)?.iter().map(|shard| segment(shard)).collect();
+            for (hasher, segment) in hashers.iter_mut().zip(segments.iter()) {
+                hasher.update(segment);
+            }
+            sink(index, segments);
+        }
+        Ok(hashers.into_iter().map(|hasher| hasher.finalize()).collect())
+    }
+
+    // Fill in the missing shards from at least data_shards present ones and return the payload, one stripe at a time.
+    // Nothing here checks the shards against their commitment: shards that each open against it need not encode one
+    // payload, so callers re-encode the payload and compare the commitment before delivering it.
+    pub fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<Vec<u8>, String> {
+        let split: Vec<Option<Vec<&[u8]>>> = shards
+            .iter()
+            .map(|shard| shard.as_deref().map(segments).transpose())
+            .collect::<Result<_, _>>()?;
+        let count = match split.iter().flatten().map(|segments| segments.len()).max() {
+            Some(count) => count,
+            None => return Err("No shards to reconstruct from".to_string()),
+        };
+        if split.iter().flatten().any(|segments| segments.len() != count) {
+            return Err("Shards carry different numbers of stripes".to_string());
+        }
+        let mut message = Vec::new();
+        let mut rebuilt: Vec<Vec<u8>> = vec![Vec::new(); shards.len()];
+        for index in 0..count {
+            let mut stripe: Vec<Option<Vec<u8>>> = split
+                .iter()
+                .map(|segments| segments.as_ref().map(|segments| segments[index].to_vec()))
+                .collect();
+            message.extend_from_slice(&self.code.reconstruct(&mut stripe)?);
+            for (shard, stripe_shard) in rebuilt.iter_mut().zip(stripe.into_iter()) {
+                shard.extend_from_slice(&segment(&stripe_shard.expect("Reconstruction fills in every shard")));
+            }
+        }
+        // The present shards are left as they were, which the rebuilt ones match
+        for (slot, shard) in shards.iter_mut().zip(rebuilt.into_iter()) {
+            if slot.is_none() {
+                *slot = Some(shard);
+            }
+        }
+        Ok(message)
+    }
+
+    // Encode a reconstructed payload again into the shards of all nodes and their digests, which commit to the same
+    // root as the dealer's only if the payload is the one the dealer committed to
+    pub fn reencode(&self, data: &[u8], hash: HashKind) -> Result<(Vec<Vec<u8>>, Vec<Hash>), String> {
+        let mut shards = vec![Vec::new(); self.code.data_shards() + self.code.parity_shards()];
+        let digests = self.encode(data, hash, |_, segments| {
+            for (shard, segment) in shards.iter_mut().zip(segments.iter()) {
+                shard.extend_from_slice(segment);
+            }
+        })?;
+        Ok((shards, digests))
+    }
+}
+
+fn segment(shard: &[u8]) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(SEGMENT_HEADER + shard.len());
+    segment.extend_from_slice(&(shard.len() as u32).to_le_bytes());
+    segment.extend_from_slice(shard);
+    segment
+}
+
+// Cut a shard into the segments of its stripes
+pub fn segments(mut shard: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let mut segments = Vec::new();
+    while !shard.is_empty() {
+        if shard.len() < SEGMENT_HEADER {
+            return Err(format!("{} trailing bytes are too short for a segment header", shard.len()));
+        }
+        let mut header = [0u8; SEGMENT_HEADER];
+        header.copy_from_slice(&shard[..SEGMENT_HEADER]);
+        let length = u32::from_le_bytes(header) as usize;
+        let rest = &shard[SEGMENT_HEADER..];
+        if length > rest.len() {
+            return Err(format!("Segment header claims {} bytes but only {} follow", length, rest.len()));
+        }
+        segments.push(&rest[..length]);
+        shard = &rest[length..];
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_shards, new_codec, CodecKind};
+
+    fn striping() -> Striping {
+        Striping::new(new_codec(CodecKind::ReedSolomon, 2, 2).unwrap(), Some(8))
+    }
+
+    #[test]
+    fn reencoding_matches_the_digests_of_an_honest_dealer() {
+        let striping = striping();
+        let payload: Vec<u8> = (0..30).collect();
+        let (shards, digests) = striping.reencode(&payload, HashKind::Sha256).unwrap();
+        assert_eq!(hash_shards(HashKind::Sha256, &shards), digests);
+
+        let mut received = vec![None, Some(shards[1].clone()), None, Some(shards[3].clone())];
+        let message = striping.reconstruct(&mut received).unwrap();
+        assert_eq!(message, payload);
+        assert_eq!(striping.reencode(&message, HashKind::Sha256).unwrap().1, digests);
+    }
+
+    #[test]
+    fn reencoding_exposes_shards_that_encode_no_payload() {
+        let striping = striping();
+        let payload: Vec<u8> = (0..30).collect();
+        let (mut shards, _) = striping.reencode(&payload, HashKind::Sha256).unwrap();
+        // The dealer commits to a parity shard that does not belong to the payload
+        let last = shards[3].len() - 1;
+        shards[3][last] ^= 1;
+        let committed = hash_shards(HashKind::Sha256, &shards);
+
+        // Shards 0 and 1 open against the commitment and reconstruct a payload, whose encoding the commitment is not
+        let mut received = vec![Some(shards[0].clone()), Some(shards[1].clone()), None, None];
+        let message = striping.reconstruct(&mut received).unwrap();
+        assert_eq!(message, payload);
+        assert_ne!(striping.reencode(&message, HashKind::Sha256).unwrap().1, committed);
+    }
+}
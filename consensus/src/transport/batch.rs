@@ -0,0 +1,149 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use network::{plaintcp::CancelHandler, Acknowledgement};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot, Mutex,
+    },
+    time::{timeout_at, Instant},
+};
+use types::Replica;
+
+use crate::{Authenticator, Envelope, Transport};
+
+// When the messages queued for a peer go out as one frame
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    // How long the first message of a batch waits for others
+    pub window: Duration,
+    pub max_messages: usize,
+    // Bodies of the messages in a batch, once exceeded the batch is sent
+    pub max_bytes: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            window: Duration::from_millis(1),
+            max_messages: 64,
+            max_bytes: 1 << 20,
+        }
+    }
+}
+
+type Pending = (Envelope, oneshot::Sender<Acknowledgement>);
+
+// Coalesces the messages for each peer into frames carrying a single tag
+pub struct BatchingTransport {
+    auth: Authenticator,
+    config: BatchConfig,
+    queues: FnvHashMap<Replica, UnboundedSender<Pending>>,
+    inner: Arc<Mutex<Box<dyn Transport>>>,
+    messages: Arc<AtomicUsize>,
+    frames: Arc<AtomicUsize>,
+}
+
+impl BatchingTransport {
+    pub fn new(auth: Authenticator, config: BatchConfig, inner: Box<dyn Transport>) -> BatchingTransport {
+        BatchingTransport {
+            auth,
+            config,
+            queues: FnvHashMap::default(),
+            inner: Arc::new(Mutex::new(inner)),
+            messages: Arc::new(AtomicUsize::new(0)),
+            frames: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn queue(&mut self, replica: Replica) -> &UnboundedSender<Pending> {
+        let (auth, config, inner) = (&self.auth, self.config, &self.inner);
+        let (messages, frames) = (&self.messages, &self.frames);
+        self.queues.entry(replica).or_insert_with(|| {
+            let (queue, pending) = unbounded_channel();
+            tokio::spawn(flush(
+                replica,
+                auth.clone(),
+                config,
+                inner.clone(),
+                pending,
+                messages.clone(),
+                frames.clone(),
+            ));
+            queue
+        })
+    }
+}
+
+impl Drop for BatchingTransport {
+    fn drop(&mut self) {
+        log::info!(
+            "Batching sent {} messages in {} frames",
+            self.messages.load(Ordering::Relaxed),
+            self.frames.load(Ordering::Relaxed)
+        );
+    }
+}
+
+#[async_trait]
+impl Transport for BatchingTransport {
+    async fn send(&mut self, replica: Replica, envelope: Envelope) -> CancelHandler<Acknowledgement> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        if self.queue(replica).send((envelope, ack_tx)).is_err() {
+            log::error!("Batching of messages to node {} has shut down", replica);
+        }
+        ack_rx
+    }
+}
+
+// Gather the messages for a peer until the window closes or a limit is reached, then send them as one frame
+async fn flush(
+    replica: Replica,
+    auth: Authenticator,
+    config: BatchConfig,
+    inner: Arc<Mutex<Box<dyn Transport>>>,
+    mut queue: UnboundedReceiver<Pending>,
+    messages: Arc<AtomicUsize>,
+    frames: Arc<AtomicUsize>,
+) {
+    while let Some(first) = queue.recv().await {
+        let deadline = Instant::now() + config.window;
+        let mut bytes = first.0.body.len();
+        let mut pending = vec![first];
+        while pending.len() < config.max_messages && bytes < config.max_bytes {
+            match timeout_at(deadline, queue.recv()).await {
+                Ok(Some(next)) => {
+                    bytes += next.0.body.len();
+                    pending.push(next);
+                }
+                _ => break,
+            }
+        }
+        // Messages given up on while they waited are not sent
+        pending.retain(|(_, ack_tx)| !ack_tx.is_closed());
+        if pending.is_empty() {
+            continue;
+        }
+        let (envelopes, ack_txs): (Vec<Envelope>, Vec<_>) = pending.into_iter().unzip();
+        messages.fetch_add(envelopes.len(), Ordering::Relaxed);
+        frames.fetch_add(1, Ordering::Relaxed);
+        let frame = auth.batch(replica, &envelopes);
+        let frame_ack = inner.lock().await.send(replica, frame).await;
+        // The frame's acknowledgement covers every message in it
+        tokio::spawn(async move {
+            if frame_ack.await.is_ok() {
+                for ack_tx in ack_txs {
+                    let _ = ack_tx.send(Acknowledgement::Pong);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use network::{plaintcp::CancelHandler, Acknowledgement};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        oneshot, Mutex,
+    },
+    time::Instant,
+};
+use types::Replica;
+
+use crate::Transport;
+
+// A lost segment of a reliable stream costs at least one retransmission timeout
+const MIN_RTO: Duration = Duration::from_millis(200);
+// Bound on the retransmissions drawn for a message, so a loss rate of 1 does not hang the sender
+const MAX_RETRANSMISSIONS: u32 = 16;
+
+// Conditions of the link from one region to another
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct LinkProfile {
+    // Mean one-way latency
+    #[serde(default)]
+    pub latency_ms: f64,
+    // Standard deviation of the latency
+    #[serde(default)]
+    pub jitter_ms: f64,
+    // Unlimited if absent
+    #[serde(default)]
+    pub bandwidth_mbps: Option<f64>,
+    // Probability that a message needs a retransmission
+    #[serde(default)]
+    pub loss: f64,
+}
+
+// Layout of the emulation file: the nodes in each region and the links between regions
+#[derive(Debug, Serialize, Deserialize)]
+struct EmulationFile {
+    regions: HashMap<String, Vec<Replica>>,
+    links: HashMap<String, HashMap<String, LinkProfile>>,
+}
+
+// Emulated WAN conditions between the nodes, for geo-distributed runs on one machine
+pub struct Emulation {
+    region_of: HashMap<Replica, String>,
+    links: HashMap<(String, String), LinkProfile>,
+}
+
+impl Emulation {
+    pub fn from_file(path: &str) -> Result<Emulation, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        let file: EmulationFile =
+            serde_json::from_str(&file).map_err(|e| format!("Invalid emulation file {}: {}", path, e))?;
+        let mut region_of = HashMap::default();
+        for (region, replicas) in file.regions.iter() {
+            for replica in replicas {
+                if let Some(other) = region_of.insert(*replica, region.clone()) {
+                    return Err(format!("Node {} is in both {} and {}", replica, other, region));
+                }
+            }
+        }
+        let mut links = HashMap::default();
+        for (from, destinations) in file.links.into_iter() {
+            for (to, profile) in destinations.into_iter() {
+                links.insert((from.clone(), to), profile);
+            }
+        }
+        Ok(Emulation { region_of, links })
+    }
+
+    // Profile of the link between two nodes. Links listed in one direction only are symmetric, and nodes outside the
+    // regions get an ideal link.
+    pub fn link(&self, from: Replica, to: Replica) -> LinkProfile {
+        let (from, to) = match (self.region_of.get(&from), self.region_of.get(&to)) {
+            (Some(from), Some(to)) => (from.clone(), to.clone()),
+            _ => return LinkProfile::default(),
+        };
+        self.links
+            .get(&(from.clone(), to.clone()))
+            .or_else(|| self.links.get(&(to, from)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Delay the messages of a transport according to the links of this node
+    pub fn wrap<T>(&self, myid: Replica, inner: Box<dyn Transport<T>>) -> EmulatedTransport<T>
+    where
+        T: Serialize + Send + 'static,
+    {
+        let profiles = self
+            .region_of
+            .keys()
+            .map(|replica| (*replica, self.link(myid, *replica)))
+            .collect();
+        EmulatedTransport {
+            profiles,
+            links: FnvHashMap::default(),
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+}
+
+// Holds every message back until it would have crossed the emulated link, and keeps the order of each link
+pub struct EmulatedTransport<T> {
+    profiles: FnvHashMap<Replica, LinkProfile>,
+    links: FnvHashMap<Replica, Link<T>>,
+    inner: Arc<Mutex<Box<dyn Transport<T>>>>,
+}
+
+struct Link<T> {
+    profile: LinkProfile,
+    // When the link finishes putting the previous messages on the wire
+    free_at: Instant,
+    last_delivery: Instant,
+    queue: UnboundedSender<(Instant, T, oneshot::Sender<Acknowledgement>)>,
+}
+
+impl<T> Link<T> {
+    // Delivery time of a message of the given size sent now
+    fn schedule(&mut self, size: u64) -> Instant {
+        let now = Instant::now();
+        let start = self.free_at.max(now);
+        let transmission = match self.profile.bandwidth_mbps {
+            Some(mbps) if mbps > 0.0 => Duration::from_secs_f64(size as f64 * 8.0 / (mbps * 1e6)),
+            _ => Duration::ZERO,
+        };
+        self.free_at = start + transmission;
+        let deliver_at = (self.free_at + sample_delay(&self.profile)).max(self.last_delivery);
+        self.last_delivery = deliver_at;
+        deliver_at
+    }
+}
+
+// Latency with jitter, plus a retransmission timeout for every loss drawn
+fn sample_delay(profile: &LinkProfile) -> Duration {
+    let mut rng = rand::thread_rng();
+    // Box-Muller transform of two uniform samples
+    let normal = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt() * (2.0 * std::f64::consts::PI * rng.gen::<f64>()).cos();
+    let latency = (profile.latency_ms + profile.jitter_ms * normal).max(0.0);
+    let rto = MIN_RTO.max(Duration::from_secs_f64(2.0 * profile.latency_ms / 1000.0));
+    let mut delay = Duration::from_secs_f64(latency / 1000.0);
+    for _ in 0..MAX_RETRANSMISSIONS {
+        if rng.gen::<f64>() >= profile.loss {
+            break;
+        }
+        delay += rto;
+    }
+    delay
+}
+
+#[async_trait]
+impl<T> Transport<T> for EmulatedTransport<T>
+where
+    T: Serialize + Send + 'static,
+{
+    async fn send(&mut self, replica: Replica, msg: T) -> CancelHandler<Acknowledgement> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        let size = bincode::serialized_size(&msg).unwrap_or(0);
+        let link = match self.links.get_mut(&replica) {
+            Some(link) => link,
+            None => {
+                let (queue, mut pending) = unbounded_channel::<(Instant, T, oneshot::Sender<Acknowledgement>)>();
+                let inner = self.inner.clone();
+                // Hand the messages of the link to the inner transport once they are due
+                tokio::spawn(async move {
+                    while let Some((deliver_at, msg, ack_tx)) = pending.recv().await {
+                        tokio::time::sleep_until(deliver_at).await;
+                        if ack_tx.is_closed() {
+                            continue;
+                        }
+                        let inner_ack = inner.lock().await.send(replica, msg).await;
+                        tokio::spawn(async move {
+                            if let Ok(ack) = inner_ack.await {
+                                let _ = ack_tx.send(ack);
+                            }
+                        });
+                    }
+                });
+                let now = Instant::now();
+                self.links.entry(replica).or_insert(Link {
+                    profile: self.profiles.get(&replica).copied().unwrap_or_default(),
+                    free_at: now,
+                    last_delivery: now,
+                    queue,
+                })
+            }
+        };
+        let deliver_at = link.schedule(size);
+        if link.queue.send((deliver_at, msg, ack_tx)).is_err() {
+            log::error!("Emulated link to node {} has shut down", replica);
+        }
+        ack_rx
+    }
+}
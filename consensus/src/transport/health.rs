@@ -0,0 +1,264 @@
+use std::{
+    collections::BTreeMap,
+    net::{SocketAddr, SocketAddrV4},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use network::{plaintcp::CancelHandler, Acknowledgement};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::oneshot,
+    time,
+};
+use types::{Replica, SyncMsg, SyncState};
+
+use crate::{SyncReport, Transport};
+
+// How a node watches its links and who it tells
+#[derive(Debug, Clone, Copy)]
+pub struct HealthConfig {
+    // How long a message may go unacknowledged before its link counts as retrying
+    pub stall: Duration,
+    // Period of the reports to the syncer. None are sent if absent, which is the default.
+    pub heartbeat: Option<Duration>,
+    // The node serves its metrics over HTTP at this port plus its id
+    pub metrics_port: Option<u16>,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            stall: Duration::from_secs(2),
+            heartbeat: None,
+            metrics_port: None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LinkState {
+    // Nothing was acknowledged yet, and nothing waits for long
+    Unknown,
+    Connected,
+    // A message waits longer than the stall time, so the sender keeps reconnecting to the peer
+    Retrying,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerHealth {
+    pub peer: Replica,
+    pub state: LinkState,
+    // Milliseconds since the peer last acknowledged a message
+    pub last_ack_ms: Option<u64>,
+    // Messages sent and not acknowledged yet
+    pub pending: usize,
+    pub sent: u64,
+    pub acked: u64,
+}
+
+// What a node sends the syncer with each heartbeat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub peers: Vec<PeerHealth>,
+}
+
+#[derive(Default)]
+struct Link {
+    next: u64,
+    // Send times of the unacknowledged messages
+    pending: BTreeMap<u64, Instant>,
+    last_ack: Option<Instant>,
+    sent: u64,
+    acked: u64,
+}
+
+// Connectivity of the links of a node, shared by its transport, heartbeat and metrics endpoint
+#[derive(Clone)]
+pub struct Health {
+    stall: Duration,
+    links: Arc<Mutex<FnvHashMap<Replica, Link>>>,
+}
+
+impl Health {
+    pub fn new(stall: Duration) -> Health {
+        Health {
+            stall,
+            links: Arc::new(Mutex::new(FnvHashMap::default())),
+        }
+    }
+
+    pub fn wrap<T: Send + 'static>(&self, inner: Box<dyn Transport<T>>) -> HealthTransport<T> {
+        HealthTransport {
+            health: self.clone(),
+            inner,
+        }
+    }
+
+    fn sent(&self, replica: Replica) -> u64 {
+        let mut links = self.links.lock().unwrap();
+        let link = links.entry(replica).or_default();
+        let seq = link.next;
+        link.next += 1;
+        link.sent += 1;
+        link.pending.insert(seq, Instant::now());
+        seq
+    }
+
+    // The message was acknowledged, or given up on by its sender
+    fn settled(&self, replica: Replica, seq: u64, acked: bool) {
+        let mut links = self.links.lock().unwrap();
+        let link = links.entry(replica).or_default();
+        link.pending.remove(&seq);
+        if acked {
+            link.acked += 1;
+            link.last_ack = Some(Instant::now());
+        }
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let now = Instant::now();
+        let links = self.links.lock().unwrap();
+        let mut peers: Vec<PeerHealth> = links
+            .iter()
+            .map(|(peer, link)| {
+                let state = match link.pending.values().next() {
+                    Some(oldest) if now.duration_since(*oldest) > self.stall => LinkState::Retrying,
+                    _ if link.last_ack.is_some() => LinkState::Connected,
+                    _ => LinkState::Unknown,
+                };
+                PeerHealth {
+                    peer: *peer,
+                    state,
+                    last_ack_ms: link.last_ack.map(|last_ack| now.duration_since(last_ack).as_millis() as u64),
+                    pending: link.pending.len(),
+                    sent: link.sent,
+                    acked: link.acked,
+                }
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.peer);
+        HealthReport { peers }
+    }
+
+    // The report in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let peers = self.report().peers;
+        let mut metrics = String::new();
+        let connected = peers.iter().map(|peer| (peer.peer, ((peer.state == LinkState::Connected) as u8).to_string()));
+        gauge(&mut metrics, "peer_connected", "Whether the link to the peer is up", connected);
+        let retrying = peers.iter().map(|peer| (peer.peer, ((peer.state == LinkState::Retrying) as u8).to_string()));
+        gauge(&mut metrics, "peer_retrying", "Whether the link to the peer is being reconnected", retrying);
+        let last_ack = peers
+            .iter()
+            .filter_map(|peer| peer.last_ack_ms.map(|ms| (peer.peer, format!("{:.3}", ms as f64 / 1000.0))));
+        gauge(&mut metrics, "peer_last_ack_seconds", "Seconds since the peer last acknowledged a message", last_ack);
+        let pending = peers.iter().map(|peer| (peer.peer, peer.pending.to_string()));
+        gauge(&mut metrics, "peer_pending_messages", "Messages to the peer not acknowledged yet", pending);
+        let sent = peers.iter().map(|peer| (peer.peer, peer.sent.to_string()));
+        gauge(&mut metrics, "peer_sent_messages", "Messages sent to the peer", sent);
+        let acked = peers.iter().map(|peer| (peer.peer, peer.acked.to_string()));
+        gauge(&mut metrics, "peer_acked_messages", "Messages the peer acknowledged", acked);
+        metrics
+    }
+}
+
+fn gauge(metrics: &mut String, name: &str, help: &str, values: impl Iterator<Item = (Replica, String)>) {
+    metrics.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name));
+    for (peer, value) in values {
+        metrics.push_str(&format!("{}{{peer=\"{}\"}} {}\n", name, peer, value));
+    }
+}
+
+// Records when the messages to each peer are sent and acknowledged
+pub struct HealthTransport<T> {
+    health: Health,
+    inner: Box<dyn Transport<T>>,
+}
+
+#[async_trait]
+impl<T: Send + 'static> Transport<T> for HealthTransport<T> {
+    async fn send(&mut self, replica: Replica, msg: T) -> CancelHandler<Acknowledgement> {
+        let seq = self.health.sent(replica);
+        let mut inner_ack = self.inner.send(replica, msg).await;
+        let (mut ack_tx, ack_rx) = oneshot::channel();
+        let health = self.health.clone();
+        tokio::spawn(async move {
+            let ack = tokio::select! {
+                ack = &mut inner_ack => ack.ok(),
+                // Dropping the inner handler gives up on the message below as well
+                _ = ack_tx.closed() => None,
+            };
+            health.settled(replica, seq, ack.is_some());
+            if let Some(ack) = ack {
+                let _ = ack_tx.send(ack);
+            }
+        });
+        ack_rx
+    }
+}
+
+// Send the syncer the health of the links every period
+pub fn spawn_heartbeat(health: Health, myid: Replica, period: Duration, mut sync: Box<dyn Transport<SyncMsg>>) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(period);
+        // Only the latest heartbeat is worth delivering, so replacing its handler gives up on the one before
+        let mut _last: Option<CancelHandler<Acknowledgement>> = None;
+        loop {
+            interval.tick().await;
+            let value = SyncReport::Heartbeat(health.report()).append(Vec::new());
+            _last = Some(
+                sync.send(
+                    0,
+                    SyncMsg {
+                        sender: myid,
+                        state: SyncState::ALIVE,
+                        value,
+                    },
+                )
+                .await,
+            );
+        }
+    });
+}
+
+// Answer every HTTP request at the port with the metrics
+pub fn serve_metrics(port: u16, health: Health) -> Result<(), String> {
+    let address = SocketAddr::V4(SocketAddrV4::new("0.0.0.0".parse().unwrap(), port));
+    let listener = std::net::TcpListener::bind(address)
+        .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        .map_err(|e| format!("Unable to serve metrics at {}: {}", address, e))?;
+    let listener = TcpListener::from_std(listener).map_err(|e| format!("Unable to serve metrics at {}: {}", address, e))?;
+    log::info!("Serving metrics at {}", address);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, remote) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("Failed to accept a metrics connection: {}", e);
+                    continue;
+                }
+            };
+            let health = health.clone();
+            tokio::spawn(async move {
+                // The request is not looked at, every path gets the metrics
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let body = health.metrics();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                if let Err(e) = stream.write_all(response.as_bytes()).await {
+                    log::debug!("Failed to send metrics to {}: {}", remote, e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use tokio::sync::{mpsc::UnboundedSender, Notify, Semaphore};
+use types::Replica;
+
+use crate::{Authenticator, Envelope};
+
+// Messages a lane holds before the connections of its peer have to wait for the protocol
+pub const DEFAULT_INBOX_CAPACITY: usize = 1024;
+// Connections of one peer that may deliver at once. A newer connection replaces the oldest, as after a reconnect.
+pub const MAX_CONNECTIONS_PER_PEER: usize = 2;
+
+// Why the inbox did not take a message
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum InboxError {
+    // The receiving side of the protocol has shut down
+    Closed,
+    // The connection delivered for another peer before, or newer connections of its peer replaced it
+    Refused,
+}
+
+// Bounded queue from the network to a protocol. Every peer gets a lane of its own and the protocol takes messages
+// from the lanes in turn, so a peer flooding its lane waits on its own connections while the others go on. Opening
+// more connections gets a peer neither more space nor more turns.
+pub fn inbox<T>(capacity: usize) -> (InboxSender<T>, InboxReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            lanes: FnvHashMap::default(),
+            turns: VecDeque::new(),
+            next_connection: 0,
+            replaced: HashSet::new(),
+            senders: 0,
+            closed: false,
+        }),
+        capacity,
+        ready: Notify::new(),
+    });
+    let sender = InboxSender::open(shared.clone());
+    (sender, InboxReceiver { shared })
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    // Wakes the receiver when a lane gets a message or the last sender leaves
+    ready: Notify,
+}
+
+struct State<T> {
+    lanes: FnvHashMap<Replica, Lane<T>>,
+    // Lanes with queued messages, served one message at a time
+    turns: VecDeque<Replica>,
+    next_connection: u64,
+    // Connections that newer ones of the same peer replaced
+    replaced: HashSet<u64>,
+    senders: usize,
+    closed: bool,
+}
+
+struct Lane<T> {
+    queue: VecDeque<T>,
+    space: Arc<Semaphore>,
+    // Connections delivering for the peer, oldest first
+    connections: VecDeque<u64>,
+}
+
+// Sender for one connection, which delivers for the peer of its first message only
+pub struct InboxSender<T> {
+    shared: Arc<Shared<T>>,
+    connection: u64,
+    peer: OnceLock<Replica>,
+}
+
+impl<T> InboxSender<T> {
+    fn open(shared: Arc<Shared<T>>) -> InboxSender<T> {
+        let connection = {
+            let mut state = shared.state.lock().unwrap();
+            let connection = state.next_connection;
+            state.next_connection += 1;
+            state.senders += 1;
+            connection
+        };
+        InboxSender {
+            shared,
+            connection,
+            peer: OnceLock::new(),
+        }
+    }
+
+    // Sender for a newly accepted connection
+    pub fn connection(&self) -> InboxSender<T> {
+        InboxSender::open(self.shared.clone())
+    }
+
+    // Space in the lane of the peer, which binds the connection to it on first use
+    fn space(&self, peer: Replica) -> Result<Arc<Semaphore>, InboxError> {
+        let mut guard = self.shared.state.lock().unwrap();
+        let state = &mut *guard;
+        if state.closed {
+            return Err(InboxError::Closed);
+        }
+        let lane = state.lanes.entry(peer).or_insert_with(|| Lane {
+            queue: VecDeque::new(),
+            space: Arc::new(Semaphore::new(self.shared.capacity)),
+            connections: VecDeque::new(),
+        });
+        match self.peer.get() {
+            Some(bound) if *bound != peer => return Err(InboxError::Refused),
+            Some(_) => {}
+            None => {
+                let _ = self.peer.set(peer);
+                lane.connections.push_back(self.connection);
+                if lane.connections.len() > MAX_CONNECTIONS_PER_PEER {
+                    let oldest = lane.connections.pop_front().unwrap();
+                    log::warn!("Node {} opened another connection, refusing its oldest", peer);
+                    state.replaced.insert(oldest);
+                }
+            }
+        }
+        if state.replaced.contains(&self.connection) {
+            return Err(InboxError::Refused);
+        }
+        Ok(lane.space.clone())
+    }
+
+    // Queue a message of the peer, waiting while its lane is full
+    pub async fn send(&self, peer: Replica, msg: T) -> Result<(), InboxError> {
+        let space = self.space(peer)?;
+        let permit = space.acquire().await.map_err(|_| InboxError::Closed)?;
+        // The receiver gives the slot back when it takes the message
+        permit.forget();
+        {
+            let mut guard = self.shared.state.lock().unwrap();
+            let state = &mut *guard;
+            if state.closed {
+                return Err(InboxError::Closed);
+            }
+            let lane = state.lanes.get_mut(&peer).expect("Lanes are never removed");
+            if lane.queue.is_empty() {
+                state.turns.push_back(peer);
+            }
+            lane.queue.push_back(msg);
+        }
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Drop for InboxSender<T> {
+    fn drop(&mut self) {
+        let last = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.senders -= 1;
+            state.replaced.remove(&self.connection);
+            if let Some(lane) = self.peer.get().and_then(|peer| state.lanes.get_mut(peer)) {
+                lane.connections.retain(|connection| *connection != self.connection);
+            }
+            state.senders == 0
+        };
+        if last {
+            self.shared.ready.notify_one();
+        }
+    }
+}
+
+pub struct InboxReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> InboxReceiver<T> {
+    // None once every sender is gone and every lane is empty
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut guard = self.shared.state.lock().unwrap();
+                let state = &mut *guard;
+                while let Some(peer) = state.turns.pop_front() {
+                    let lane = match state.lanes.get_mut(&peer) {
+                        Some(lane) => lane,
+                        None => continue,
+                    };
+                    if let Some(msg) = lane.queue.pop_front() {
+                        lane.space.add_permits(1);
+                        if !lane.queue.is_empty() {
+                            state.turns.push_back(peer);
+                        }
+                        return Some(msg);
+                    }
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            self.shared.ready.notified().await;
+        }
+    }
+}
+
+impl<T> Drop for InboxReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        // Connections waiting for space learn that the protocol is gone
+        for lane in state.lanes.values() {
+            lane.space.close();
+        }
+    }
+}
+
+// Where a listener hands the messages it receives
+#[async_trait]
+pub trait Inbound<T: Send + 'static>: Send + Sync + 'static {
+    // Sender for a newly accepted connection
+    fn connection(&self) -> Self
+    where
+        Self: Sized;
+
+    async fn deliver(&self, msg: T) -> Result<(), InboxError>;
+}
+
+// Inbox of the messages of other nodes. Envelopes are authenticated on the connection that brings them, so each one
+// goes to the lane of the node that sent it, and the protocol does not check them again.
+pub struct PeerInbox {
+    auth: Authenticator,
+    tx: InboxSender<Envelope>,
+}
+
+impl PeerInbox {
+    pub fn new(auth: Authenticator, tx: InboxSender<Envelope>) -> PeerInbox {
+        PeerInbox { auth, tx }
+    }
+}
+
+#[async_trait]
+impl Inbound<Envelope> for PeerInbox {
+    fn connection(&self) -> Self {
+        PeerInbox {
+            auth: self.auth.clone(),
+            tx: self.tx.connection(),
+        }
+    }
+
+    // Envelopes failing authentication are dropped
+    async fn deliver(&self, envelope: Envelope) -> Result<(), InboxError> {
+        for envelope in self.auth.authenticate(envelope) {
+            self.tx.send(envelope.sender, envelope).await?;
+        }
+        Ok(())
+    }
+}
+
+// Syncer messages come from a single trusted client and stay unbounded
+#[async_trait]
+impl<T: Send + 'static> Inbound<T> for UnboundedSender<T> {
+    fn connection(&self) -> Self {
+        self.clone()
+    }
+
+    async fn deliver(&self, msg: T) -> Result<(), InboxError> {
+        self.send(msg).map_err(|_| InboxError::Closed)
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Mutex, OnceLock},
+};
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use network::{plaintcp::CancelHandler, Acknowledgement};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use types::Replica;
+
+use crate::{Envelope, InboxError, Inbound, PeerInbox, Transport};
+
+// Inboxes of the nodes of this process by address. An inbox is created by whichever of its owner and its first sender
+// comes first, so messages sent before the owner starts are kept.
+#[derive(Default)]
+struct Hub {
+    inboxes: HashMap<SocketAddr, UnboundedSender<Envelope>>,
+    unclaimed: HashMap<SocketAddr, UnboundedReceiver<Envelope>>,
+}
+
+impl Hub {
+    fn inbox(&mut self, address: SocketAddr) -> UnboundedSender<Envelope> {
+        if let Some(inbox) = self.inboxes.get(&address) {
+            return inbox.clone();
+        }
+        let (inbox, receiver) = unbounded_channel();
+        self.inboxes.insert(address, inbox.clone());
+        self.unclaimed.insert(address, receiver);
+        inbox
+    }
+}
+
+fn hub() -> &'static Mutex<Hub> {
+    static HUB: OnceLock<Mutex<Hub>> = OnceLock::new();
+    HUB.get_or_init(|| Mutex::new(Hub::default()))
+}
+
+pub struct MemoryTransport {
+    peers: FnvHashMap<Replica, UnboundedSender<Envelope>>,
+}
+
+impl MemoryTransport {
+    pub fn new(
+        my_address: SocketAddr,
+        peers: FnvHashMap<Replica, SocketAddr>,
+        consensus_tx: PeerInbox,
+    ) -> Result<MemoryTransport, String> {
+        let mut hub = hub().lock().unwrap();
+        hub.inbox(my_address);
+        let mut receiver = hub
+            .unclaimed
+            .remove(&my_address)
+            .ok_or_else(|| format!("Another node of this process already listens at {}", my_address))?;
+        // Senders never wait on a receiver. Each node of the process delivers as a connection of its own, so the
+        // messages still land in the lanes of their senders.
+        tokio::spawn(async move {
+            let mut connections: FnvHashMap<Replica, PeerInbox> = FnvHashMap::default();
+            while let Some(envelope) = receiver.recv().await {
+                let connection = connections
+                    .entry(envelope.sender)
+                    .or_insert_with(|| consensus_tx.connection());
+                // A refused message is dropped as on a TCP connection, only a closed inbox ends delivery
+                match connection.deliver(envelope).await {
+                    Ok(()) => {}
+                    Err(InboxError::Closed) => break,
+                    Err(InboxError::Refused) => {
+                        log::warn!("Refusing a message on a connection that delivers for another node or was replaced");
+                    }
+                }
+            }
+        });
+        let peers = peers
+            .into_iter()
+            .map(|(replica, address)| (replica, hub.inbox(address)))
+            .collect();
+        Ok(MemoryTransport { peers })
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&mut self, replica: Replica, envelope: Envelope) -> CancelHandler<Acknowledgement> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        match self.peers.get(&replica) {
+            Some(inbox) => {
+                // Delivery is immediate, so is the acknowledgement
+                if inbox.send(envelope).is_ok() {
+                    let _ = ack_tx.send(Acknowledgement::Pong);
+                } else {
+                    log::warn!("Node {} has shut down", replica);
+                }
+            }
+            None => log::warn!("No address for node {}", replica),
+        }
+        ack_rx
+    }
+}
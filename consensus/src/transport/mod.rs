@@ -0,0 +1,187 @@
+use std::{
+    net::{SocketAddr, SocketAddrV4},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use config::Node;
+use fnv::FnvHashMap;
+use network::{
+    plaintcp::{CancelHandler, TcpReceiver, TcpReliableSender},
+    Acknowledgement,
+};
+use tokio::sync::mpsc::UnboundedSender;
+use types::{Replica, SyncMsg};
+
+use crate::{AdmissionConfig, Authenticator, CompressionKind, Envelope};
+
+mod tcp;
+pub use tcp::*;
+
+mod memory;
+pub use memory::*;
+
+mod quic;
+pub use quic::*;
+
+mod tls;
+pub use tls::*;
+
+mod emulation;
+pub use emulation::*;
+
+mod batch;
+pub use batch::*;
+
+mod inbox;
+pub use inbox::*;
+
+mod health;
+pub use health::*;
+
+// How protocol messages travel between nodes. Syncer traffic always uses TCP, with TLS if configured.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TransportKind {
+    // The plaintcp stack of libnet-rs, one connection per peer
+    Tcp,
+    // Channels between nodes running in the same process
+    Memory,
+    // One QUIC connection per peer with a stream per message, so instances do not block each other
+    Quic,
+}
+
+impl TransportKind {
+    pub fn from_flag(transport: &str) -> Option<TransportKind> {
+        match transport {
+            "tcp" => Some(TransportKind::Tcp),
+            "memory" => Some(TransportKind::Memory),
+            "quic" => Some(TransportKind::Quic),
+            _ => None,
+        }
+    }
+}
+
+// Links of a node: the transport of protocol messages, and mutual TLS on the consensus and syncer ports if set
+#[derive(Clone)]
+pub struct NetworkConfig {
+    pub transport: TransportKind,
+    pub tls: Option<Arc<TlsConfig>>,
+    // WAN conditions imposed on the links between nodes
+    pub emulation: Option<Arc<Emulation>>,
+    // Coalescing of the messages for each peer into frames with one MAC
+    pub batch: Option<BatchConfig>,
+    // Codec dealers compress their payloads with
+    pub compression: CompressionKind,
+    // Largest payload a node accepts, framing of the ordering layer included
+    pub max_payload: usize,
+    // Messages held for each peer before its connections have to wait for the protocol
+    pub inbox_capacity: usize,
+    // Limits on the instances and messages each peer can make a node keep
+    pub admission: AdmissionConfig,
+    // Bytes of payload a dealer encodes and sends at a time in the coded protocols. Whole payloads if absent.
+    pub stripe_size: Option<usize>,
+    // Tracking of the links to the peers, and where it is reported
+    pub health: HealthConfig,
+}
+
+// Tags, proofs and headers a message carries beside its payload
+const MESSAGE_OVERHEAD: usize = 64 * 1024;
+
+impl NetworkConfig {
+    // Largest message a node accepts from a peer: one carrying a whole payload, after the messages a batch may gather
+    // before it
+    pub fn max_message(&self) -> usize {
+        self.max_payload + self.batch.map_or(0, |batch| batch.max_bytes) + MESSAGE_OVERHEAD
+    }
+}
+
+// Sending half of the network between nodes, or between nodes and the syncer
+#[async_trait]
+pub trait Transport<T: Send + 'static = Envelope>: Send {
+    // Deliver a message to a node. The handler resolves once the node acknowledged it, and dropping it gives up on
+    // the message.
+    async fn send(&mut self, replica: Replica, msg: T) -> CancelHandler<Acknowledgement>;
+}
+
+// Listen at the address of this node in the config, handing received messages to consensus_tx, and return the
+// sending half. Batches are tagged with the authenticator. The health of the links is sent to the syncer and served as
+// metrics if configured.
+pub fn new_transport(
+    network: &NetworkConfig,
+    config: &Node,
+    auth: &Authenticator,
+    consensus_tx: InboxSender<Envelope>,
+) -> Result<Box<dyn Transport>, String> {
+    let consensus_tx = PeerInbox::new(auth.clone(), consensus_tx);
+    let mut peers: FnvHashMap<Replica, SocketAddr> = FnvHashMap::default();
+    for (replica, address) in config.net_map.iter() {
+        let address: SocketAddr = address
+            .parse()
+            .map_err(|e| format!("Unable to parse address {} of node {}: {}", address, replica, e))?;
+        peers.insert(*replica, address);
+    }
+    let my_address = *peers
+        .get(&config.id)
+        .ok_or_else(|| format!("No address for node {} in the config", config.id))?;
+    // Listen on all interfaces at the port of this node
+    let listen_address = SocketAddr::V4(SocketAddrV4::new("0.0.0.0".parse().unwrap(), my_address.port()));
+    let tls = network.tls.as_deref();
+    let transport: Box<dyn Transport> = match (network.transport, tls) {
+        (TransportKind::Tcp, None) => Box::new(TcpTransport::new(listen_address, peers, consensus_tx)),
+        (TransportKind::Tcp, Some(tls)) => {
+            tls.listen(listen_address, consensus_tx)?;
+            Box::new(tls.sender::<Envelope>(peers))
+        }
+        // Nodes in the same process are told apart by their configured address
+        (TransportKind::Memory, _) => Box::new(MemoryTransport::new(my_address, peers, consensus_tx)?),
+        (TransportKind::Quic, _) => Box::new(QuicTransport::new(
+            listen_address,
+            peers,
+            consensus_tx,
+            network.max_message(),
+            tls,
+        )?),
+    };
+    // Syncer links are left alone, so the measured latency only covers the emulated network
+    let transport: Box<dyn Transport> = match network.emulation.as_deref() {
+        Some(emulation) => Box::new(emulation.wrap(config.id, transport)),
+        None => transport,
+    };
+    // Watch the links as the protocol experiences them, emulated conditions included. With batching, it counts frames.
+    let health = Health::new(network.health.stall);
+    let transport: Box<dyn Transport> = Box::new(health.wrap(transport));
+    if let Some(period) = network.health.heartbeat {
+        let mut syncer: FnvHashMap<Replica, SocketAddr> = FnvHashMap::default();
+        syncer.insert(0, config.client_addr);
+        spawn_heartbeat(health.clone(), config.id, period, sync_sender(network, syncer));
+    }
+    if let Some(port) = network.health.metrics_port {
+        let port = u16::try_from(port as usize + config.id)
+            .map_err(|_| format!("No metrics port for node {} above {}", config.id, port))?;
+        serve_metrics(port, health)?;
+    }
+    // Batch above the emulation, so that emulated links carry the frames
+    match network.batch {
+        Some(batch) => Ok(Box::new(BatchingTransport::new(auth.clone(), batch, transport))),
+        None => Ok(transport),
+    }
+}
+
+// Listen for syncer messages at the address
+pub fn listen_sync(network: &NetworkConfig, address: SocketAddr, tx: UnboundedSender<SyncMsg>) -> Result<(), String> {
+    match network.tls.as_deref() {
+        Some(tls) => tls.listen(address, tx),
+        None => {
+            TcpReceiver::<Acknowledgement, SyncMsg, _>::spawn(address, SyncHandler::new(tx));
+            Ok(())
+        }
+    }
+}
+
+// Sender of syncer messages to the given peers
+pub fn sync_sender(network: &NetworkConfig, peers: FnvHashMap<Replica, SocketAddr>) -> Box<dyn Transport<SyncMsg>> {
+    match network.tls.as_deref() {
+        Some(tls) => Box::new(tls.sender::<SyncMsg>(peers)),
+        None => Box::new(TcpReliableSender::<Replica, SyncMsg, Acknowledgement>::with_peers(peers)),
+    }
+}
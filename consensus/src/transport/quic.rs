@@ -0,0 +1,274 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use network::{plaintcp::CancelHandler, Acknowledgement};
+use quinn::{ClientConfig, Connection, Endpoint, ServerConfig, TransportConfig, VarInt};
+use tokio::sync::{oneshot, Mutex};
+use types::Replica;
+
+use crate::{Envelope, Inbound, TlsConfig, Transport, TLS_SERVER_NAME};
+
+// Streams a peer may have open at once, i.e. messages in flight to this node. Each may grow to the largest message
+// before the protocol takes it.
+const MAX_STREAMS: u32 = 64;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+pub struct QuicTransport {
+    endpoint: Endpoint,
+    peers: FnvHashMap<Replica, Peer>,
+}
+
+// Connection to a peer, opened on first use and reopened once lost
+#[derive(Clone)]
+struct Peer {
+    address: SocketAddr,
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+
+impl QuicTransport {
+    // Messages longer than max_message are dropped with their stream
+    pub fn new<I: Inbound<Envelope>>(
+        listen_address: SocketAddr,
+        peers: FnvHashMap<Replica, SocketAddr>,
+        consensus_tx: I,
+        max_message: usize,
+        tls: Option<&TlsConfig>,
+    ) -> Result<QuicTransport, String> {
+        let mut endpoint = Endpoint::server(server_config(tls)?, listen_address)
+            .map_err(|e| format!("Unable to listen at {}: {}", listen_address, e))?;
+        endpoint.set_default_client_config(client_config(tls));
+        tokio::spawn(accept(endpoint.clone(), consensus_tx, max_message));
+        let peers = peers
+            .into_iter()
+            .map(|(replica, address)| {
+                (
+                    replica,
+                    Peer {
+                        address,
+                        connection: Arc::new(Mutex::new(None)),
+                    },
+                )
+            })
+            .collect();
+        Ok(QuicTransport { endpoint, peers })
+    }
+}
+
+#[async_trait]
+impl Transport for QuicTransport {
+    async fn send(&mut self, replica: Replica, envelope: Envelope) -> CancelHandler<Acknowledgement> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        let peer = match self.peers.get(&replica) {
+            Some(peer) => peer.clone(),
+            None => {
+                log::warn!("No address for node {}", replica);
+                return ack_rx;
+            }
+        };
+        let bytes = bincode::serialize(&envelope).expect("Failed to serialize envelope");
+        let endpoint = self.endpoint.clone();
+        // Retry like the reliable TCP sender until the peer acknowledges or the caller gives up
+        tokio::spawn(async move {
+            loop {
+                match send_stream(&endpoint, &peer, &bytes).await {
+                    Ok(()) => {
+                        let _ = ack_tx.send(Acknowledgement::Pong);
+                        return;
+                    }
+                    Err(e) => {
+                        log::debug!("Failed to send a message to node {}: {}", replica, e);
+                        peer.connection.lock().await.take();
+                    }
+                }
+                if ack_tx.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        });
+        ack_rx
+    }
+}
+
+// Send a message on a stream of its own. Finishing the stream waits for the peer to acknowledge all of it.
+async fn send_stream(endpoint: &Endpoint, peer: &Peer, bytes: &[u8]) -> Result<(), String> {
+    let connection = connect(endpoint, peer).await?;
+    let mut stream = connection.open_uni().await.map_err(|e| e.to_string())?;
+    stream.write_all(bytes).await.map_err(|e| e.to_string())?;
+    stream.finish().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn connect(endpoint: &Endpoint, peer: &Peer) -> Result<Connection, String> {
+    let mut connection = peer.connection.lock().await;
+    if let Some(open) = connection.as_ref() {
+        if open.close_reason().is_none() {
+            return Ok(open.clone());
+        }
+    }
+    let open = endpoint
+        .connect(peer.address, TLS_SERVER_NAME)
+        .map_err(|e| e.to_string())?
+        .await
+        .map_err(|e| e.to_string())?;
+    *connection = Some(open.clone());
+    Ok(open)
+}
+
+async fn accept<I: Inbound<Envelope>>(endpoint: Endpoint, consensus_tx: I, max_message: usize) {
+    while let Some(connecting) = endpoint.accept().await {
+        // The streams of a connection share its sender
+        let consensus_tx = Arc::new(consensus_tx.connection());
+        tokio::spawn(async move {
+            let connection = match connecting.await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::warn!("Failed to accept a QUIC connection: {}", e);
+                    return;
+                }
+            };
+            // Every message arrives on a stream of its own, so a large message does not hold back the others
+            let remote = connection.remote_address();
+            while let Ok(mut stream) = connection.accept_uni().await {
+                let consensus_tx = consensus_tx.clone();
+                let connection = connection.clone();
+                tokio::spawn(async move {
+                    let bytes = match stream.read_to_end(max_message).await {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            log::warn!("Failed to read a message from {}: {}", remote, e);
+                            return;
+                        }
+                    };
+                    match bincode::deserialize::<Envelope>(&bytes) {
+                        Ok(envelope) => {
+                            if let Err(e) = consensus_tx.deliver(envelope).await {
+                                log::warn!("Closing the connection from {}: {:?}", remote, e);
+                                connection.close(VarInt::from_u32(0), b"refused");
+                            }
+                        }
+                        Err(e) => log::warn!("Received a malformed envelope: {}", e),
+                    }
+                });
+            }
+        });
+    }
+}
+
+fn transport_config() -> TransportConfig {
+    let mut transport = TransportConfig::default();
+    transport.max_concurrent_uni_streams(VarInt::from_u32(MAX_STREAMS));
+    transport.keep_alive_interval(Some(KEEP_ALIVE));
+    transport
+}
+
+// Without TLS settings each node presents a fresh self-signed certificate. Messages carry their own MAC or signature,
+// so the channel then only needs to be private and reliable.
+fn server_config(tls: Option<&TlsConfig>) -> Result<ServerConfig, String> {
+    let mut server_config = match tls {
+        Some(tls) => ServerConfig::with_crypto(tls.server.clone()),
+        None => {
+            let cert = rcgen::generate_simple_self_signed(vec![TLS_SERVER_NAME.to_string()]).map_err(|e| e.to_string())?;
+            let key = rustls::PrivateKey(cert.serialize_private_key_der());
+            let cert = rustls::Certificate(cert.serialize_der().map_err(|e| e.to_string())?);
+            ServerConfig::with_single_cert(vec![cert], key).map_err(|e| e.to_string())?
+        }
+    };
+    server_config.transport_config(Arc::new(transport_config()));
+    Ok(server_config)
+}
+
+fn client_config(tls: Option<&TlsConfig>) -> ClientConfig {
+    let crypto = match tls {
+        Some(tls) => tls.client.clone(),
+        None => Arc::new(
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+                .with_no_client_auth(),
+        ),
+    };
+    let mut client_config = ClientConfig::new(crypto);
+    client_config.transport_config(Arc::new(transport_config()));
+    client_config
+}
+
+struct AcceptAnyCertificate;
+
+impl rustls::client::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde::Serialize;
+    use tokio::{sync::mpsc::unbounded_channel, time::timeout};
+
+    use super::*;
+
+    // Envelope as it travels, without the flags that never leave a node
+    #[derive(Serialize)]
+    struct Wire {
+        sender: Replica,
+        body: Bytes,
+        tag: Vec<u8>,
+        batch: bool,
+    }
+
+    fn envelope(sender: Replica, body: Vec<u8>) -> Envelope {
+        let wire = Wire {
+            sender,
+            body: Bytes::from(body),
+            tag: vec![7; 32],
+            batch: false,
+        };
+        bincode::deserialize(&bincode::serialize(&wire).unwrap()).unwrap()
+    }
+
+    fn free_address() -> SocketAddr {
+        std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_between_two_nodes_and_drops_oversized_messages() {
+        let addresses: FnvHashMap<Replica, SocketAddr> =
+            vec![(0, free_address()), (1, free_address())].into_iter().collect();
+        let max_message = 64 * 1024;
+        let mut inboxes = Vec::new();
+        let mut transports = Vec::new();
+        for replica in 0..2 {
+            let (tx, rx) = unbounded_channel::<Envelope>();
+            let transport = QuicTransport::new(addresses[&replica], addresses.clone(), tx, max_message, None).unwrap();
+            inboxes.push(rx);
+            transports.push(transport);
+        }
+
+        let ack = transports[0].send(1, envelope(0, b"hello".to_vec())).await;
+        let received = timeout(Duration::from_secs(5), inboxes[1].recv()).await.unwrap().unwrap();
+        assert_eq!(received.sender, 0);
+        assert_eq!(&received.body[..], b"hello");
+        assert!(timeout(Duration::from_secs(5), ack).await.unwrap().is_ok());
+
+        let ack = transports[1].send(0, envelope(1, vec![1; 2 * max_message])).await;
+        assert!(timeout(Duration::from_secs(1), inboxes[0].recv()).await.is_err());
+        drop(ack);
+
+        // The link still carries messages within the limit
+        let _ack = transports[1].send(0, envelope(1, b"again".to_vec())).await;
+        let received = timeout(Duration::from_secs(5), inboxes[0].recv()).await.unwrap().unwrap();
+        assert_eq!(&received.body[..], b"again");
+    }
+}
@@ -0,0 +1,117 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use fnv::FnvHashMap;
+use futures_util::SinkExt;
+use network::{
+    plaintcp::{CancelHandler, TcpReceiver, TcpReliableSender},
+    Acknowledgement,
+};
+use tokio::sync::mpsc::UnboundedSender;
+use types::{Replica, SyncMsg};
+
+use crate::{Envelope, InboxError, Inbound, PeerInbox, Transport};
+
+pub struct TcpTransport {
+    sender: TcpReliableSender<Replica, Envelope, Acknowledgement>,
+}
+
+impl TcpTransport {
+    pub fn new(
+        listen_address: SocketAddr,
+        peers: FnvHashMap<Replica, SocketAddr>,
+        consensus_tx: PeerInbox,
+    ) -> TcpTransport {
+        TcpReceiver::<Acknowledgement, Envelope, _>::spawn(listen_address, EnvelopeHandler::new(consensus_tx));
+        TcpTransport {
+            sender: TcpReliableSender::<Replica, Envelope, Acknowledgement>::with_peers(peers),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn send(&mut self, replica: Replica, envelope: Envelope) -> CancelHandler<Acknowledgement> {
+        self.sender.send(replica, envelope).await
+    }
+}
+
+#[async_trait]
+impl Transport<SyncMsg> for TcpReliableSender<Replica, SyncMsg, Acknowledgement> {
+    async fn send(&mut self, replica: Replica, msg: SyncMsg) -> CancelHandler<Acknowledgement> {
+        TcpReliableSender::send(self, replica, msg).await
+    }
+}
+
+// Forwards the messages of a TCP connection to the consensus inbox
+pub struct EnvelopeHandler {
+    consensus_tx: PeerInbox,
+}
+
+impl EnvelopeHandler {
+    pub fn new(consensus_tx: PeerInbox) -> Self {
+        Self { consensus_tx }
+    }
+}
+
+// The receiver clones the handler for every connection it accepts, which gives each connection a sender of its own
+impl Clone for EnvelopeHandler {
+    fn clone(&self) -> Self {
+        Self {
+            consensus_tx: self.consensus_tx.connection(),
+        }
+    }
+}
+
+#[async_trait]
+impl network::Handler<Acknowledgement, Envelope> for EnvelopeHandler {
+    async fn dispatch(&self, msg: Envelope, writer: &mut network::Writer<Acknowledgement>) {
+        // Waiting for space here stops reading the connection, so a peer that sends faster than the protocol keeps up
+        // is held back by TCP flow control
+        match self.consensus_tx.deliver(msg).await {
+            Ok(()) => {}
+            // Without an acknowledgement the peer keeps the message and gives up once this node is gone
+            Err(InboxError::Closed) => {
+                log::warn!("Consensus has shut down, leaving a message from the network unacknowledged");
+                return;
+            }
+            Err(InboxError::Refused) => {
+                log::warn!("Refusing a message on a connection that delivers for another node or was replaced");
+                return;
+            }
+        }
+        // Acknowledge
+        let status = writer.send(Acknowledgement::Pong).await;
+        if status.is_err() {
+            log::error!("Failed to acknowledge consensus message because of {:?}", status.err().unwrap());
+        }
+    }
+}
+
+// Forwards the syncer messages of a TCP connection to the consensus channel
+#[derive(Debug, Clone)]
+pub struct SyncHandler {
+    consensus_tx: UnboundedSender<SyncMsg>,
+}
+
+impl SyncHandler {
+    pub fn new(consensus_tx: UnboundedSender<SyncMsg>) -> Self {
+        Self { consensus_tx }
+    }
+}
+
+#[async_trait]
+impl network::Handler<Acknowledgement, SyncMsg> for SyncHandler {
+    async fn dispatch(&self, msg: SyncMsg, writer: &mut network::Writer<Acknowledgement>) {
+        // Forward the message
+        if let Err(e) = self.consensus_tx.send(msg) {
+            log::warn!("Consensus has shut down, leaving a syncer message unacknowledged: {:?}", e);
+            return;
+        }
+
+        // Acknowledge
+        if let Err(e) = writer.send(Acknowledgement::Pong).await {
+            log::warn!("Failed to acknowledge a syncer message: {:?}", e);
+        }
+    }
+}
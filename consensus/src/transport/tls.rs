@@ -0,0 +1,232 @@
+use std::{io::BufReader, marker::PhantomData, net::SocketAddr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use fnv::FnvHashMap;
+use futures::{SinkExt, StreamExt};
+use network::{plaintcp::CancelHandler, Acknowledgement};
+use rustls::{server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerName};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use types::Replica;
+
+use crate::{Inbound, Transport};
+
+// Name in the certificates of all nodes. Any certificate issued by the root is accepted, and messages carry their own
+// MAC or signature binding them to the sender.
+pub const TLS_SERVER_NAME: &str = "replica";
+// Largest frame accepted on a link
+const MAX_FRAME_SIZE: usize = 1 << 30;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+// Sent back for every frame once it reached the consensus channel
+const ACK: &[u8] = &[1];
+
+// Mutual TLS between nodes and with the syncer. Both ends present a certificate issued by the root of the config.
+pub struct TlsConfig {
+    pub server: Arc<rustls::ServerConfig>,
+    pub client: Arc<rustls::ClientConfig>,
+}
+
+impl TlsConfig {
+    // Build from the PEM root certificate of the config and the PEM certificate chain and key of this node
+    pub fn from_pem(root_cert: &[u8], cert_chain: &[u8], key: &[u8]) -> Result<TlsConfig, String> {
+        let mut roots = RootCertStore::empty();
+        for cert in read_certs(root_cert)? {
+            roots.add(&cert).map_err(|e| format!("Invalid root certificate: {}", e))?;
+        }
+        if roots.is_empty() {
+            return Err("The root_cert of the config holds no certificate".to_string());
+        }
+        let cert_chain = read_certs(cert_chain)?;
+        let key = read_key(key)?;
+        let server = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots.clone()).boxed())
+            .with_single_cert(cert_chain.clone(), key.clone())
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        let client = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(cert_chain, key)
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        Ok(TlsConfig {
+            server: Arc::new(server),
+            client: Arc::new(client),
+        })
+    }
+
+    // Accept TLS connections at the address and hand the messages they carry to tx
+    pub fn listen<T, I>(&self, address: SocketAddr, tx: I) -> Result<(), String>
+    where
+        T: DeserializeOwned + Send + 'static,
+        I: Inbound<T>,
+    {
+        let listener = std::net::TcpListener::bind(address).map_err(|e| format!("Unable to listen at {}: {}", address, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let listener = TcpListener::from_std(listener).map_err(|e| e.to_string())?;
+        let acceptor = TlsAcceptor::from(self.server.clone());
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        log::warn!("Failed to accept a connection at {}: {}", address, e);
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.connection();
+                tokio::spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => receive(stream, tx).await,
+                        // Peers without a certificate of the root end here
+                        Err(e) => log::warn!("TLS handshake with {} failed: {}", peer, e),
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    pub fn sender<T>(&self, peers: FnvHashMap<Replica, SocketAddr>) -> TlsSender<T>
+    where
+        T: Serialize + Send + 'static,
+    {
+        let connector = TlsConnector::from(self.client.clone());
+        let peers = peers
+            .into_iter()
+            .map(|(replica, address)| {
+                let (queue, pending) = unbounded_channel();
+                tokio::spawn(deliver(replica, address, connector.clone(), pending));
+                (replica, queue)
+            })
+            .collect();
+        TlsSender {
+            peers,
+            _msg: PhantomData,
+        }
+    }
+}
+
+// Reliable sender over TLS links, one connection and one ordered queue per peer like the plaintcp sender
+pub struct TlsSender<T> {
+    peers: FnvHashMap<Replica, UnboundedSender<(Bytes, oneshot::Sender<Acknowledgement>)>>,
+    _msg: PhantomData<fn(T)>,
+}
+
+#[async_trait]
+impl<T> Transport<T> for TlsSender<T>
+where
+    T: Serialize + Send + 'static,
+{
+    async fn send(&mut self, replica: Replica, msg: T) -> CancelHandler<Acknowledgement> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        let bytes = Bytes::from(bincode::serialize(&msg).expect("Failed to serialize message"));
+        match self.peers.get(&replica) {
+            Some(queue) => {
+                if queue.send((bytes, ack_tx)).is_err() {
+                    log::error!("Link to node {} has shut down", replica);
+                }
+            }
+            None => log::warn!("No address for node {}", replica),
+        }
+        ack_rx
+    }
+}
+
+// Send the queued messages of a peer in order, reconnecting until each one is acknowledged or given up on
+async fn deliver(
+    replica: Replica,
+    address: SocketAddr,
+    connector: TlsConnector,
+    mut pending: UnboundedReceiver<(Bytes, oneshot::Sender<Acknowledgement>)>,
+) {
+    let server_name = ServerName::try_from(TLS_SERVER_NAME).expect("Valid server name");
+    let mut link = None;
+    while let Some((bytes, ack_tx)) = pending.recv().await {
+        while !ack_tx.is_closed() {
+            if link.is_none() {
+                match connect(address, &connector, server_name.clone()).await {
+                    Ok(connected) => link = Some(connected),
+                    Err(e) => {
+                        log::debug!("Failed to connect to node {} at {}: {}", replica, address, e);
+                        tokio::time::sleep(RETRY_DELAY).await;
+                        continue;
+                    }
+                }
+            }
+            let framed = link.as_mut().unwrap();
+            let acked = match framed.send(bytes.clone()).await {
+                Ok(()) => matches!(framed.next().await, Some(Ok(ack)) if &ack[..] == ACK),
+                Err(_) => false,
+            };
+            if acked {
+                let _ = ack_tx.send(Acknowledgement::Pong);
+                break;
+            }
+            log::debug!("Lost the link to node {}, reconnecting", replica);
+            link = None;
+        }
+    }
+}
+
+async fn connect(
+    address: SocketAddr,
+    connector: &TlsConnector,
+    server_name: ServerName,
+) -> Result<Framed<tokio_rustls::client::TlsStream<TcpStream>, LengthDelimitedCodec>, String> {
+    let stream = TcpStream::connect(address).await.map_err(|e| e.to_string())?;
+    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+    let stream = connector.connect(server_name, stream).await.map_err(|e| e.to_string())?;
+    Ok(Framed::new(stream, codec()))
+}
+
+async fn receive<S, T, I>(stream: S, tx: I)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: DeserializeOwned + Send + 'static,
+    I: Inbound<T>,
+{
+    let mut framed = Framed::new(stream, codec());
+    while let Some(Ok(frame)) = framed.next().await {
+        match bincode::deserialize::<T>(&frame) {
+            Ok(msg) => {
+                // Waiting here stops reading the connection until the protocol catches up
+                if let Err(e) = tx.deliver(msg).await {
+                    log::warn!("Closing the connection: {:?}", e);
+                    return;
+                }
+            }
+            Err(e) => log::warn!("Received a malformed message: {}", e),
+        }
+        if framed.send(Bytes::from_static(ACK)).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder().max_frame_length(MAX_FRAME_SIZE).new_codec()
+}
+
+fn read_certs(pem: &[u8]) -> Result<Vec<Certificate>, String> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem)).map_err(|e| format!("Invalid PEM certificate: {}", e))?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(pem: &[u8]) -> Result<PrivateKey, String> {
+    let keys = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(pem)).map_err(|e| format!("Invalid PEM key: {}", e))?;
+    keys.into_iter()
+        .next()
+        .map(PrivateKey)
+        .ok_or_else(|| "No PKCS#8 private key in the key file".to_string())
+}
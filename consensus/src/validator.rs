@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use crate::Compression;
+
+// Application-level validity predicate on broadcast payloads. Honest nodes only echo
+// and deliver payloads it accepts, which turns each RBC into a validated broadcast.
+// The predicate sees the payload as broadcast, i.e. including causal dependencies in causal delivery mode.
+pub type Validator = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+pub fn accept_all() -> Validator {
+    Arc::new(|_payload: &[u8]| true)
+}
+
+// Payload of a terminated instance: its frame decompressed, if that works and the predicate accepts the payload.
+// Honest nodes deliver the same frame, so they all reject the same instances and deliver nothing for them.
+pub fn validated(compression: &Compression, validator: &Validator, frame: &[u8]) -> Option<Vec<u8>> {
+    compression.decompress(frame).filter(|payload| validator(payload))
+}
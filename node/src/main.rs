@@ -1,13 +1,14 @@
 use anyhow::{anyhow, Result};
 use clap::{load_yaml, App};
 use config::Node;
+use consensus::{accept_all, rbc_service, AdmissionConfig, AuthKind, BatchConfig, CodecKind, CompressionKind, DeliveryMode, DEFAULT_INBOX_CAPACITY, Emulation, HashKind, HealthConfig, NetworkConfig, RBCOptions, TlsConfig, TransportKind, Validator};
 use fnv::FnvHashMap;
 use node::Syncer;
 use signal_hook::{
     consts::{SIGINT, SIGTERM},
     iterator::Signals,
 };
-use std::{fs::File, io::{self, BufRead}, net::{SocketAddr, SocketAddrV4}};
+use std::{fs::File, io::{self, BufRead}, net::{SocketAddr, SocketAddrV4}, sync::Arc, time::Duration};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -47,6 +48,72 @@ async fn main() -> Result<()> {
             panic!("Byz flag invalid value");
         }
     };
+    let ordering_flag = m.value_of("ordering").unwrap_or("none");
+    let delivery_mode = match DeliveryMode::from_flag(ordering_flag) {
+        Some(mode) => mode,
+        None => {
+            panic!("Ordering flag invalid value. found: {}", ordering_flag);
+        }
+    };
+    let gather_flag = m.value_of("gather").unwrap_or("false");
+    let gather: bool = match gather_flag {
+        "true" => true,
+        "false" => false,
+        _ => {
+            panic!("Gather flag invalid value. found: {}", gather_flag);
+        }
+    };
+    let codec_flag = m.value_of("codec").unwrap_or("rs");
+    let codec = match CodecKind::from_flag(codec_flag) {
+        Some(codec) => codec,
+        None => {
+            panic!("Codec flag invalid value. found: {}", codec_flag);
+        }
+    };
+    let hash_flag = m.value_of("hash").unwrap_or("sha256");
+    let hash = match HashKind::from_flag(hash_flag) {
+        Some(hash) => hash,
+        None => {
+            panic!("Hash flag invalid value. found: {}", hash_flag);
+        }
+    };
+    let commitment_flag = m.value_of("commitment").unwrap_or("merkle");
+    let commitment = match ctrbc::CommitmentKind::from_flag(commitment_flag) {
+        Some(commitment) => commitment,
+        None => {
+            panic!("Commitment flag invalid value. found: {}", commitment_flag);
+        }
+    };
+    let auth_flag = m.value_of("auth").unwrap_or("mac");
+    let auth = match AuthKind::from_flag(auth_flag) {
+        Some(auth) => auth,
+        None => {
+            panic!("Auth flag invalid value. found: {}", auth_flag);
+        }
+    };
+    let transport_flag = m.value_of("transport").unwrap_or("tcp");
+    let transport = match TransportKind::from_flag(transport_flag) {
+        Some(transport) => transport,
+        None => {
+            panic!("Transport flag invalid value. found: {}", transport_flag);
+        }
+    };
+    let compression_flag = m.value_of("compression").unwrap_or("none");
+    let compression = match CompressionKind::from_flag(compression_flag) {
+        Some(compression) => compression,
+        None => {
+            panic!("Compression flag invalid value. found: {}", compression_flag);
+        }
+    };
+    let dag_rbc = m.value_of("rbc").unwrap_or("ctrbc");
+    let dag_rounds: usize = m
+        .value_of("rounds")
+        .unwrap_or("50")
+        .parse()
+        .expect("Unable to parse number of DAG rounds");
+    if dag_rounds == 0 {
+        panic!("The DAG needs at least one round. found: {}", dag_rounds);
+    }
     let conf_file = std::path::Path::new(conf_str);
     let str = String::from(conf_str);
     let mut config = match conf_file
@@ -74,54 +141,158 @@ async fn main() -> Result<()> {
         config.update_config(file_to_ips(f.to_string()));
     }
     let config = config;
+    // Mutual TLS with certificates issued by the root_cert of the config
+    let tls = match (m.value_of("tls_cert"), m.value_of("tls_key")) {
+        (Some(cert), Some(key)) => {
+            let cert = std::fs::read(cert).expect("Unable to read the TLS certificate");
+            let key = std::fs::read(key).expect("Unable to read the TLS key");
+            Some(Arc::new(
+                TlsConfig::from_pem(&config.root_cert, &cert, &key).expect("Invalid TLS setup"),
+            ))
+        }
+        (None, None) => None,
+        _ => panic!("TLS needs both a certificate and a key"),
+    };
+    let emulation = m.value_of("emulate").map(|file| {
+        log::info!("Emulating the links described in {}", file);
+        Arc::new(Emulation::from_file(file).expect("Invalid emulation file"))
+    });
+    // Batching is on once any of its parameters is given
+    let batch = if m.is_present("batch_window") || m.is_present("batch_messages") || m.is_present("batch_bytes") {
+        let default = BatchConfig::default();
+        let window_us: u64 = m
+            .value_of("batch_window")
+            .map(|w| w.parse().expect("Unable to parse the batch window"))
+            .unwrap_or(default.window.as_micros() as u64);
+        Some(BatchConfig {
+            window: Duration::from_micros(window_us),
+            max_messages: m
+                .value_of("batch_messages")
+                .map(|n| n.parse().expect("Unable to parse the batch size"))
+                .unwrap_or(default.max_messages),
+            max_bytes: m
+                .value_of("batch_bytes")
+                .map(|n| n.parse().expect("Unable to parse the batch byte limit"))
+                .unwrap_or(default.max_bytes),
+        })
+    } else {
+        None
+    };
+    let default = AdmissionConfig::default();
+    let admission = AdmissionConfig {
+        window: m
+            .value_of("instance_window")
+            .map(|n| n.parse().expect("Unable to parse the instance window"))
+            .unwrap_or(default.window),
+        open_quota: m
+            .value_of("open_quota")
+            .map(|n| n.parse().expect("Unable to parse the open instance quota")),
+        rate: m
+            .value_of("rate_limit")
+            .map(|r| r.parse().expect("Unable to parse the rate limit")),
+        park: m
+            .value_of("park_capacity")
+            .map(|n| n.parse().expect("Unable to parse the park capacity"))
+            .unwrap_or(default.park),
+    };
+    let default = HealthConfig::default();
+    let health = HealthConfig {
+        stall: m
+            .value_of("stall")
+            .map(|ms| Duration::from_millis(ms.parse().expect("Unable to parse the stall time")))
+            .unwrap_or(default.stall),
+        // A period of 0 turns heartbeats off
+        heartbeat: match m.value_of("heartbeat") {
+            Some(ms) => match ms.parse().expect("Unable to parse the heartbeat period") {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            None => default.heartbeat,
+        },
+        metrics_port: m
+            .value_of("metrics_port")
+            .map(|port| port.parse().expect("Unable to parse the metrics port")),
+    };
+    let network = NetworkConfig {
+        transport,
+        tls,
+        emulation,
+        batch,
+        compression,
+        // Room for the dependencies the ordering layer and the DAG put around the payload
+        max_payload: msg_size as usize + (1 << 20),
+        inbox_capacity: m
+            .value_of("inbox_capacity")
+            .map(|n| n.parse().expect("Unable to parse the inbox capacity"))
+            .unwrap_or(DEFAULT_INBOX_CAPACITY),
+        admission,
+        stripe_size: m
+            .value_of("stripe_size")
+            .map(|n| n.parse().expect("Unable to parse the stripe size")),
+        health,
+    };
     // Start the Reliable Broadcast protocol
+    let options = RBCOptions {
+        byz: node_normal,
+        crash: node_crash,
+        delivery_mode,
+        codec,
+        hash,
+        auth,
+        network: network.clone(),
+        validator: accept_all(),
+    };
+    let message = input_value.as_bytes().to_vec();
     let exit_tx;
+    let mut rbc_exit_tx = None;
     match vss_type {
         "rbc" => {
-            exit_tx = rbc::Context::spawn(
-                config,
-                input_value.as_bytes().to_vec(),
-                node_normal,
-                node_crash,
-            )
-            .unwrap();
+            exit_tx = rbc::Context::spawn(config, message, options).unwrap();
         }
         "addrbc" => {
-            exit_tx = addrbc::Context::spawn(
-                config,
-                input_value.as_bytes().to_vec(),
-                node_normal,
-                node_crash,
-            )
-            .unwrap();
+            exit_tx = addrbc::Context::spawn(config, message, options).unwrap();
         }
         "ccbrb" => {
-            exit_tx = ccbrb::Context::spawn(
-                config,
-                input_value.as_bytes().to_vec(),
-                node_normal,
-                node_crash,
-            )
-            .unwrap();
+            exit_tx = ccbrb::Context::spawn(config, message, options, gather).unwrap();
         }
         "ctrbc" => {
-            exit_tx = ctrbc::Context::spawn(
-                config,
-                input_value.as_bytes().to_vec(),
-                node_normal,
-                node_crash,
-            )
-            .unwrap();
+            exit_tx = ctrbc::Context::spawn(config, message, options, commitment).unwrap();
         }
         "borbc" => {
-            exit_tx = borbc::Context::spawn(
+            exit_tx = borbc::Context::spawn(config, message, options).unwrap();
+        }
+        "aba" => {
+            exit_tx = aba::Context::spawn(
                 config,
-                input_value.as_bytes().to_vec(),
+                message,
                 node_normal,
                 node_crash,
+                auth,
+                network,
             )
             .unwrap();
         }
+        "dag" => {
+            // The DAG layer talks to the syncer and disseminates vertices through the chosen RBC
+            let (service, handle) = rbc_service();
+            // Honest nodes only deliver payloads that are well-formed vertices
+            let vertex_validator: Validator =
+                Arc::new(|payload: &[u8]| bincode::deserialize::<dag::Vertex>(payload).is_ok());
+            let options = RBCOptions {
+                validator: vertex_validator,
+                ..options
+            };
+            rbc_exit_tx = Some(match dag_rbc {
+                "rbc" => rbc::Context::spawn_service(config.clone(), service, options),
+                "addrbc" => addrbc::Context::spawn_service(config.clone(), service, options),
+                "ccbrb" => ccbrb::Context::spawn_service(config.clone(), service, options),
+                "ctrbc" => ctrbc::Context::spawn_service(config.clone(), service, options, commitment),
+                "borbc" => borbc::Context::spawn_service(config.clone(), service, options),
+                _ => panic!("Invalid RBC protocol for the DAG. found: {}", dag_rbc),
+            }
+            .unwrap());
+            exit_tx = dag::Context::spawn(config, handle, dag_rounds, network).unwrap();
+        }
         "sync" => {
             let f_str = syncer_file.to_string();
             log::info!("Logging the file f {}", f_str);
@@ -137,6 +308,7 @@ async fn main() -> Result<()> {
                 net_map,
                 config.client_addr.clone(),
                 msg_size,
+                network,
             )
             .unwrap();
         }
@@ -156,6 +328,11 @@ async fn main() -> Result<()> {
     exit_tx
         .send(())
         .map_err(|_| anyhow!("Server already shut down"))?;
+    if let Some(rbc_exit_tx) = rbc_exit_tx {
+        rbc_exit_tx
+            .send(())
+            .map_err(|_| anyhow!("RBC service already shut down"))?;
+    }
     log::error!("Shutting down server");
     Ok(())
 }
@@ -1,15 +1,13 @@
 use std::{
     collections::{HashMap, HashSet},
     net::{SocketAddr, SocketAddrV4},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{anyhow, Result};
+use consensus::{listen_sync, sync_sender, HealthReport, LinkState, NetworkConfig, SyncReport, Transport};
 use fnv::FnvHashMap;
-use network::{
-    plaintcp::{CancelHandler, TcpReceiver, TcpReliableSender},
-    Acknowledgement,
-};
+use network::{plaintcp::CancelHandler, Acknowledgement};
 
 use serde::{Deserialize, Serialize};
 use tokio::{
@@ -21,8 +19,6 @@ use tokio::{
 };
 use types::{Replica, SyncMsg, SyncState};
 
-use crate::SyncHandler;
-
 pub struct Syncer {
     pub num_nodes: usize,
     pub ready_for_broadcast: bool,
@@ -32,6 +28,13 @@ pub struct Syncer {
     pub rbc_start_times: HashMap<usize, u128>,
     pub rbc_complete_times: HashMap<usize, HashMap<Replica, u128>>,
     pub rbc_comp_values: HashMap<usize, HashSet<Vec<u8>>>,
+    pub aba_rounds: HashMap<Replica, usize>,
+    // Rounds built, vertices committed and average commit latency of each DAG node
+    pub dag_stats: HashMap<Replica, (usize, usize, u128)>,
+    // (instance id, dealer) pairs each node blamed
+    pub blamed: HashMap<Replica, Vec<(usize, Replica)>>,
+    // Instance ids in the Gather output of each CCRBC node
+    pub gathered: HashMap<Replica, Vec<usize>>,
 
     pub broadcast_msgs: Vec<u8>,
 
@@ -39,12 +42,19 @@ pub struct Syncer {
     pub recon_start_time: u128,
     pub net_map: FnvHashMap<Replica, String>,
     pub alive: HashSet<Replica>,
+    // Latest heartbeat of each node and when it came
+    pub heartbeats: HashMap<Replica, (Instant, HealthReport)>,
+    // When each node first reported alive, so that a node crashing before its first heartbeat is suspected too
+    pub alive_since: HashMap<Replica, Instant>,
+    pub heartbeat: Option<Duration>,
+    // Nodes that stopped sending heartbeats or that most nodes cannot reach
+    pub suspected: HashSet<Replica>,
     pub timings: HashMap<Replica, u128>,
 
     pub cli_addr: SocketAddr,
 
     pub rx_net: UnboundedReceiver<SyncMsg>,
-    pub net_send: TcpReliableSender<Replica, SyncMsg, Acknowledgement>,
+    pub net_send: Box<dyn Transport<SyncMsg>>,
 
     exit_rx: oneshot::Receiver<()>,
     /// Cancel Handlers
@@ -56,15 +66,13 @@ impl Syncer {
         net_map: FnvHashMap<Replica, String>,
         cli_addr: SocketAddr,
         rbc_msg_size: u64,
+        network: NetworkConfig,
     ) -> anyhow::Result<oneshot::Sender<()>> {
         let (exit_tx, exit_rx) = oneshot::channel();
         let (tx_net_to_server, rx_net_to_server) = unbounded_channel();
         let cli_addr_sock = cli_addr.port();
         let new_sock_address = SocketAddrV4::new("0.0.0.0".parse().unwrap(), cli_addr_sock);
-        TcpReceiver::<Acknowledgement, SyncMsg, _>::spawn(
-            std::net::SocketAddr::V4(new_sock_address),
-            SyncHandler::new(tx_net_to_server),
-        );
+        listen_sync(&network, std::net::SocketAddr::V4(new_sock_address), tx_net_to_server).map_err(anyhow::Error::msg)?;
         
         let mut broadcast_msgs = Vec::new();
         for _ in 0..rbc_msg_size{
@@ -77,8 +85,8 @@ impl Syncer {
             let address: SocketAddr = address.parse().expect("Unable to parse address");
             server_addrs.insert(*replica, SocketAddr::from(address.clone()));
         }
-        let net_send =
-            TcpReliableSender::<Replica, SyncMsg, Acknowledgement>::with_peers(server_addrs);
+        let net_send = sync_sender(&network, server_addrs);
+        let heartbeat = network.health.heartbeat;
         tokio::spawn(async move {
             let mut syncer = Syncer {
                 net_map: net_map.clone(),
@@ -89,6 +97,10 @@ impl Syncer {
                 rbc_start_times: HashMap::default(),
                 rbc_complete_times: HashMap::default(),
                 rbc_comp_values: HashMap::default(),
+                aba_rounds: HashMap::default(),
+                dag_stats: HashMap::default(),
+                blamed: HashMap::default(),
+                gathered: HashMap::default(),
 
                 broadcast_msgs: broadcast_msgs,
 
@@ -96,6 +108,10 @@ impl Syncer {
                 recon_start_time: 0,
                 num_nodes: net_map.len(),
                 alive: HashSet::default(),
+                heartbeats: HashMap::default(),
+                alive_since: HashMap::default(),
+                heartbeat,
+                suspected: HashSet::default(),
 
                 timings: HashMap::default(),
                 cli_addr: cli_addr,
@@ -137,9 +153,20 @@ impl Syncer {
                         anyhow!("Networking layer has closed")
                     )?;
                     match msg.state{
+                        // Heartbeats carry the node's view of its links, while the first ALIVE is empty
+                        SyncState::ALIVE if !msg.value.is_empty() => {
+                            match bincode::deserialize::<SyncReport>(&msg.value) {
+                                Ok(SyncReport::Heartbeat(report)) => {
+                                    self.heartbeats.insert(msg.sender, (Instant::now(), report));
+                                },
+                                Ok(report) => log::warn!("Node {} sent a {:?} report with ALIVE", msg.sender, report),
+                                Err(e) => log::warn!("Unable to read the heartbeat of node {}: {}", msg.sender, e),
+                            }
+                        },
                         SyncState::ALIVE=>{
                             log::debug!("Got ALIVE message from node {}",msg.sender);
                             self.alive.insert(msg.sender);
+                            self.alive_since.entry(msg.sender).or_insert_with(Instant::now);
                             if self.alive.len() == self.num_nodes{
                                 self.ready_for_broadcast = true;
                             }
@@ -151,7 +178,30 @@ impl Syncer {
                             // log::info!("Got COMPLETED message from node {} with value {:?}",msg.sender, msg.value.clone());
 
                             // deserialize message
-                            let rbc_msg: RBCSyncMsg = bincode::deserialize(&msg.value).expect("Unable to deserialize message received from node");
+                            let mut reader: &[u8] = &msg.value;
+                            let rbc_msg: RBCSyncMsg = bincode::deserialize_from(&mut reader).expect("Unable to deserialize message received from node");
+                            // ABA, DAG and RBC nodes may follow it with a report, which its tag tells apart
+                            if !reader.is_empty() {
+                                match bincode::deserialize::<SyncReport>(reader) {
+                                    Ok(SyncReport::Rounds(rounds)) => {
+                                        self.aba_rounds.insert(msg.sender, rounds);
+                                    },
+                                    Ok(SyncReport::Dag { rounds, committed_vertices, commit_latency }) => {
+                                        self.dag_stats.insert(msg.sender, (rounds, committed_vertices, commit_latency));
+                                    },
+                                    Ok(SyncReport::Blame(blamed)) => {
+                                        self.blamed.insert(msg.sender, blamed);
+                                    },
+                                    Ok(SyncReport::Gather { gathered, blamed }) => {
+                                        if !blamed.is_empty() {
+                                            self.blamed.insert(msg.sender, blamed);
+                                        }
+                                        self.gathered.insert(msg.sender, gathered);
+                                    },
+                                    Ok(report) => log::warn!("Node {} sent a {:?} report with COMPLETED", msg.sender, report),
+                                    Err(e) => log::warn!("Unable to read the report of node {}: {}", msg.sender, e),
+                                }
+                            }
 
 
                             let latency_map = self.rbc_complete_times.entry(0).or_default();
@@ -201,6 +251,27 @@ impl Syncer {
                                 else{
                                     log::info!("All n nodes completed the protocol for ID: {} with latency {:?} ", rbc_msg.id,vec_times);
                                 }
+                                if !self.aba_rounds.is_empty(){
+                                    let mut vec_rounds: Vec<usize> = self.aba_rounds.values().cloned().collect();
+                                    vec_rounds.sort();
+                                    log::info!("Rounds taken by nodes for ID: {}: {:?}", rbc_msg.id, vec_rounds);
+                                }
+                                if !self.suspected.is_empty(){
+                                    let mut suspected: Vec<&Replica> = self.suspected.iter().collect();
+                                    suspected.sort();
+                                    log::warn!("Nodes suspected to have crashed: {:?}", suspected);
+                                }
+                                for (rep, blamed) in self.blamed.iter(){
+                                    log::info!("Node {} blamed (instance id, dealer) pairs {:?}", rep, blamed);
+                                }
+                                for (rep, gathered) in self.gathered.iter(){
+                                    log::info!("Node {} output the Gather set {:?}", rep, gathered);
+                                }
+                                for (rep, (rounds, committed, commit_latency)) in self.dag_stats.iter(){
+                                    let elapsed = latency_map.get(rep).unwrap() - start_time;
+                                    log::info!("Node {} committed {} vertices in {} rounds and {} ms ({} vertices/s) with average commit latency {} ms",
+                                        rep, committed, rounds, elapsed, (*committed as u128 * 1000) / elapsed.max(1), commit_latency);
+                                }
                                 if self.rbc_id >= self.num_nodes * self.broadcast_msgs.len(){
                                     self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:"".to_string().into_bytes()}).await;
                                 }
@@ -210,6 +281,7 @@ impl Syncer {
                     }
                 },
                 _ = interval.tick() => {
+                    self.check_health();
                     if self.ready_for_broadcast{
                         // Initiate new broadcast
                         if self.rbc_id >= 1{
@@ -274,6 +346,51 @@ impl Syncer {
     pub fn add_cancel_handler(&mut self, canc: CancelHandler<Acknowledgement>) {
         self.cancel_handlers.push(canc);
     }
+
+    // Suspect the nodes whose heartbeats stopped for three periods or never came in three periods after they reported
+    // alive, and those that most nodes with recent heartbeats keep reconnecting to. Suspicions clear once both recover.
+    fn check_health(&mut self) {
+        let period = match self.heartbeat {
+            Some(period) => period,
+            None => return,
+        };
+        let now = Instant::now();
+        let recent: Vec<&HealthReport> = self
+            .heartbeats
+            .values()
+            .filter(|(received, _)| now.duration_since(*received) <= 3 * period)
+            .map(|(_, report)| report)
+            .collect();
+        for replica in 0..self.num_nodes {
+            let last_heard = self
+                .heartbeats
+                .get(&replica)
+                .map(|(received, _)| *received)
+                .or_else(|| self.alive_since.get(&replica).copied());
+            let silent = last_heard.is_some_and(|last_heard| now.duration_since(last_heard) > 3 * period);
+            let retrying = recent
+                .iter()
+                .filter(|report| {
+                    report
+                        .peers
+                        .iter()
+                        .any(|peer| peer.peer == replica && peer.state == LinkState::Retrying)
+                })
+                .count();
+            let unreachable = retrying > 0 && 2 * retrying > recent.len();
+            if silent || unreachable {
+                if self.suspected.insert(replica) {
+                    log::warn!(
+                        "Node {} is suspected to have crashed: {}",
+                        replica,
+                        if silent { "its heartbeats stopped" } else { "most nodes keep reconnecting to it" }
+                    );
+                }
+            } else if self.suspected.remove(&replica) {
+                log::info!("Node {} recovered and is no longer suspected to have crashed", replica);
+            }
+        }
+    }
 }
 
 #[derive(Debug,Serialize,Deserialize,Clone)]
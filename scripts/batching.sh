@@ -0,0 +1,48 @@
+#!/bin/bash
+
+# Runs ccbrb and addrbc with and without batching, and prints the frames the nodes sent next to the latencies the
+# syncer reported. Build the release binary and create the test data for the nodes first.
+# Usage: ./scripts/batching.sh [<num_nodes> <batch_window_us>]
+# Defaults:
+#   <num_nodes> = 16
+#   <batch_window_us> = 500
+
+NUM_NODES=${1:-16}
+WINDOW=${2:-500}
+METRICS_PORT=9100
+TIMEOUT=120
+TESTDATA_FILE="testdata/test_msgs.txt"
+
+mkdir -p logs
+echo "Protocol | Batching | Frames sent | Latencies (ms)"
+echo "-------------------------------------------------"
+for protocol in ccbrb addrbc; do
+    for batching in false true; do
+        extra="--metrics-port $METRICS_PORT"
+        if [ "$batching" == "true" ]; then
+            extra="$extra --batch-window $WINDOW"
+        fi
+        EXTRA="$extra" ./scripts/test.sh testdata/hyb_"$NUM_NODES"/syncer Hi false "$TESTDATA_FILE" "$protocol" "$NUM_NODES" false
+
+        # Wait for the syncer to report that all nodes completed
+        latency=""
+        for ((s=0; s<TIMEOUT; s++)); do
+            sleep 1
+            latency=$(grep -o "All n nodes completed the protocol .* with latency .*" logs/syncer.log | head -1 | sed 's/.*with latency //')
+            if [ -n "$latency" ]; then
+                break
+            fi
+        done
+
+        # Links are watched below the batching layer, so with batching the nodes count frames
+        frames=0
+        for ((i=0; i<NUM_NODES; i++)); do
+            sent=$(curl -s "http://127.0.0.1:$((METRICS_PORT + i))/metrics" | awk '/^peer_sent_messages/ {sum += $2} END {print sum + 0}')
+            frames=$((frames + sent))
+        done
+        echo "$protocol | $batching | $frames | ${latency:-timed out}"
+
+        pkill -f "./target/release/node"
+        sleep 2
+    done
+done
@@ -0,0 +1,48 @@
+#!/bin/bash
+
+# Generate a root certificate and a certificate per replica for mutual TLS, and store the root in the
+# root_cert field of the configs of the test directory
+
+if [ "$#" -ne 2 ]; then
+  echo "Usage: $0 <test_dir> <num_nodes>"
+  exit 1
+fi
+
+testdir=$1
+num_nodes=$2
+tlsdir=$testdir/tls
+
+mkdir -p $tlsdir
+openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes \
+  -keyout $tlsdir/ca.key -out $tlsdir/ca.pem -days 3650 -subj "/CN=rbc-root" 2> /dev/null
+
+# Nodes check that peers hold a certificate of the root under the shared name "replica"
+issue() {
+  name=$1
+  openssl req -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes \
+    -keyout $tlsdir/$name.key -out $tlsdir/$name.csr -subj "/CN=$name" 2> /dev/null
+  openssl x509 -req -in $tlsdir/$name.csr -CA $tlsdir/ca.pem -CAkey $tlsdir/ca.key -CAcreateserial \
+    -days 3650 -out $tlsdir/$name.pem \
+    -extfile <(printf "subjectAltName=DNS:replica\nextendedKeyUsage=serverAuth,clientAuth") 2> /dev/null
+  rm $tlsdir/$name.csr
+}
+
+for ((i = 0; i < num_nodes; i++)); do
+  issue node$i
+done
+issue syncer
+
+python3 - "$testdir" "$tlsdir/ca.pem" <<'PY'
+import glob, json, sys
+testdir, ca = sys.argv[1], sys.argv[2]
+root_cert = list(open(ca, 'rb').read())
+for path in glob.glob(testdir + '/*.json'):
+    with open(path) as f:
+        config = json.load(f)
+    if 'root_cert' in config:
+        config['root_cert'] = root_cert
+        with open(path, 'w') as f:
+            json.dump(config, f, indent=2)
+PY
+
+echo "Certificates written to $tlsdir"
@@ -14,6 +14,9 @@ protocol=${5:-rbc}
 NUM_NODES=${6:-4}
 TESTDIR=${TESTDIR:="testdata/hyb_$NUM_NODES"}
 crash=${7:-true}
+ordering=${8:-none}
+# Further flags for the syncer and the nodes, e.g. EXTRA="--batch-window 500" or EXTRA="--heartbeat 1000"
+EXTRA=${EXTRA:=""}
 
 # Run the syncer
 ./target/$TYPE/node \
@@ -24,7 +27,7 @@ crash=${7:-true}
     --syncer "$1" \
     --msg_size "$4" \
     --byzantine false \
-    --crash false > logs/syncer.log &
+    --crash false $EXTRA > logs/syncer.log &
 
 
 # Run all the nodes
@@ -37,8 +40,10 @@ for ((i=0; i<NUM_NODES; i++)); do
         --syncer "$1" \
         --msg_size "$4" \
         --byzantine "$3" \
-        --crash "$crash" > logs/$i.log &
+        --crash "$crash" \
+        --ordering "$ordering" $EXTRA > logs/$i.log &
 done
 
 # Example usage:
 # ./test.sh syncer_id 10 false bfile_path rbc 4
+# ./test.sh syncer_id 10 false bfile_path ctrbc 4 true fifo